@@ -0,0 +1,80 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use dlg::{Dialog, Widget};
+use failure::Fallible;
+use lib::CatalogBuilder;
+use reverse::bs2s;
+use simplelog::{Config, LevelFilter, TermLogger};
+use structopt::StructOpt;
+
+/// Show the widgets parsed out of DLG files
+#[derive(Debug, StructOpt)]
+struct Opt {
+    /// The DLG files to load
+    inputs: Vec<String>,
+}
+
+fn main() -> Fallible<()> {
+    let opt = Opt::from_args();
+    let (mut catalog, inputs) = CatalogBuilder::build_and_select(&opt.inputs)?;
+    TermLogger::init(LevelFilter::Warn, Config::default())?;
+
+    for &fid in &inputs {
+        let label = catalog.file_label(fid)?;
+        let game = label.split(':').last().unwrap();
+        let meta = catalog.stat_sync(fid)?;
+        println!(
+            "At: {}:{:13} @ {}",
+            game,
+            meta.name,
+            meta.path
+                .unwrap_or_else(|| "<none>".into())
+                .to_string_lossy()
+        );
+        catalog.set_default_label(&label);
+        let dlg = Dialog::from_bytes(&catalog.read_sync(fid)?)?;
+
+        for (i, widget) in dlg.widgets.iter().enumerate() {
+            match widget {
+                Widget::Preload(preload) => println!(
+                    "{:>3}: Preload kind: {:?}, name: {}",
+                    i,
+                    preload.kind(),
+                    preload.name().unwrap_or("<none>")
+                ),
+                Widget::Action(action) => println!(
+                    "{:>3}: Action {:04X},{:04X} flag: {:02X} label: {} handler: {}",
+                    i,
+                    action.unk0,
+                    action.unk1,
+                    action.flag,
+                    action.label,
+                    action.handler.as_deref().unwrap_or("<none>")
+                ),
+                Widget::Rocker(rocker) => println!("{:>3}: {:?}", i, rocker),
+            }
+        }
+        if !dlg.trailing.is_empty() {
+            println!(
+                "trailing {} bytes: {}",
+                dlg.trailing.len(),
+                bs2s(&dlg.trailing)
+            );
+        }
+        println!();
+    }
+
+    Ok(())
+}
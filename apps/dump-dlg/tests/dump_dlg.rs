@@ -0,0 +1,29 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use std::process::Command;
+
+#[test]
+fn it_dumps_the_graphics_preferences_dialog() {
+    let output = Command::new(env!("CARGO_BIN_EXE_dump-dlg"))
+        .arg("FA:GRAFPREF.DLG")
+        .output()
+        .expect("failed to run dump-dlg");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("GRAFPREF.DLG"));
+    assert!(stdout.contains("  0: Preload kind: Some(GrafPrefPreload)"));
+    assert!(stdout.lines().any(|line| line.contains(": Action ")));
+}
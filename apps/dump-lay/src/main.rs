@@ -45,20 +45,7 @@ fn main() -> Fallible<()> {
                 continue;
             }
 
-            let layer_data = layer.for_index(i)?;
-
-            let r0 = layer_data.slice(0x00, 0x10)?;
-            let r1 = layer_data.slice(0x10, 0x20)?;
-            let r2 = layer_data.slice(0x20, 0x30)?;
-            let r3 = layer_data.slice(0x30, 0x40)?;
-
-            // We need to put rows r0, r1, and r2 into into 0xC0, 0xE0, 0xF0 somehow.
-            let mut palette = system_palette.clone();
-            palette.overlay_at(&r1, 0xF0 - 1)?;
-            palette.overlay_at(&r0, 0xE0 - 1)?;
-            palette.overlay_at(&r3, 0xD0)?;
-            palette.overlay_at(&r2, 0xC0)?;
-            // palette.override_one(0xFF, [0, 0, 0]);
+            let palette = layer.overlay_palette(i, &system_palette)?;
 
             let output = format!("dump/lay-pal/{}-{}/{}", game, name, i);
             println!("Writing: {}.png", output);
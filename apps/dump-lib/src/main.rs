@@ -15,7 +15,7 @@
 
 // Unpack lib files.
 use catalog::Catalog;
-use failure::Fallible;
+use failure::{bail, Fallible};
 use humansize::{file_size_opts as options, FileSize};
 use lib::LibDrawer;
 use std::{
@@ -32,6 +32,10 @@ enum Opt {
     #[structopt(name = "ls")]
     /// List the contents of a lib
     List {
+        #[structopt(short = "-s", long = "--sort", default_value = "name")]
+        /// Order the listing by name, size, or offset
+        sort: String,
+
         #[structopt(parse(from_os_str))]
         /// The lib files to list
         inputs: Vec<PathBuf>,
@@ -54,7 +58,7 @@ fn main() -> Fallible<()> {
     let opt = Opt::from_args();
 
     match opt {
-        Opt::List { inputs } => handle_ls(inputs),
+        Opt::List { sort, inputs } => handle_ls(&sort, inputs),
         Opt::Unpack {
             inputs,
             output_path,
@@ -62,18 +66,26 @@ fn main() -> Fallible<()> {
     }
 }
 
-fn handle_ls(inputs: Vec<PathBuf>) -> Fallible<()> {
+fn handle_ls(sort: &str, inputs: Vec<PathBuf>) -> Fallible<()> {
     let multi_input = inputs.len() > 1;
     for (i, input) in inputs.iter().enumerate() {
-        let catalog = Catalog::with_drawers(vec![LibDrawer::from_path(0, input)?])?;
+        let mut manifest = LibDrawer::new(0, input)?.manifest()?;
+        match sort {
+            "name" => manifest.sort_by(|a, b| a.name.cmp(&b.name)),
+            "size" => manifest.sort_by(|a, b| b.unpacked_size.cmp(&a.unpacked_size)),
+            "offset" => {}
+            _ => bail!(
+                "unknown sort order {}; expected name, size, or offset",
+                sort
+            ),
+        }
         if multi_input {
             if i != 0 {
                 println!();
             }
             println!("{}:", input.to_string_lossy());
         }
-        for name in catalog.find_matching_names("*")?.iter() {
-            let info = catalog.stat_name_sync(name)?;
+        for info in &manifest {
             let mut psize = info.packed_size.file_size(options::BINARY).unwrap();
             if psize.ends_with(" B") {
                 psize += "  ";
@@ -92,8 +104,9 @@ fn handle_ls(inputs: Vec<PathBuf>) -> Fallible<()> {
             };
 
             println!(
-                "{:15} {:<8} {:>12} {:>12}  {}",
+                "{:15} {:08X} {:<8} {:>12} {:>12}  {}",
                 info.name,
+                info.offset,
                 info.compression.unwrap_or("none"),
                 psize,
                 asize,
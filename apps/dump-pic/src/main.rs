@@ -12,12 +12,17 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
-use failure::Fallible;
+use catalog::{Catalog, FileId};
+use failure::{ensure, Fallible};
 use image::GenericImageView;
 use lib::CatalogBuilder;
 use pal::Palette;
 use pic::Pic;
-use std::{fs, iter};
+use std::{
+    collections::HashMap,
+    fs, iter,
+    path::{Path, PathBuf},
+};
 use structopt::StructOpt;
 
 /// Extract PICs to PNG files and show PIC metadata
@@ -47,6 +52,10 @@ struct Opt {
     #[structopt(short = "o", long = "output")]
     write_image: Option<String>,
 
+    /// Export every input as a PNG under this directory, one subdirectory per game
+    #[structopt(short = "x", long = "export-dir")]
+    export_dir: Option<PathBuf>,
+
     /// One or more PIC files to process
     inputs: Vec<String>,
 }
@@ -59,6 +68,10 @@ fn main() -> Fallible<()> {
         return Ok(());
     }
 
+    if let Some(export_dir) = &opt.export_dir {
+        return export_all(&catalog, &inputs, export_dir);
+    }
+
     for &fid in &inputs {
         let label = catalog.file_label(fid)?;
         let game = label.split(':').last().unwrap();
@@ -116,3 +129,79 @@ fn main() -> Fallible<()> {
 
     Ok(())
 }
+
+fn export_all(catalog: &Catalog, inputs: &[FileId], export_dir: &Path) -> Fallible<()> {
+    let mut failures = Vec::new();
+    let mut palettes = HashMap::new();
+    for &fid in inputs {
+        let label = catalog.file_label(fid)?;
+        let game = label.split(':').last().unwrap().to_owned();
+        let meta = catalog.stat_sync(fid)?;
+
+        // PICs with their own palette overlay it on top of the game's.
+        if !palettes.contains_key(&label) {
+            let palette =
+                Palette::from_bytes(&catalog.read_labeled_name_sync(&label, "PALETTE.PAL")?)?;
+            palettes.insert(label.clone(), palette);
+        }
+        let image = match Pic::decode(&palettes[&label], &catalog.read_sync(fid)?) {
+            Ok(image) => image,
+            Err(e) => {
+                failures.push(format!("{}:{}: {}", game, meta.name, e));
+                continue;
+            }
+        };
+
+        let path = export_path(export_dir, &game, &meta.name);
+        fs::create_dir_all(path.parent().unwrap())?;
+        if let Err(e) = image.save(&path) {
+            failures.push(format!("{}:{}: {}", game, meta.name, e));
+            continue;
+        }
+        println!("{}:{} -> {}", game, meta.name, path.display());
+    }
+
+    println!(
+        "exported {} of {} PICs",
+        inputs.len() - failures.len(),
+        inputs.len()
+    );
+    for failure in &failures {
+        println!("failed: {}", failure);
+    }
+    ensure!(
+        failures.is_empty(),
+        "failed to export {} PICs",
+        failures.len()
+    );
+    Ok(())
+}
+
+fn export_path(export_dir: &Path, game: &str, name: &str) -> PathBuf {
+    let stem = name.split('.').next().unwrap_or(name);
+    export_dir.join(game).join(format!("{}.png", stem))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_exports_pics_at_their_own_size() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["FA:FONT4X6.PIC".to_owned()])?;
+        assert_eq!(inputs.len(), 1);
+        let fid = inputs[0];
+        let export_dir = std::env::temp_dir().join("openfa-test-dump-pic");
+        export_all(&catalog, &inputs, &export_dir)?;
+
+        let label = catalog.file_label(fid)?;
+        let game = label.split(':').last().unwrap();
+        let meta = catalog.stat_sync(fid)?;
+        let pic = Pic::from_bytes(&catalog.read_sync(fid)?)?;
+        let png = image::open(export_path(&export_dir, game, &meta.name))?;
+        assert_eq!(png.dimensions(), (pic.width, pic.height));
+
+        fs::remove_dir_all(&export_dir)?;
+        Ok(())
+    }
+}
@@ -14,8 +14,8 @@
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
 use failure::Fallible;
 use lib::CatalogBuilder;
-use reverse::b2h;
-use sh::{Instr, RawShape, SHAPE_LOAD_BASE};
+use reverse::{b2h, Escape};
+use sh::{format_diff, Instr, ParseCoverage, RawShape, SHAPE_LOAD_BASE};
 use simplelog::*;
 use std::{collections::HashMap, fs};
 use structopt::StructOpt;
@@ -31,6 +31,10 @@ struct Opt {
     #[structopt(short = "a", long = "all")]
     show_all: bool,
 
+    /// Show a table of vertex, facet, and texture counts
+    #[structopt(short = "s", long = "stats")]
+    show_stats: bool,
+
     /// Show the min and max coordinates
     #[structopt(short = "e", long = "extents")]
     show_extents: bool,
@@ -67,10 +71,30 @@ struct Opt {
     #[structopt(short, long)]
     dump_code: bool,
 
+    /// Disassemble all i386 code fragments, with trampolines resolved
+    #[structopt(long)]
+    disasm: bool,
+
     /// Run a custom action
     #[structopt(short, long)]
     custom: bool,
 
+    /// Show the PE relocations, with the imports they point at
+    #[structopt(long)]
+    relocs: bool,
+
+    /// Classify how completely each shape parses and print totals
+    #[structopt(long)]
+    coverage: bool,
+
+    /// Leave ansi colors out of the output, for piping to a file
+    #[structopt(long)]
+    plain: bool,
+
+    /// Compare the first two inputs section-by-section
+    #[structopt(long)]
+    diff: bool,
+
     /// Shape files to display
     #[structopt()]
     inputs: Vec<String>,
@@ -90,6 +114,51 @@ fn main() -> Fallible<()> {
         LevelFilter::Warn
     };
     TermLogger::init(level, Config::default())?;
+    let esc = Escape::new().with_plain(opt.plain);
+
+    if opt.diff {
+        if inputs.len() != 2 {
+            println!("Diff requires exactly two inputs");
+            return Ok(());
+        }
+        let left = RawShape::from_bytes(&catalog.read_sync(inputs[0])?)?;
+        let right = RawShape::from_bytes(&catalog.read_sync(inputs[1])?)?;
+        println!("{}", format_diff(&left.diff(&right), &left, &right));
+        return Ok(());
+    }
+
+    if opt.coverage {
+        let mut totals = HashMap::new();
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            let game = label.split(':').last().unwrap();
+            let meta = catalog.stat_sync(fid)?;
+            let coverage = ParseCoverage::classify(&catalog.read_sync(fid)?);
+            if opt.verbose || coverage != ParseCoverage::Full {
+                println!("{:8} {:13} {:?}", game, meta.name, coverage);
+            }
+            *totals.entry(coverage).or_insert(0) += 1;
+        }
+        for coverage in &[
+            ParseCoverage::Full,
+            ParseCoverage::Partial,
+            ParseCoverage::Failed,
+        ] {
+            println!(
+                "{:8}: {}",
+                format!("{:?}", coverage),
+                totals.get(coverage).unwrap_or(&0)
+            );
+        }
+        return Ok(());
+    }
+
+    if opt.show_stats {
+        println!(
+            "{:8} {:13} {:>6} {:>6} {:>4} {:>4} {:>6} {:>6}",
+            "game", "name", "verts", "facets", "texs", "code", "detail", "frames"
+        );
+    }
 
     for &fid in &inputs {
         let label = catalog.file_label(fid)?;
@@ -101,7 +170,30 @@ fn main() -> Fallible<()> {
 
         if opt.show_all {
             for (i, instr) in shape.instrs.iter().enumerate() {
-                println!("{:3}: {}", i, instr.show());
+                println!("{:3}: {}", i, esc.filter(&instr.show()));
+            }
+        } else if opt.show_stats {
+            let stats = shape.stats();
+            println!(
+                "{:8} {:13} {:>6} {:>6} {:>4} {:>4} {:>6} {:>6}",
+                game,
+                meta.name,
+                stats.vertex_count,
+                stats.facet_count,
+                stats.texture_count,
+                if stats.has_code { "yes" } else { "no" },
+                stats.detail_levels,
+                stats.frame_count
+            );
+        } else if opt.relocs {
+            println!("{}:{}", game, meta.name);
+            for reloc in shape.relocations()? {
+                println!(
+                    "  0x{:04X} -> 0x{:08X} {}",
+                    reloc.offset,
+                    reloc.target,
+                    reloc.thunk_name.as_ref().map(String::as_str).unwrap_or("")
+                );
             }
         } else if opt.show_extents {
             let mut min = [std::i16::MAX; 3];
@@ -136,7 +228,7 @@ fn main() -> Fallible<()> {
                             frags.push(shape.instrs[i + 1 + j].show())
                         }
                     }
-                    let out = frags.join("; ");
+                    let out = esc.filter(&frags.join("; "));
 
                     if opt.quiet {
                         println!("{}", out);
@@ -179,7 +271,7 @@ fn main() -> Fallible<()> {
                 .map(sh::Instr::show)
                 .ok_or("NO INSTRUCTIONS")
                 .unwrap();
-            println!("{:20}: {}", meta.name, fmt);
+            println!("{:20}: {}", meta.name, esc.filter(&fmt));
         } else if opt.show_unknown {
             for i in shape.instrs.iter() {
                 if let sh::Instr::UnknownUnknown(unk) = i {
@@ -227,6 +319,11 @@ fn main() -> Fallible<()> {
                     fs::write(&filename, &v)?;
                 }
             }
+        } else if opt.disasm {
+            if !opt.quiet {
+                println!("{}:{}", game, meta.name);
+            }
+            print!("{}", shape.disassemble());
         } else if opt.custom {
             let mut offset = 0;
             while offset < shape.instrs.len() {
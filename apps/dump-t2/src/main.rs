@@ -0,0 +1,80 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use failure::Fallible;
+use lib::CatalogBuilder;
+use std::fs;
+use structopt::StructOpt;
+use t2::{Terrain, FEET_PER_HEIGHT_UNIT};
+
+/// Dump T2 files
+#[derive(Debug, StructOpt)]
+struct Opt {
+    /// Write a 16 bit grayscale heightmap for each terrain to dump/t2
+    #[structopt(long)]
+    dump_heightmap: bool,
+
+    /// Terrain files to dump
+    inputs: Vec<String>,
+}
+
+fn main() -> Fallible<()> {
+    let opt = Opt::from_args();
+    let (catalog, inputs) = CatalogBuilder::build_and_select(&opt.inputs)?;
+    for &fid in &inputs {
+        let label = catalog.file_label(fid)?;
+        let game = label.split(':').last().unwrap();
+        let name = catalog.stat_sync(fid)?.name;
+        let terrain = Terrain::from_bytes(&catalog.read_sync(fid)?)?;
+        let (lo, hi) = terrain.height_range();
+
+        println!("{}:{}", game, name);
+        println!("  name:    {}", terrain.name());
+        println!("  samples: {}x{}", terrain.width(), terrain.height());
+        println!(
+            "  extent:  {}ft x {}ft",
+            terrain.extent_east_west_in_ft(),
+            terrain.extent_north_south_in_ft()
+        );
+        println!("  heights: {}..={}", lo, hi);
+
+        if opt.dump_heightmap {
+            // The gray range covers lo..=hi, so record the feet that it spans in the name.
+            fs::create_dir_all("dump/t2")?;
+            let output = format!(
+                "dump/t2/{}-{}.height-{}-{}ft.png",
+                game,
+                name,
+                (f32::from(lo) * FEET_PER_HEIGHT_UNIT) as u32,
+                (f32::from(hi) * FEET_PER_HEIGHT_UNIT) as u32
+            );
+            let img = terrain.to_heightmap_image();
+            // PNG stores 16 bit samples big-endian.
+            let bytes = img
+                .iter()
+                .flat_map(|v| v.to_be_bytes().to_vec())
+                .collect::<Vec<u8>>();
+            println!("Writing: {}", output);
+            image::save_buffer(
+                &output,
+                &bytes,
+                img.width(),
+                img.height(),
+                image::ColorType::Gray(16),
+            )?;
+        }
+    }
+
+    Ok(())
+}
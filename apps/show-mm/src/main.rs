@@ -15,6 +15,7 @@
 use absolute_unit::{degrees, meters};
 use atmosphere::AtmosphereBuffer;
 use camera::ArcBallCamera;
+use catalog::Catalog;
 use command::Bindings;
 use failure::{bail, Fallible};
 use fnt::Font;
@@ -25,19 +26,20 @@ use geodesy::{GeoSurface, Graticule};
 use global_data::GlobalParametersBuffer;
 use gpu::GPU;
 use input::InputSystem;
+use legion::world::EntityStore;
 use lib::{from_dos_string, CatalogBuilder};
 use log::trace;
-use mm::MissionMap;
+use mm::{MissionMap, PlacementKind};
 use nalgebra::convert;
 use orrery::Orrery;
 use physical_constants::FEET_TO_HM_32;
 use screen_text::ScreenTextRenderPass;
 use shape::ShapeRenderPass;
-use shape_instance::{DrawSelection, ShapeInstanceBuffer};
+use shape_instance::{Clock, DrawSelection, ShapeInstanceBuffer, ShapeState};
 use simplelog::{Config, LevelFilter, TermLogger};
 use skybox::SkyboxRenderPass;
 use stars::StarsBuffer;
-use std::time::Instant;
+use std::{cell::RefCell, sync::Arc, time::Instant};
 use structopt::StructOpt;
 use t2_buffer::T2Buffer;
 use t2_terrain::T2TerrainRenderPass;
@@ -72,6 +74,195 @@ make_frame_graph!(
     }
 );
 
+// Upload the shape for every placement in the mission and add it to the world, with behavior
+// matching its type. Returns a label for each placed object.
+fn place_mission_shapes(
+    mm: &MissionMap,
+    t2_buffer: &T2Buffer,
+    shape_instance_buffer: &RefCell<ShapeInstanceBuffer>,
+    galaxy: &mut Galaxy,
+    catalog: &Catalog,
+    gpu: &mut GPU,
+) -> Fallible<Vec<String>> {
+    let placements = mm.placements(t2_buffer.t2())?;
+    let ns_ft = t2_buffer.t2().extent_north_south_in_ft();
+    let mut placed = Vec::with_capacity(placements.len());
+    for placement in &placements {
+        let (shape_id, slot_id) = shape_instance_buffer
+            .borrow_mut()
+            .upload_and_allocate_slot(
+                &placement.shape_name,
+                DrawSelection::NormalModel,
+                galaxy.palette(),
+                catalog,
+                gpu,
+            )?;
+        let aabb = *shape_instance_buffer
+            .borrow()
+            .part(shape_id)
+            .widgets()
+            .read()
+            .unwrap()
+            .aabb();
+
+        let scale = if placement.shape_name.starts_with("BNK") {
+            2f32
+        } else {
+            4f32
+        };
+        let mut p = placement.position();
+        p.coords[2] = ns_ft - p.coords[2]; // flip z for vulkan
+        p *= FEET_TO_HM_32;
+        // Terrain is drawn with up as -y; lift the shape so that its base rests at the
+        // placement height rather than its origin.
+        p.coords[1] = -p.coords[1] - aabb[1][1] * scale * FEET_TO_HM_32;
+
+        let instance_buffer = shape_instance_buffer.borrow();
+        let part = instance_buffer.part(shape_id);
+        match placement.kind {
+            PlacementKind::Plane => {
+                let entity =
+                    galaxy.create_flyer(slot_id, shape_id, part, scale, p, placement.rotation())?;
+                let start = galaxy.start_time_owned();
+                if let Some(mut shape_state) =
+                    galaxy.world_mut().get_component_mut::<ShapeState>(entity)
+                {
+                    let draw_state = &mut shape_state.draw_state;
+                    if draw_state.gear_retracted() == placement.draw_state.gear_down {
+                        draw_state.toggle_gear(&start);
+                    }
+                }
+                entity
+            }
+            PlacementKind::Vehicle => galaxy.create_ground_mover(
+                slot_id,
+                shape_id,
+                part,
+                scale,
+                p,
+                placement.rotation(),
+            )?,
+            PlacementKind::Structure => {
+                galaxy.create_building(slot_id, shape_id, part, scale, p, placement.rotation())?
+            }
+        };
+
+        let name = if let Some(n) = placement.name.as_ref() {
+            n.to_owned() + " (" + &placement.shape_name + ")"
+        } else {
+            placement.shape_name.clone()
+        };
+        placed.push(name);
+    }
+    Ok(placed)
+}
+
+// Everything needed to draw a mission: the terrain, the placed shapes, and the sky.
+struct MissionViewer {
+    galaxy: Galaxy,
+    placed: Vec<String>,
+    atmosphere_buffer: Arc<RefCell<AtmosphereBuffer>>,
+    globals_buffer: Arc<RefCell<GlobalParametersBuffer>>,
+    shape_instance_buffer: Arc<RefCell<ShapeInstanceBuffer>>,
+    text_layout_buffer: Arc<RefCell<TextLayoutBuffer>>,
+    frame_graph: FrameGraph,
+}
+
+impl MissionViewer {
+    fn new(name: &str, catalog: &Catalog, gpu: &mut GPU) -> Fallible<Self> {
+        let mut galaxy = Galaxy::new(catalog)?;
+        let types = TypeManager::empty();
+        let mm = MissionMap::from_str(
+            &from_dos_string(catalog.read_name_sync(name)?),
+            &types,
+            catalog,
+        )?;
+
+        let t2_buffer = T2Buffer::new(&mm, galaxy.palette(), catalog, gpu)?;
+        let shape_instance_buffer = ShapeInstanceBuffer::new(gpu.device())?;
+        let placed = place_mission_shapes(
+            &mm,
+            &t2_buffer.borrow(),
+            &shape_instance_buffer,
+            &mut galaxy,
+            catalog,
+            gpu,
+        )?;
+        shape_instance_buffer.borrow_mut().ensure_uploaded(gpu)?;
+
+        let atmosphere_buffer = AtmosphereBuffer::new(gpu)?;
+        let fullscreen_buffer = FullscreenBuffer::new(gpu)?;
+        let globals_buffer = GlobalParametersBuffer::new(gpu.device())?;
+        let stars_buffer = StarsBuffer::new(gpu)?;
+        let text_layout_buffer = TextLayoutBuffer::new(gpu)?;
+
+        let frame_graph = FrameGraph::new(
+            gpu,
+            &atmosphere_buffer,
+            &fullscreen_buffer,
+            &globals_buffer,
+            &shape_instance_buffer,
+            &stars_buffer,
+            &t2_buffer,
+            &text_layout_buffer,
+        )?;
+
+        Ok(Self {
+            galaxy,
+            placed,
+            atmosphere_buffer,
+            globals_buffer,
+            shape_instance_buffer,
+            text_layout_buffer,
+            frame_graph,
+        })
+    }
+
+    fn placed(&self) -> &[String] {
+        &self.placed
+    }
+
+    fn text_layout_buffer(&self) -> &Arc<RefCell<TextLayoutBuffer>> {
+        &self.text_layout_buffer
+    }
+
+    fn render_frame(
+        &mut self,
+        arcball: &ArcBallCamera,
+        orrery: &Orrery,
+        gpu: &mut GPU,
+    ) -> Fallible<()> {
+        self.globals_buffer
+            .borrow()
+            //.make_upload_buffer_for_arcball_in_tile(
+            .make_upload_buffer(
+                //t2_buffer.borrow().t2(),
+                arcball.camera(),
+                gpu,
+                self.frame_graph.tracker_mut(),
+            )?;
+        self.atmosphere_buffer.borrow().make_upload_buffer(
+            convert(orrery.sun_direction()),
+            gpu,
+            self.frame_graph.tracker_mut(),
+        )?;
+        self.shape_instance_buffer.borrow_mut().make_upload_buffer(
+            &Clock::Real {
+                start: self.galaxy.start_time_owned(),
+                now: Instant::now(),
+            },
+            self.galaxy.world_mut(),
+            gpu,
+            self.frame_graph.tracker_mut(),
+        )?;
+        self.text_layout_buffer
+            .borrow_mut()
+            .make_upload_buffer(gpu, self.frame_graph.tracker_mut())?;
+        self.frame_graph.run(gpu)?;
+        Ok(())
+    }
+}
+
 fn main() -> Fallible<()> {
     let opt = Opt::from_args();
     let (mut catalog, inputs) = CatalogBuilder::build_and_select(&opt.inputs)?;
@@ -84,8 +275,6 @@ fn main() -> Fallible<()> {
     let label = catalog.file_label(fid)?;
     catalog.set_default_label(&label);
     let meta = catalog.stat_sync(fid)?;
-    let name = meta.name;
-    let mut galaxy = Galaxy::new(&catalog)?;
 
     let mm_bindings = Bindings::new("map")
         .bind("prev-object", "Shift+n")?
@@ -101,168 +290,11 @@ fn main() -> Fallible<()> {
     ])?;
     let mut gpu = GPU::new(&input, Default::default())?;
 
-    let types = TypeManager::empty();
-    let mm = MissionMap::from_str(
-        &from_dos_string(catalog.read_name_sync(&name)?),
-        &types,
-        &catalog,
-    )?;
-
+    let mut viewer = MissionViewer::new(&meta.name, &catalog, &mut gpu)?;
     let mut position_index = 0;
-    let mut positions = Vec::new();
-    let mut names = Vec::new();
-    let t2_buffer = T2Buffer::new(&mm, galaxy.palette(), &catalog, &mut gpu)?;
-
-    let shape_instance_buffer = ShapeInstanceBuffer::new(gpu.device())?;
-    {
-        for info in mm.objects() {
-            if info.xt().ot().shape.is_none() {
-                // FIXME: this still needs to add the entity.
-                // I believe these are only for hidden flak guns in TVIET.
-                continue;
-            }
-
-            let (shape_id, slot_id) = shape_instance_buffer
-                .borrow_mut()
-                .upload_and_allocate_slot(
-                    info.xt().ot().shape.as_ref().expect("a shape file"),
-                    DrawSelection::NormalModel,
-                    galaxy.palette(),
-                    &catalog,
-                    &mut gpu,
-                )?;
-            let aabb = *shape_instance_buffer
-                .borrow()
-                .part(shape_id)
-                .widgets()
-                .read()
-                .unwrap()
-                .aabb();
-
-            if let Ok(_pt) = info.xt().pt() {
-                //galaxy.create_flyer(pt, shape_id, slot_id)?
-                //unimplemented!()
-            } else if let Ok(_nt) = info.xt().nt() {
-                //galaxy.create_ground_mover(nt)
-                //unimplemented!()
-            } else if info.xt().jt().is_ok() {
-                bail!("did not expect a projectile in MM objects")
-            } else {
-                println!("Obj Inst {:?}: {:?}", info.xt().ot().shape, info.position());
-                let scale = if info
-                    .xt()
-                    .ot()
-                    .shape
-                    .as_ref()
-                    .expect("a shape file")
-                    .starts_with("BNK")
-                {
-                    2f32
-                } else {
-                    4f32
-                };
-                let mut p = info.position();
-                let ns_ft = t2_buffer.borrow().t2().extent_north_south_in_ft();
-                p.coords[2] = ns_ft - p.coords[2]; // flip z for vulkan
-                p *= FEET_TO_HM_32;
-                p.coords[1] = /*t2_buffer.borrow().ground_height_at_tile(&p)*/
-                    -aabb[1][1] * scale * FEET_TO_HM_32;
-                positions.push(p);
-                let sh_name = info
-                    .xt()
-                    .ot()
-                    .shape
-                    .as_ref()
-                    .expect("a shape file")
-                    .to_owned();
-                if let Some(n) = info.name() {
-                    names.push(n + " (" + &sh_name + ")");
-                } else {
-                    names.push(sh_name);
-                }
-                galaxy.create_building(
-                    slot_id,
-                    shape_id,
-                    shape_instance_buffer.borrow().part(shape_id),
-                    scale,
-                    p,
-                    info.angle(),
-                )?;
-            };
-        }
-
-        /*
-        let (shape_id, slot_id) = shape_instance_buffer
-            .borrow_mut()
-            .upload_and_allocate_slot(
-                "TREEA.SH",
-                DrawSelection::NormalModel,
-                galaxy.palette(),
-                galaxy.library(),
-                &mut gpu,
-            )?;
-        let height = shape_instance_buffer
-            .borrow()
-            .part(shape_id)
-            .widgets()
-            .read()
-            .unwrap()
-            .height();
-        use nalgebra::Point3;
-        use rand::distributions::{IndependentSample, Range};
-        let ns_between = Range::new(
-            0f32,
-            t2_buffer.borrow().t2().extent_north_south_in_ft()
-                / t2_buffer.borrow().t2().height() as f32,
-        );
-        let we_between = Range::new(
-            0f32,
-            t2_buffer.borrow().t2().extent_east_west_in_ft()
-                / t2_buffer.borrow().t2().width() as f32,
-        );
-        let mut rng = rand::thread_rng();
-        for i in 0..10000 {
-            let x = we_between.ind_sample(&mut rng);
-            let z = ns_between.ind_sample(&mut rng);
-            let mut p = Point3::new(x, 0f32, z);
-            p *= FEET_TO_HM_32;
-            p.coords[1] = /*t2_buffer.borrow().ground_height_at_tile(&p)*/
-                - height * 4.0 * FEET_TO_HM_32 / 2f32;
-            println!("p: {:?}", p);
-            galaxy.create_building(
-                slot_id,
-                shape_id,
-                shape_instance_buffer.borrow().part(shape_id),
-                p,
-                &UnitQuaternion::identity(),
-            )?;
-        }
-        */
-    }
-    shape_instance_buffer
-        .borrow_mut()
-        .ensure_uploaded(&mut gpu)?;
 
-    ///////////////////////////////////////////////////////////
-    let atmosphere_buffer = AtmosphereBuffer::new(&mut gpu)?;
-    let fullscreen_buffer = FullscreenBuffer::new(&gpu)?;
-    let globals_buffer = GlobalParametersBuffer::new(gpu.device())?;
-    let stars_buffer = StarsBuffer::new(&gpu)?;
-    let text_layout_buffer = TextLayoutBuffer::new(&mut gpu)?;
-
-    let mut frame_graph = FrameGraph::new(
-        &mut gpu,
-        &atmosphere_buffer,
-        &fullscreen_buffer,
-        &globals_buffer,
-        &shape_instance_buffer,
-        &stars_buffer,
-        &t2_buffer,
-        &text_layout_buffer,
-    )?;
-    ///////////////////////////////////////////////////////////
-
-    let fps_handle = text_layout_buffer
+    let fps_handle = viewer
+        .text_layout_buffer()
         .borrow_mut()
         .add_screen_text(Font::HUD11.name(), "", &gpu)?
         .with_color(&[1f32, 0f32, 0f32, 1f32])
@@ -303,7 +335,7 @@ fn main() -> Fallible<()> {
                     //camera.set_target_point(&nalgebra::convert(positions[position_index]));
                 }
                 "next-object" => {
-                    if position_index < positions.len() - 1 {
+                    if position_index < viewer.placed().len() - 1 {
                         position_index += 1;
                     }
                     //camera.set_target_point(&nalgebra::convert(positions[position_index]));
@@ -313,41 +345,43 @@ fn main() -> Fallible<()> {
             }
         }
 
-        globals_buffer
-            .borrow()
-            //.make_upload_buffer_for_arcball_in_tile(
-            .make_upload_buffer(
-                //t2_buffer.borrow().t2(),
-                arcball.camera(),
-                &gpu,
-                frame_graph.tracker_mut(),
-            )?;
-        atmosphere_buffer.borrow().make_upload_buffer(
-            convert(orrery.sun_direction()),
-            &gpu,
-            frame_graph.tracker_mut(),
-        )?;
-        shape_instance_buffer.borrow_mut().make_upload_buffer(
-            &galaxy.start_time_owned(),
-            galaxy.world_mut(),
-            &gpu,
-            frame_graph.tracker_mut(),
-        )?;
-        text_layout_buffer
-            .borrow_mut()
-            .make_upload_buffer(&gpu, frame_graph.tracker_mut())?;
-        frame_graph.run(&mut gpu)?;
+        viewer.render_frame(&arcball, &orrery, &mut gpu)?;
 
         let ft = loop_start.elapsed();
         let ts = format!(
             "@{} {} - {}.{} ms",
             position_index,
-            names[position_index],
+            viewer.placed()[position_index],
             ft.as_secs() * 1000 + u64::from(ft.subsec_millis()),
             ft.subsec_micros()
         );
         fps_handle
-            .grab(&mut text_layout_buffer.borrow_mut())
+            .grab(&mut viewer.text_layout_buffer().borrow_mut())
             .set_span(&ts);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_can_render_a_mission() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:BAL.MM".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let meta = catalog.stat_sync(fid)?;
+
+            let input = InputSystem::new(vec![])?;
+            let mut gpu = GPU::new(&input, Default::default())?;
+            let mut viewer = MissionViewer::new(&meta.name, &catalog, &mut gpu)?;
+            assert!(!viewer.placed().is_empty());
+
+            let arcball = ArcBallCamera::new(gpu.aspect_ratio(), meters!(0.1), meters!(3.4e+38));
+            let orrery = Orrery::now();
+            viewer.render_frame(&arcball, &orrery, &mut gpu)?;
+        }
+        Ok(())
+    }
+}
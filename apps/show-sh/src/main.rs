@@ -33,7 +33,7 @@ use nalgebra::{convert, Point3, UnitQuaternion};
 use orrery::Orrery;
 use screen_text::ScreenTextRenderPass;
 use shape::ShapeRenderPass;
-use shape_instance::{DrawSelection, DrawState, ShapeInstanceBuffer, ShapeState};
+use shape_instance::{Clock, DrawSelection, DrawState, ShapeInstanceBuffer, ShapeState};
 use simplelog::{Config, LevelFilter, TermLogger};
 use skybox::SkyboxRenderPass;
 use stars::StarsBuffer;
@@ -232,8 +232,7 @@ fn main() -> Fallible<()> {
     let globals_buffer = GlobalParametersBuffer::new(gpu.device())?;
     let stars_buffer = StarsBuffer::new(&gpu)?;
     let text_layout_buffer = TextLayoutBuffer::new(&mut gpu)?;
-    let fnt = Fnt::from_bytes(&catalog.read_name_sync("HUD11.FNT")?)?;
-    let font = FntFont::from_fnt(&fnt, &mut gpu)?;
+    let font = FntFont::from_fnt(&Fnt::from_catalog(&catalog, Font::HUD11)?, &mut gpu)?;
     text_layout_buffer
         .borrow_mut()
         .add_font(Font::HUD11.name().into(), font, &gpu);
@@ -333,7 +332,10 @@ fn main() -> Fallible<()> {
             frame_graph.tracker_mut(),
         )?;
         shape_instance_buffer.borrow_mut().make_upload_buffer(
-            &galaxy.start_time_owned(),
+            &Clock::Real {
+                start: galaxy.start_time_owned(),
+                now: Instant::now(),
+            },
             galaxy.world_mut(),
             &gpu,
             frame_graph.tracker_mut(),
@@ -16,7 +16,8 @@
 #![allow(clippy::transmute_ptr_to_ptr)]
 
 use ansi::ansi;
-use failure::{bail, ensure, Fallible};
+use failure::{bail, ensure, err_msg, Fallible};
+use log::warn;
 use packed_struct::packed_struct;
 use peff::PE;
 use reverse::bs2s;
@@ -74,7 +75,7 @@ pub struct Preload {
     unk_header_1: u16,
     unk_header_2: u16,
     unk_header_3: u16,
-    unk_header_4: u16, // usually 0, but 0x100 in one case
+    unk_header_4: u16, // usually 0, but 0x100 in one case; others are warned about
     name: Option<String>,
     unk_footer_0: u16,
     unk_footer_1: u16,
@@ -82,18 +83,29 @@ pub struct Preload {
 }
 
 impl Preload {
+    pub fn kind(&self) -> Option<&PreloadKind> {
+        self.kind.as_ref()
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
     fn from_bytes(
         bytes: &[u8],
         offset: &mut usize,
         pe: &PE,
         trampolines: &HashMap<u32, String>,
     ) -> Fallible<Preload> {
-        let header_ptr: *const PreloadHeader = bytes.as_ptr() as *const _;
-        let header: &PreloadHeader = unsafe { &*header_ptr };
         ensure!(
-            header.unk4() == 0 || header.unk4() == 0x0100,
-            "expected 0 or 100 word in header"
+            bytes.len() >= mem::size_of::<PreloadHeader>(),
+            "preload header past end of code"
         );
+        let header_ptr: *const PreloadHeader = bytes.as_ptr() as *const _;
+        let header: &PreloadHeader = unsafe { &*header_ptr };
+        if header.unk4() != 0 && header.unk4() != 0x0100 {
+            warn!("unexpected preload header word: {:04X}", header.unk4());
+        }
 
         let trampoline_target = header.function().saturating_sub(pe.code_vaddr);
         let kind = if trampolines.contains_key(&trampoline_target) {
@@ -102,25 +114,32 @@ impl Preload {
             None
         };
 
-        let header_end_offset = *offset + mem::size_of::<PreloadHeader>();
-        let (name, footer_offset) = match header.flag_or_ascii() {
-            0 => (None, header_end_offset),
-            0xFF => (None, header_end_offset + 1),
+        // All offsets below are relative to the start of bytes, which is the
+        // start of the preload.
+        let header_end = mem::size_of::<PreloadHeader>();
+        let (name, footer_start) = match header.flag_or_ascii() {
+            0 => (None, header_end),
+            0xFF => (None, header_end + 1),
             _ => {
-                let mut off = header_end_offset;
-                let mut name = String::new();
-                while pe.code[off] != 0 {
-                    name.push(pe.code[off] as char);
-                    off += 1;
-                }
-                (Some(name), off + 1)
+                let name_len = bytes[header_end..]
+                    .iter()
+                    .position(|&b| b == 0)
+                    .ok_or_else(|| err_msg("unterminated preload name"))?;
+                let name = bytes[header_end..header_end + name_len]
+                    .iter()
+                    .map(|&b| b as char)
+                    .collect::<String>();
+                (Some(name), header_end + name_len + 1)
             }
         };
-        let footer_ptr: *const PreloadFooter = bytes.as_ptr() as *const _;
+        ensure!(
+            bytes.len() >= footer_start + mem::size_of::<PreloadFooter>(),
+            "preload footer past end of code"
+        );
+        let footer_ptr: *const PreloadFooter = bytes[footer_start..].as_ptr() as *const _;
         let footer: &PreloadFooter = unsafe { &*footer_ptr };
 
-        let end_offset = footer_offset + mem::size_of::<PreloadFooter>();
-        *offset += end_offset - *offset;
+        *offset += footer_start + mem::size_of::<PreloadFooter>();
         Ok(Self {
             kind,
             unk_header_0: header.unk0(),
@@ -157,6 +176,8 @@ pub struct DrawAction {
     pub unk2: u16,
     pub label: String,
     pub unk3: u16,
+    // The imported function that handles activation, if we can resolve one.
+    pub handler: Option<String>,
 }
 
 impl DrawAction {
@@ -181,8 +202,9 @@ impl DrawAction {
             .ptr_to_label()
             .saturating_sub(pe.code_vaddr)
             .saturating_sub(pe.image_base);
-        let label = if trampolines.contains_key(&label_ptr) {
-            trampolines[&label_ptr].clone()
+        let label_handler = trampolines.get(&label_ptr).cloned();
+        let label = if let Some(name) = &label_handler {
+            name.to_owned()
         } else {
             let mut label = String::new();
             let mut off = label_ptr as usize;
@@ -192,6 +214,11 @@ impl DrawAction {
             }
             label
         };
+        let data_ptr = header
+            .maybe_data()
+            .saturating_sub(pe.code_vaddr)
+            .saturating_sub(pe.image_base);
+        let handler = trampolines.get(&data_ptr).cloned().or(label_handler);
         *offset += mem::size_of::<DrawActionHeader>();
 
         Ok(DrawAction {
@@ -201,6 +228,7 @@ impl DrawAction {
             unk2: header.unk2(),
             label,
             unk3: header.unk3(),
+            handler,
         })
     }
 }
@@ -273,6 +301,8 @@ pub enum Widget {
 
 pub struct Dialog {
     pub widgets: Vec<Widget>,
+    // Code between the last parsed widget and the trampolines.
+    pub trailing: Vec<u8>,
 }
 
 impl Dialog {
@@ -281,6 +311,7 @@ impl Dialog {
         if pe.code.is_empty() {
             return Ok(Self {
                 widgets: Vec::new(),
+                trailing: Vec::new(),
             });
         }
 
@@ -288,7 +319,7 @@ impl Dialog {
         let trampolines = Self::find_trampolines(&pe)?;
         let targets = Self::find_targets(&pe, &trampolines)?;
 
-        let preload = Preload::from_bytes(&pe.code, &mut offset, &pe, &trampolines)?;
+        let preload = Preload::from_bytes(&pe.code[offset..], &mut offset, &pe, &trampolines)?;
         let mut widgets = Vec::new();
         widgets.push(Widget::Preload(preload));
 
@@ -330,7 +361,18 @@ impl Dialog {
                 break;
             }
         }
-        Ok(Self { widgets })
+
+        let code_end = trampolines
+            .keys()
+            .min()
+            .map(|&t| t as usize)
+            .unwrap_or_else(|| pe.code.len());
+        let trailing = if offset < code_end {
+            pe.code[offset..code_end].to_vec()
+        } else {
+            Vec::new()
+        };
+        Ok(Self { widgets, trailing })
     }
 
     fn find_trampolines(pe: &PE) -> Fallible<HashMap<u32, String>> {
@@ -505,4 +547,155 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn it_does_not_reject_preload_unk4() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:*.DLG".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let pe = PE::from_bytes(&catalog.read_sync(fid)?)?;
+            if pe.code.is_empty() {
+                continue;
+            }
+            let trampolines = Dialog::find_trampolines(&pe)?;
+            let mut offset = 0;
+            let preload = Preload::from_bytes(&pe.code, &mut offset, &pe, &trampolines)?;
+            let header_ptr: *const PreloadHeader = pe.code.as_ptr() as *const _;
+            let header: &PreloadHeader = unsafe { &*header_ptr };
+            assert_eq!(preload.unk_header_4, header.unk4());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_reads_preload_footer_after_name() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:*.DLG".to_owned()])?;
+        let mut named_count = 0;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let pe = PE::from_bytes(&catalog.read_sync(fid)?)?;
+            if pe.code.is_empty() {
+                continue;
+            }
+            let trampolines = Dialog::find_trampolines(&pe)?;
+            let mut offset = 0;
+            let preload = Preload::from_bytes(&pe.code, &mut offset, &pe, &trampolines)?;
+            if preload.name.is_none() {
+                continue;
+            }
+            named_count += 1;
+            let footer = &pe.code[offset - mem::size_of::<PreloadFooter>()..offset];
+            let word = |i: usize| u16::from_le_bytes([footer[i * 2], footer[i * 2 + 1]]);
+            assert_eq!(preload.unk_footer_0, word(0));
+            assert_eq!(preload.unk_footer_1, word(1));
+            assert_eq!(preload.unk_footer_2, word(2));
+        }
+        assert!(named_count > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_resolve_action_handlers() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:*.DLG".to_owned()])?;
+        let mut handler_count = 0;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let dlg = Dialog::from_bytes(&catalog.read_sync(fid)?)?;
+            for widget in &dlg.widgets {
+                if let Widget::Action(action) = widget {
+                    if let Some(handler) = &action.handler {
+                        assert!(handler.starts_with('_'), "not an import: {}", handler);
+                        handler_count += 1;
+                    }
+                }
+            }
+        }
+        assert!(handler_count > 0);
+        Ok(())
+    }
+
+    // A DrawAction with its label at 0x30 and its data pointer at the given address.
+    fn make_action_code(maybe_data: u32) -> Vec<u8> {
+        let mut code = vec![0u8; mem::size_of::<DrawActionHeader>()];
+        code[20..24].copy_from_slice(&0x30u32.to_le_bytes());
+        code[34..38].copy_from_slice(&maybe_data.to_le_bytes());
+        code.resize(0x30, 0);
+        code.extend_from_slice(b"Multiplayer\0");
+        code
+    }
+
+    fn make_pe(code: Vec<u8>) -> PE {
+        PE {
+            thunks: Vec::new(),
+            relocs: Vec::new(),
+            code,
+            section_info: HashMap::new(),
+            image_base: 0,
+            code_vaddr: 0,
+            code_addr: 0,
+        }
+    }
+
+    #[test]
+    fn it_reads_a_preload_relative_to_its_slice() -> Fallible<()> {
+        // Put the preload somewhere other than the start of the code.
+        let mut code = vec![0xAAu8; 4];
+        code.extend_from_slice(&[0, 0, 0, 0]); // function
+        code.extend_from_slice(&[1, 0, 2, 0, 3, 0, 4, 0, 0, 0]); // unk0-4
+        code.push(1); // flag_or_ascii
+        code.extend_from_slice(b"GRAFPREF\0");
+        code.extend_from_slice(&[5, 0, 6, 0, 7, 0]); // footer
+        code.extend_from_slice(&[0xAA; 4]);
+        let pe = make_pe(code);
+
+        let mut offset = 4;
+        let preload = Preload::from_bytes(&pe.code[offset..], &mut offset, &pe, &HashMap::new())?;
+        assert_eq!(offset, pe.code.len() - 4);
+        assert_eq!(preload.kind(), None);
+        assert_eq!(preload.name(), Some("GRAFPREF"));
+        assert_eq!(preload.unk_header_3, 4);
+        assert_eq!(
+            [
+                preload.unk_footer_0,
+                preload.unk_footer_1,
+                preload.unk_footer_2
+            ],
+            [5, 6, 7]
+        );
+
+        // A name that runs off the end of the code is an error, not a panic.
+        let pe = make_pe(pe.code[..24].to_vec());
+        assert!(Preload::from_bytes(&pe.code[4..], &mut 4, &pe, &HashMap::new()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_resolves_handlers_for_hand_built_actions() -> Fallible<()> {
+        let mut trampolines = HashMap::new();
+        trampolines.insert(0x40, "_MultiPreload".to_owned());
+
+        // The data pointer lands on a trampoline.
+        let pe = make_pe(make_action_code(0x40));
+        let mut offset = 0;
+        let action = DrawAction::from_bytes(&pe.code, &mut offset, &pe, &trampolines)?;
+        assert_eq!(offset, mem::size_of::<DrawActionHeader>());
+        assert_eq!(action.label, "Multiplayer");
+        assert_eq!(action.handler.as_deref(), Some("_MultiPreload"));
+
+        // Nothing to resolve.
+        let pe = make_pe(make_action_code(0));
+        let action = DrawAction::from_bytes(&pe.code, &mut 0, &pe, &trampolines)?;
+        assert_eq!(action.label, "Multiplayer");
+        assert_eq!(action.handler, None);
+
+        // The label itself is a trampoline, which stands in for the handler.
+        trampolines.insert(0x30, "_ErrorExit".to_owned());
+        let action = DrawAction::from_bytes(&pe.code, &mut 0, &pe, &trampolines)?;
+        assert_eq!(action.label, "_ErrorExit");
+        assert_eq!(action.handler.as_deref(), Some("_ErrorExit"));
+        Ok(())
+    }
 }
@@ -14,6 +14,7 @@
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
 #![allow(clippy::transmute_ptr_to_ptr)]
 
+use catalog::Catalog;
 use codepage_437::{FromCp437, CP437_CONTROL};
 use failure::{bail, ensure, Fallible};
 use i386::{ByteCode, Interpreter, Reg};
@@ -77,6 +78,19 @@ impl Font {
             Self::HUD11 => "hud11",
         }
     }
+
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Self::HUD11 => "HUD11.FNT",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "hud11" => Some(Self::HUD11),
+            _ => None,
+        }
+    }
 }
 
 pub struct GlyphInfo {
@@ -94,6 +108,17 @@ pub struct Fnt {
 const FNT_LOAD_BASE: u32 = 0x0000_0000;
 
 impl Fnt {
+    /// Load the FNT backing `font` out of the game archives; fails if the
+    /// current game does not ship the font so that the caller can fall back.
+    pub fn from_catalog(catalog: &Catalog, font: Font) -> Fallible<Self> {
+        ensure!(
+            catalog.exists(font.file_name()),
+            "font {} not found in catalog",
+            font.file_name()
+        );
+        Self::from_bytes(&catalog.read_name_sync(font.file_name())?)
+    }
+
     pub fn from_bytes(bytes: &[u8]) -> Fallible<Self> {
         let mut pe = PE::from_bytes(bytes)?;
         pe.relocate(FNT_LOAD_BASE)?;
@@ -250,4 +275,19 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn it_can_load_fonts_from_the_catalog() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:HUD11.FNT".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let fnt = Fnt::from_catalog(&catalog, Font::HUD11)?;
+            assert!(fnt.height > 0);
+            assert!(fnt.glyphs.contains_key(&b'A'));
+        }
+        assert_eq!(Font::from_name(Font::HUD11.name()), Some(Font::HUD11));
+        assert_eq!(Font::from_name("comic-sans"), None);
+        Ok(())
+    }
 }
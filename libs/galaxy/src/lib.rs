@@ -28,6 +28,14 @@ use shape_instance::{
 };
 use std::{sync::Arc, time::Instant};
 
+// Marks entities that drive along the ground. Wheeled dynamics will hang off of this.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct GroundMover;
+
+// Marks entities that fly. Flight dynamics will hang off of this.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Flyer;
+
 pub struct Galaxy {
     start_time: Instant,
 
@@ -93,6 +101,48 @@ impl Galaxy {
         scale: f32,
         position: Point3<f32>,
         rotation: &UnitQuaternion<f32>,
+    ) -> Fallible<Entity> {
+        self.create_shape_entity(slot_id, shape_id, part, scale, position, rotation)
+    }
+
+    pub fn create_ground_mover(
+        &mut self,
+        slot_id: SlotId,
+        shape_id: ShapeId,
+        part: &ChunkPart,
+        scale: f32,
+        position: Point3<f32>,
+        rotation: &UnitQuaternion<f32>,
+    ) -> Fallible<Entity> {
+        let entity =
+            self.create_shape_entity(slot_id, shape_id, part, scale, position, rotation)?;
+        self.legion_world.add_component(entity, GroundMover)?;
+        Ok(entity)
+    }
+
+    pub fn create_flyer(
+        &mut self,
+        slot_id: SlotId,
+        shape_id: ShapeId,
+        part: &ChunkPart,
+        scale: f32,
+        position: Point3<f32>,
+        rotation: &UnitQuaternion<f32>,
+    ) -> Fallible<Entity> {
+        let entity =
+            self.create_shape_entity(slot_id, shape_id, part, scale, position, rotation)?;
+        self.legion_world.add_component(entity, Flyer)?;
+        Ok(entity)
+    }
+
+    fn create_shape_entity(
+        &mut self,
+        slot_id: SlotId,
+        shape_id: ShapeId,
+        part: &ChunkPart,
+        scale: f32,
+        position: Point3<f32>,
+        rotation: &UnitQuaternion<f32>,
     ) -> Fallible<Entity> {
         let widget_ref = part.widgets();
         let widgets = widget_ref.read().unwrap();
@@ -117,46 +167,6 @@ impl Galaxy {
         Ok(entity)
     }
 
-    /*
-    pub fn create_ground_mover(
-        &mut self,
-        slot_id: SlotId,
-        shape_id: ShapeId,
-        position: Point3<f32>,
-    ) -> Fallible<Entity> {
-        Ok(self
-            .ecs
-            .create_entity()
-            .with(Transform::new(position, UnitQuaternion::identity()))
-            .with(WheeledDynamics::new())
-            .with(ShapeSlot::new(slot_id, shape_id))
-            .build())
-    }
-
-    pub fn create_flyer(
-        &mut self,
-        shape_id: ShapeId,
-        position: Point3<f64>,
-        part: &ChunkPart,
-    ) -> Fallible<Entity> {
-        //let slot_id = self.shape_renderer.chunk_manager().reserve_slot()?;
-
-        let widget_ref = part.widgets();
-        let widgets = widget_ref.read().unwrap();
-        Ok(self
-            .ecs
-            .create_entity()
-            .with(Transform::new(position))
-            .with(WheeledDynamics::new())
-            .with(FlightDynamics::new())
-            .with(ShapeMesh::new(shape_id))
-            .with(ShapeMeshTransformBuffer::new())
-            .with(ShapeMeshFlagBuffer::new(widgets.errata()))
-            .with(ShapeMeshXformBuffer::new(shape_id, part.widgets()))
-            .build())
-    }
-    */
-
     pub fn destroy_entity(&mut self, entity: Entity) -> bool {
         self.legion_world.delete(entity)
     }
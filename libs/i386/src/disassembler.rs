@@ -12,14 +12,13 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
-#![allow(clippy::transmute_ptr_to_ptr)]
 
 use crate::lut::{AddressingMethod, OpCodeDef, OperandDef, OperandType};
 use ansi::ansi;
 use failure::{bail, ensure, Error, Fail, Fallible};
 use log::trace;
 use reverse::bs2s;
-use std::{cell::RefCell, fmt, mem, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
 
 pub use crate::lut::{Memonic, HAS_INLINE_REG, OPCODES, PREFIX_CODES, USE_REG_OPCODES};
 
@@ -60,6 +59,51 @@ impl DisassemblyError {
     }
 }
 
+// Tallies unknown opcodes across many disassembly attempts, so that we can see
+// which opcodes are most worth implementing next.
+#[derive(Clone, Debug, Default)]
+pub struct MissingOpcodes {
+    counts: HashMap<(u16, u8), usize>,
+}
+
+impl MissingOpcodes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Returns false if the error was not an unknown opcode.
+    pub fn record(&mut self, e: &Error) -> bool {
+        if let Some(&DisassemblyError::UnknownOpcode { op, .. }) =
+            e.downcast_ref::<DisassemblyError>()
+        {
+            *self.counts.entry(op).or_insert(0) += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Most frequent first; ties are ordered by opcode.
+    pub fn report(&self) -> Vec<((u16, u8), usize)> {
+        let mut out = self
+            .counts
+            .iter()
+            .map(|(&op, &count)| (op, count))
+            .collect::<Vec<_>>();
+        out.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        out
+    }
+}
+
+impl fmt::Display for MissingOpcodes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for ((op, ext), count) in self.report() {
+            writeln!(f, "{:6} {:4X} /{}", count, op, ext)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Reg {
     AL,
@@ -742,8 +786,7 @@ impl Operand {
             code.len() > *ip + 1,
             DisassemblyError::TooShort { phase: "op read 2" }
         );
-        let r: &[u16] = unsafe { mem::transmute(&code[*ip..]) };
-        let w = r[0];
+        let w = u16::from_le_bytes([code[*ip], code[*ip + 1]]);
         *ip += 2;
         Ok(w)
     }
@@ -753,8 +796,7 @@ impl Operand {
             code.len() > *ip + 3,
             DisassemblyError::TooShort { phase: "op read 4" }
         );
-        let r: &[u32] = unsafe { mem::transmute(&code[*ip..]) };
-        let dw = r[0];
+        let dw = u32::from_le_bytes([code[*ip], code[*ip + 1], code[*ip + 2], code[*ip + 3]]);
         *ip += 4;
         Ok(dw)
     }
@@ -877,6 +919,12 @@ impl Instr {
         let mut op = u16::from(code[*ip]);
         *ip += 1;
         if op == 0x0Fu16 {
+            ensure!(
+                code.len() > *ip,
+                DisassemblyError::TooShort {
+                    phase: "read_op escape"
+                }
+            );
             op <<= 8;
             op |= u16::from(code[*ip]);
             *ip += 1;
@@ -943,6 +991,7 @@ impl Instr {
             Memonic::Jump => true,
             Memonic::Call => true,
             Memonic::Jcc(_) => true,
+            Memonic::Loop => true,
             _ => false,
         };
         let mut s = format!(
@@ -1086,6 +1135,7 @@ impl fmt::Display for ByteCode {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lut::{ConditionCode, ConditionCode1, FlagKind};
     use std::fs;
 
     #[test]
@@ -1112,4 +1162,85 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    #[ignore]
+    fn missing_opcode_report() -> Fallible<()> {
+        let mut missing = MissingOpcodes::new();
+        for game in &["ATF", "ATFGOLD", "ATFNATO", "FA", "MF", "USNF", "USNF97"] {
+            let dirname = format!("../../dump/i386/{}", game);
+            for i in fs::read_dir(&dirname)? {
+                let data = fs::read(i?.path())?;
+                if let Err(e) = ByteCode::disassemble_until(0, &data, |_| false) {
+                    if !missing.record(&e) {
+                        println!("Error: {}", e);
+                    }
+                }
+            }
+        }
+        println!("{}", missing);
+        Ok(())
+    }
+
+    #[test]
+    fn it_reads_unaligned_immediates() -> Fallible<()> {
+        let code = [0x90, 0x78, 0x56, 0x34, 0x12, 0xCD, 0xAB];
+        let mut ip = 1;
+        assert_eq!(Operand::read4(&code, &mut ip)?, 0x1234_5678);
+        assert_eq!(ip, 5);
+        assert_eq!(Operand::read2(&code, &mut ip)?, 0xABCD);
+        assert!(Operand::read2(&code, &mut ip).is_err());
+        let mut ip = 5;
+        assert!(Operand::read4(&code, &mut ip).is_err());
+
+        // mov eax, 0x12345678 at an odd offset.
+        let code = [0x90, 0xB8, 0x78, 0x56, 0x34, 0x12];
+        let mut ip = 1;
+        let instr = Instr::decode_one(&code, &mut ip)?;
+        match instr.op(1) {
+            Operand::Imm32(v) => assert_eq!(*v, 0x1234_5678),
+            _ => panic!("expected an immediate"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_decodes_two_byte_opcodes() -> Fallible<()> {
+        // jne rel32
+        let code = [0x0F, 0x85, 0x10, 0x00, 0x00, 0x00];
+        let mut ip = 0;
+        let instr = Instr::decode_one(&code, &mut ip)?;
+        assert_eq!(instr.size(), 6);
+        let cc = ConditionCode::Unary(ConditionCode1::Check(FlagKind::ZF, false));
+        assert_eq!(instr.memonic, Memonic::Jcc(cc));
+        match instr.op(0) {
+            Operand::Imm32(rel) => assert_eq!(instr.size() + *rel as usize, 0x16),
+            _ => panic!("expected a relative target"),
+        }
+
+        // movsx eax, BYTE PTR [ecx]; movsx eax, cx
+        let code = [0x0F, 0xBE, 0x01, 0x0F, 0xBF, 0xC1];
+        let bc = ByteCode::disassemble_until(0, &code, |_| false)?;
+        assert_eq!(bc.instrs.len(), 2);
+        assert_eq!(bc.instrs[0].memonic, Memonic::MoveSX);
+        assert_eq!(bc.instrs[0].op(1).size(), 1);
+        assert_eq!(bc.instrs[1].op(1).size(), 2);
+
+        // A lone escape byte is too short, not an unknown opcode.
+        let mut ip = 0;
+        assert!(Instr::decode_one(&[0x0F], &mut ip).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_tallies_missing_opcodes() {
+        let fixtures: [&[u8]; 4] = [&[0xF4], &[0x40, 0xF4], &[0x0F, 0x05], &[0x40, 0x40]];
+        let mut missing = MissingOpcodes::new();
+        for code in fixtures.iter() {
+            if let Err(e) = ByteCode::disassemble_until(0, code, |_| false) {
+                assert!(missing.record(&e));
+            }
+        }
+        assert_eq!(missing.report(), vec![((0xF4, 0), 2), ((0x0F05, 0), 1)]);
+    }
 }
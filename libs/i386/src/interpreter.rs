@@ -12,20 +12,99 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
-#![allow(clippy::new_without_default, clippy::transmute_ptr_to_ptr)]
+#![allow(clippy::new_without_default)]
 
 use crate::{
-    disassembler::{ByteCode, MemRef, Memonic, Operand, Reg},
+    disassembler::{ByteCode, Instr, MemRef, Memonic, Operand, Reg},
     lut::{ConditionCode, ConditionCode1, ConditionCode2, FlagKind},
 };
 use failure::{bail, ensure, Fallible};
 use log::trace;
-use std::{collections::HashMap, mem};
+use std::{
+    collections::HashMap,
+    fmt,
+    ops::{Index, IndexMut},
+    sync::{Arc, Mutex},
+};
 
 #[derive(Debug)]
 pub enum ExitInfo {
     OutOfInstructions,
     Trampoline(String, Vec<u32>),
+    Halted,
+}
+
+// Returned by a trace hook to decide whether the interpreter keeps going.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TraceAction {
+    Continue,
+    Halt,
+}
+
+type TraceFn = dyn FnMut(&Instr, &Registers) -> TraceAction + Send;
+
+// Shared so that the interpreter can stay Clone; clones will call the same hook.
+#[derive(Clone)]
+struct TraceHook(Arc<Mutex<Box<TraceFn>>>);
+
+impl fmt::Debug for TraceHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TraceHook")
+    }
+}
+
+type ReturnValueFn = dyn FnMut() -> u32 + Send;
+
+// Supplies EAX for a trampoline that we answer in place instead of exiting.
+#[derive(Clone)]
+struct ReturnValue(Arc<Mutex<Box<ReturnValueFn>>>);
+
+impl fmt::Debug for ReturnValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ReturnValue")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Registers {
+    values: Vec<u32>,
+}
+
+impl Registers {
+    fn new() -> Self {
+        let mut values = Vec::new();
+        values.resize(Reg::num_registers(), 0);
+        values[Reg::ESP.to_offset()] = 0xFFFF_FFFF;
+        Self { values }
+    }
+
+    // Reads the register, masking down to the low word or byte for sub-registers.
+    pub fn get(&self, reg: &Reg) -> u32 {
+        let base = self.values[reg.to_offset()];
+        if reg.is_reg16() {
+            base & 0xFFFF
+        } else if reg.is_low8() {
+            base & 0xFF
+        } else if reg.is_high8() {
+            (base >> 8) & 0xFF
+        } else {
+            base
+        }
+    }
+}
+
+impl Index<usize> for Registers {
+    type Output = u32;
+
+    fn index(&self, offset: usize) -> &u32 {
+        &self.values[offset]
+    }
+}
+
+impl IndexMut<usize> for Registers {
+    fn index_mut(&mut self, offset: usize) -> &mut u32 {
+        &mut self.values[offset]
+    }
 }
 
 impl ExitInfo {
@@ -62,7 +141,7 @@ impl MemMap {
 
 #[derive(Clone, Debug)]
 pub struct Interpreter {
-    registers: Vec<u32>,
+    registers: Registers,
     cf: bool,
     of: bool,
     zf: bool,
@@ -72,15 +151,14 @@ pub struct Interpreter {
     value_maps: HashMap<u32, u32>,
     bytecode: Vec<ByteCode>,
     trampolines: HashMap<u32, (String, usize)>,
+    return_values: HashMap<String, ReturnValue>,
+    trace_hook: Option<TraceHook>,
 }
 
 impl Interpreter {
     pub fn new() -> Self {
-        let mut registers = Vec::new();
-        registers.resize(Reg::num_registers(), 0);
-        registers[Reg::ESP.to_offset()] = 0xFFFF_FFFF;
         Self {
-            registers,
+            registers: Registers::new(),
             cf: false,
             of: false,
             zf: false,
@@ -90,7 +168,28 @@ impl Interpreter {
             bytecode: Vec::new(),
             value_maps: HashMap::new(),
             trampolines: HashMap::new(),
+            return_values: HashMap::new(),
+            trace_hook: None,
+        }
+    }
+
+    // Called with each instruction, and the registers before it runs. Returning
+    // TraceAction::Halt stops interpretation with ExitInfo::Halted.
+    pub fn set_trace_hook(&mut self, hook: Box<TraceFn>) {
+        self.trace_hook = Some(TraceHook(Arc::new(Mutex::new(hook))));
+    }
+
+    pub fn clear_trace_hook(&mut self) {
+        self.trace_hook = None;
+    }
+
+    fn call_trace_hook(&self, instr: &Instr) -> TraceAction {
+        if let Some(TraceHook(hook)) = &self.trace_hook {
+            let mut hook = hook.lock().expect("poisoned trace hook");
+            let f = &mut *hook;
+            return f(instr, &self.registers);
         }
+        TraceAction::Continue
     }
 
     pub fn push_stack_value(&mut self, value: u32) {
@@ -102,10 +201,54 @@ impl Interpreter {
         self.registers[reg.to_offset()] = value;
     }
 
+    pub fn register(&self, reg: Reg) -> u32 {
+        self.registers.get(&reg)
+    }
+
+    // Returns None if any of the four bytes at addr are not mapped.
+    pub fn read_mem_u32(&self, addr: u32) -> Option<u32> {
+        if let Some(value) = self.value_maps.get(&addr) {
+            return Some(*value);
+        }
+        for map in self.mem_maps.iter() {
+            if addr >= map.start && (addr - map.start) as usize + 4 <= map.mem.len() {
+                let rel = (addr - map.start) as usize;
+                let mut bytes = [0u8; 4];
+                bytes.copy_from_slice(&map.mem[rel..rel + 4]);
+                return Some(u32::from_le_bytes(bytes));
+            }
+        }
+        None
+    }
+
+    // The start address and length of every mapped value and writable block.
+    pub fn dump_mapped_regions(&self) -> Vec<(u32, usize)> {
+        let mut regions = self
+            .value_maps
+            .keys()
+            .map(|&addr| (addr, 4))
+            .chain(self.mem_maps.iter().map(|map| (map.start, map.mem.len())))
+            .collect::<Vec<_>>();
+        regions.sort();
+        regions
+    }
+
     pub fn add_trampoline(&mut self, addr: u32, name: &str, arg_count: usize) {
         self.trampolines.insert(addr, (name.to_owned(), arg_count));
     }
 
+    // Returning into the named trampoline will set EAX to the result of the
+    // callback and resume after the return, rather than exiting the interpreter.
+    // This is how engine functions like @HARDNumLoaded@8 hand values back.
+    pub fn register_return_value(&mut self, name: &str, f: Box<ReturnValueFn>) {
+        self.return_values
+            .insert(name.to_owned(), ReturnValue(Arc::new(Mutex::new(f))));
+    }
+
+    pub fn unregister_return_value(&mut self, name: &str) {
+        self.return_values.remove(name);
+    }
+
     pub fn map_value(&mut self, addr: u32, value: u32) {
         self.value_maps.insert(addr, value);
     }
@@ -178,6 +321,9 @@ impl Interpreter {
         while offset < bc_len {
             let instr = self.bytecode[bc_offset].instrs[offset].to_owned();
             trace!("{:3}:{:04X}: {}", offset, self.eip(), instr);
+            if self.call_trace_hook(&instr) == TraceAction::Halt {
+                return Ok(ExitInfo::Halted);
+            }
             offset += 1;
             *self.eip_mut() += instr.size() as u32;
             match instr.memonic {
@@ -187,6 +333,7 @@ impl Interpreter {
                 Memonic::Pop => self.do_pop(instr.op(0))?,
                 Memonic::Move => self.do_move(instr.op(0), instr.op(1))?,
                 Memonic::MoveStr => self.do_move_str(instr.op(0), instr.op(1))?,
+                Memonic::MoveSX => self.do_move_sx(instr.op(0), instr.op(1))?,
                 Memonic::MoveZX => self.do_move_zx(instr.op(0), instr.op(1))?,
                 Memonic::Dec => self.do_dec(instr.op(0))?,
                 Memonic::Inc => self.do_inc(instr.op(0))?,
@@ -219,6 +366,12 @@ impl Interpreter {
                         return self.jump(offset);
                     }
                 }
+                Memonic::Loop => {
+                    let offset = self.do_loop(instr.op(0))?;
+                    if offset != 0 {
+                        return self.jump(offset);
+                    }
+                }
                 Memonic::Call => {
                     let offset = self.do_call(instr.op(0))?;
                     if offset != 0 {
@@ -231,6 +384,16 @@ impl Interpreter {
                     // about what we would be returning from.
                     trace!("checking {:08X} against {:?}", absolute, self.trampolines);
                     if self.trampolines.contains_key(&absolute) {
+                        let return_value = self
+                            .return_values
+                            .get(&self.trampolines[&absolute].0)
+                            .cloned();
+                        if let Some(ReturnValue(f)) = return_value {
+                            let value = (&mut *f.lock().expect("poisoned return value"))();
+                            self.set_register_value(Reg::EAX, value);
+                            let resume = self.eip();
+                            return self.interpret(resume);
+                        }
                         let (ref name, ref arg_count) = self.trampolines[&absolute];
                         let mut args = self.stack[self.stack.len() - *arg_count..].to_owned();
                         args.reverse();
@@ -344,6 +507,16 @@ impl Interpreter {
         Ok(())
     }
 
+    fn do_move_sx(&mut self, op1: &Operand, op2: &Operand) -> Fallible<()> {
+        let v = self.get(op2)?;
+        let v = match op2.size() {
+            1 => i32::from(v as u8 as i8) as u32,
+            2 => i32::from(v as u16 as i16) as u32,
+            _ => v,
+        };
+        self.put(op1, v)
+    }
+
     fn do_move_zx(&mut self, op1: &Operand, op2: &Operand) -> Fallible<()> {
         let v = self.get(op2)?;
         self.put(op1, v)
@@ -580,6 +753,16 @@ impl Interpreter {
         Ok(offset)
     }
 
+    fn do_loop(&mut self, op: &Operand) -> Fallible<i32> {
+        *self.ecx_mut() = self.ecx().wrapping_sub(1);
+        if self.ecx() == 0 {
+            return Ok(0);
+        }
+        let offset = self.get(op)? as i32;
+        trace!("    loop -> {:04X}", offset);
+        Ok(offset)
+    }
+
     fn do_call(&mut self, op: &Operand) -> Fallible<i32> {
         let offset = self.get(op)? as i32;
         let ip = self.eip();
@@ -645,20 +828,9 @@ impl Interpreter {
             Operand::Imm32(u) => *u,
             Operand::Imm32s(i) => *i as u32,
             Operand::Register(r) => {
-                let base = self.registers[r.to_offset()];
-                if r.is_reg16() {
-                    trace!("    read_reg {} -> {:04X}", r, base & 0xFFFF);
-                    base & 0xFFFF
-                } else if r.is_low8() {
-                    trace!("    read_reg {} -> {:02X}", r, base & 0xFF);
-                    base & 0xFF
-                } else if r.is_high8() {
-                    trace!("    read_reg {} -> {:02X}", r, (base >> 8) & 0xFF);
-                    (base >> 8) & 0xFF
-                } else {
-                    trace!("    read_reg {} -> {:08X}", r, base);
-                    base
-                }
+                let v = self.registers.get(r);
+                trace!("    read_reg {} -> {:08X}", r, v);
+                v
             }
             Operand::Memory(mem) => {
                 let addr = self.lea(mem)?;
@@ -667,14 +839,28 @@ impl Interpreter {
         })
     }
 
+    // Find the mapped value that fully contains the access, returning its
+    // address and the offset of the access within it.
+    fn find_value_map(&self, addr: u32, size: u8) -> Option<(u32, usize)> {
+        if self.value_maps.contains_key(&addr) {
+            return Some((addr, 0));
+        }
+        self.value_maps
+            .keys()
+            .find(|&&base| addr > base && addr - base + u32::from(size) <= 4)
+            .map(|&base| (base, (addr - base) as usize))
+    }
+
     fn mem_lookup(&self, addr: u32, size: u8) -> Fallible<u32> {
-        if let Some(value) = self.value_maps.get(&addr) {
-            trace!("    read_val  {:08X} -> {:08X}", addr, value);
-            return Ok(*value);
+        if let Some((base, rel)) = self.find_value_map(addr, size) {
+            let bytes = self.value_maps[&base].to_le_bytes();
+            let v = load(&bytes, rel, size)?;
+            trace!("    read_val  {:08X} -> {:08X}", addr, v);
+            return Ok(v);
         }
         for map in self.mem_maps.iter() {
             if addr >= map.start && ((addr - map.start) as usize) < map.mem.len() {
-                let v = self.mem_peek((addr - map.start) as usize, &map.mem, size)?;
+                let v = load(&map.mem, (addr - map.start) as usize, size)?;
                 trace!("    read_rw  {} @ {:08X} -> {:08X}", size, addr, v);
                 return Ok(v);
             }
@@ -686,21 +872,6 @@ impl Interpreter {
         )
     }
 
-    fn mem_peek(&self, rel: usize, v: &[u8], size: u8) -> Fallible<u32> {
-        Ok(match size {
-            1 => u32::from(v[rel]),
-            2 => {
-                let vp: &[u16] = unsafe { mem::transmute(&v[rel..rel + 2]) };
-                u32::from(vp[0])
-            }
-            4 => {
-                let vp: &[u32] = unsafe { mem::transmute(&v[rel..rel + 4]) };
-                vp[0]
-            }
-            _ => bail!("don't know how to handle read size {}", size),
-        })
-    }
-
     fn put(&mut self, op: &Operand, v: u32) -> Fallible<()> {
         match op {
             Operand::Register(r) => {
@@ -731,14 +902,13 @@ impl Interpreter {
     }
 
     fn mem_write(&mut self, addr: u32, v: u32, size: u8) -> Fallible<()> {
-        if let Some(value) = self.value_maps.get_mut(&addr) {
-            trace!("    write_rw {}@ {:08X} <- {:08X}", size, addr, v);
-            match size {
-                1 => *value = (*value & 0xFFFF_FF00) | (v & 0xFF),
-                2 => *value = (*value & 0xFFFF_0000) | (v & 0xFFFF),
-                4 => *value = v,
-                _ => bail!("don't know how to handle write size {}", size),
-            }
+        if let Some((base, rel)) = self.find_value_map(addr, size) {
+            trace!("    write_val {}@ {:08X} <- {:08X}", size, addr, v);
+            let value = self.value_maps.get_mut(&base).unwrap();
+            let mut bytes = value.to_le_bytes();
+            store(&mut bytes, rel, v, size)?;
+            *value = u32::from_le_bytes(bytes);
+            return Ok(());
         }
         for map in self.mem_maps.iter_mut() {
             if addr >= map.start && ((addr - map.start) as usize) < map.mem.len() {
@@ -746,19 +916,7 @@ impl Interpreter {
                     map.protection == MapProtection::Write,
                     "write to read-only memory"
                 );
-                let rel = (addr - map.start) as usize;
-                match size {
-                    1 => map.mem[rel] = v as u8,
-                    2 => {
-                        let vp: &mut [u16] = unsafe { mem::transmute(&mut map.mem[rel..rel + 2]) };
-                        vp[0] = v as u16;
-                    }
-                    4 => {
-                        let vp: &mut [u32] = unsafe { mem::transmute(&mut map.mem[rel..rel + 4]) };
-                        vp[0] = v;
-                    }
-                    _ => bail!("don't know how to handle write size {}", size),
-                }
+                store(&mut map.mem, (addr - map.start) as usize, v, size)?;
                 trace!("    write_rw {}@ {:08X} <- {:08X}", size, addr, v);
                 return Ok(());
             }
@@ -767,6 +925,50 @@ impl Interpreter {
     }
 }
 
+// Little-endian loads and stores of 1, 2, or 4 bytes at an offset in mapped memory.
+fn load(mem: &[u8], rel: usize, size: u8) -> Fallible<u32> {
+    ensure!(
+        rel + size as usize <= mem.len(),
+        "read of {} bytes at offset {} runs off the end of the mapping",
+        size,
+        rel
+    );
+    Ok(match size {
+        1 => u32::from(mem[rel]),
+        2 => u32::from(u16::from_le_bytes([mem[rel], mem[rel + 1]])),
+        4 => u32::from_le_bytes([mem[rel], mem[rel + 1], mem[rel + 2], mem[rel + 3]]),
+        _ => bail!("don't know how to handle read size {}", size),
+    })
+}
+
+fn store(mem: &mut [u8], rel: usize, v: u32, size: u8) -> Fallible<()> {
+    ensure!(
+        rel + size as usize <= mem.len(),
+        "write of {} bytes at offset {} runs off the end of the mapping",
+        size,
+        rel
+    );
+    match size {
+        1 => store8(mem, rel, v as u8),
+        2 => store16(mem, rel, v as u16),
+        4 => store32(mem, rel, v),
+        _ => bail!("don't know how to handle write size {}", size),
+    }
+    Ok(())
+}
+
+fn store8(mem: &mut [u8], rel: usize, v: u8) {
+    mem[rel] = v;
+}
+
+fn store16(mem: &mut [u8], rel: usize, v: u16) {
+    mem[rel..rel + 2].copy_from_slice(&v.to_le_bytes());
+}
+
+fn store32(mem: &mut [u8], rel: usize, v: u32) {
+    mem[rel..rel + 4].copy_from_slice(&v.to_le_bytes());
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -794,4 +996,151 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn it_can_inspect_registers_and_memory() -> Fallible<()> {
+        // mov eax, [0x1000]
+        let code = [0xA1, 0x00, 0x10, 0x00, 0x00];
+        let bc = ByteCode::disassemble_until(0, &code, |_| false)?;
+
+        let mut interp = Interpreter::new();
+        interp.add_code(bc);
+        interp.map_value(0x1000, 0xDEAD_BEEF);
+        interp.map_writable(0x2000, vec![1, 2, 3, 4, 5, 6])?;
+        interp.interpret(0)?;
+
+        assert_eq!(interp.register(Reg::EAX), 0xDEAD_BEEF);
+        assert_eq!(interp.register(Reg::AX), 0xBEEF);
+        assert_eq!(interp.read_mem_u32(0x1000), Some(0xDEAD_BEEF));
+        assert_eq!(interp.read_mem_u32(0x2002), Some(0x0605_0403));
+        assert_eq!(interp.read_mem_u32(0x2003), None);
+        assert_eq!(interp.read_mem_u32(0x3000), None);
+        assert_eq!(interp.dump_mapped_regions(), vec![(0x1000, 4), (0x2000, 6)]);
+        Ok(())
+    }
+
+    #[test]
+    fn it_writes_only_the_bytes_covered_by_a_store() -> Fallible<()> {
+        // mov WORD PTR [0x2000], ax; mov BYTE PTR [0x1001], al
+        let code = [
+            0x66, 0x89, 0x05, 0x00, 0x20, 0x00, 0x00, 0x88, 0x05, 0x01, 0x10, 0x00, 0x00,
+        ];
+        let bc = ByteCode::disassemble_until(0, &code, |_| false)?;
+
+        let mut interp = Interpreter::new();
+        interp.add_code(bc);
+        interp.set_register_value(Reg::EAX, 0x1234_5678);
+        interp.map_value(0x1000, 0x1122_3344);
+        interp.map_writable(0x2000, vec![0x11, 0x22, 0x33, 0x44])?;
+        interp.interpret(0)?;
+
+        assert_eq!(interp.unmap_value(0x1000), 0x1122_7844);
+        assert_eq!(interp.unmap_writable(0x2000)?, vec![0x78, 0x56, 0x33, 0x44]);
+        Ok(())
+    }
+
+    #[test]
+    fn it_sign_extends_moves() -> Fallible<()> {
+        // movsx eax, cl; movsx edx, cx
+        let code = [0x0F, 0xBE, 0xC1, 0x0F, 0xBF, 0xD1];
+        let bc = ByteCode::disassemble_until(0, &code, |_| false)?;
+
+        let mut interp = Interpreter::new();
+        interp.add_code(bc);
+        interp.set_register_value(Reg::ECX, 0x0000_80FE);
+        interp.interpret(0)?;
+        assert_eq!(interp.register(Reg::EAX), 0xFFFF_FFFE);
+        assert_eq!(interp.register(Reg::EDX), 0xFFFF_80FE);
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_jump_and_loop() -> Fallible<()> {
+        // jmp +1; inc eax; inc eax; jmp near +1; inc eax; inc eax
+        let code = [
+            0xEB, 0x01, 0x40, 0x40, 0xE9, 0x01, 0x00, 0x00, 0x00, 0x40, 0x40,
+        ];
+        let bc = ByteCode::disassemble_until(0, &code, |_| false)?;
+        let mut interp = Interpreter::new();
+        interp.add_code(bc);
+        interp.interpret(0)?;
+        assert_eq!(interp.register(Reg::EAX), 2);
+        assert_eq!(interp.eip(), code.len() as u32);
+
+        // mov ecx, 3; inc eax; loop -3
+        let code = [0xB9, 0x03, 0x00, 0x00, 0x00, 0x40, 0xE2, 0xFD];
+        let bc = ByteCode::disassemble_until(0, &code, |_| false)?;
+        let mut interp = Interpreter::new();
+        interp.add_code(bc);
+        interp.interpret(0)?;
+        assert_eq!(interp.register(Reg::EAX), 3);
+        assert_eq!(interp.register(Reg::ECX), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn it_resumes_after_return_value_trampolines() -> Fallible<()> {
+        // push 0x100; ret; inc eax
+        let code = [0x68, 0x00, 0x01, 0x00, 0x00, 0xC3, 0x40];
+        let bc = ByteCode::disassemble_until(0, &code, |_| false)?;
+
+        let mut interp = Interpreter::new();
+        interp.add_code(bc.clone());
+        interp.add_trampoline(0x100, "@HARDNumLoaded@8", 0);
+        let (name, _) = interp.interpret(0)?.ok_trampoline()?;
+        assert_eq!(name, "@HARDNumLoaded@8");
+
+        let mut interp = Interpreter::new();
+        interp.add_code(bc);
+        interp.add_trampoline(0x100, "@HARDNumLoaded@8", 0);
+        interp.register_return_value("@HARDNumLoaded@8", Box::new(|| 41));
+        match interp.interpret(0)? {
+            ExitInfo::OutOfInstructions => {}
+            _ => panic!("expected to resume after the trampoline"),
+        }
+        assert_eq!(interp.register(Reg::EAX), 42);
+        Ok(())
+    }
+
+    #[test]
+    fn it_calls_the_trace_hook_per_instruction() -> Fallible<()> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // mov eax, 1; inc eax; inc eax
+        let code = [0xB8, 0x01, 0x00, 0x00, 0x00, 0x40, 0x40];
+        let bc = ByteCode::disassemble_until(0, &code, |_| false)?;
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let hook_count = count.clone();
+        let mut interp = Interpreter::new();
+        interp.add_code(bc.clone());
+        interp.set_trace_hook(Box::new(move |_instr, _regs| {
+            hook_count.fetch_add(1, Ordering::SeqCst);
+            TraceAction::Continue
+        }));
+        let exit = interp.interpret(0)?;
+        match exit {
+            ExitInfo::OutOfInstructions => {}
+            _ => panic!("expected to run off the end of the code"),
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+
+        // Halting before the second inc leaves eax at 2.
+        let mut interp = Interpreter::new();
+        interp.add_code(bc);
+        interp.set_trace_hook(Box::new(|_instr, regs| {
+            if regs.get(&Reg::EAX) == 2 {
+                TraceAction::Halt
+            } else {
+                TraceAction::Continue
+            }
+        }));
+        let exit = interp.interpret(0)?;
+        match exit {
+            ExitInfo::Halted => {}
+            _ => panic!("expected the trace hook to halt"),
+        }
+        assert_eq!(interp.eip(), 6);
+        Ok(())
+    }
 }
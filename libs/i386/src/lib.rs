@@ -22,6 +22,8 @@ mod interpreter;
 mod lut;
 
 pub use crate::{
-    disassembler::{ByteCode, DisassemblyError, Instr, MemRef, Memonic, Operand, Reg},
-    interpreter::{ExitInfo, Interpreter},
+    disassembler::{
+        ByteCode, DisassemblyError, Instr, MemRef, Memonic, MissingOpcodes, Operand, Reg,
+    },
+    interpreter::{ExitInfo, Interpreter, Registers, TraceAction},
 };
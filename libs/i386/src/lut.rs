@@ -183,8 +183,10 @@ pub enum Memonic {
     IMul3,
     IMul2,
     LEA,
+    Loop,
     Move,
     MoveStr,
+    MoveSX,
     MoveZX,
     Mul,
     Neg,
@@ -367,6 +369,7 @@ lazy_static! {
             (0xD1, 1, make_op!(RotCR:   E/v, Imp/const1)),
             (0xD1, 4, make_op!(ShiftL:  E/v)),
             (0xD1, 7, make_op!(Sar:     E/v, Imp/const1)),
+            (0xE2, 0, make_op!(Loop:    J/bs)),
             (0xE8, 0, make_op!(Call:    J/v)),
             (0xE9, 0, make_op!(Jump:    J/v)),
             (0xEB, 0, make_op!(Jump:    J/bs)),
@@ -380,10 +383,28 @@ lazy_static! {
             (0xFF, 1, make_op!(Dec:     E/v)),
             (0xFF, 4, make_op!(Jump:    E/v)),
 
-            (0x0F85, 0, make_op!(J|ZF=0: J/v)),
-            (0x0FAF, 0, make_op!(IMul2:  G/v, E/v)),
-            (0x0FB6, 0, make_op!(MoveZX: G/v, E/b)),
-            (0x0FB7, 0, make_op!(MoveZX: G/v, E/w)),
+            // Two byte opcodes, escaped with 0x0F.
+            (0x0F80, 0, make_op!(J|OF=1:  J/v)),
+            (0x0F81, 0, make_op!(J|OF=0:  J/v)),
+            (0x0F82, 0, make_op!(J|CF=1:  J/v)),
+            (0x0F83, 0, make_op!(J|CF=0:  J/v)),
+            (0x0F84, 0, make_op!(J|ZF=1:  J/v)),
+            (0x0F85, 0, make_op!(J|ZF=0:  J/v)),
+            (0x0F86, 0, make_op!(J|CF=1||ZF=1: J/v)),
+            (0x0F87, 0, make_op!(J|CF=0&&ZF=0: J/v)),
+            (0x0F88, 0, make_op!(J|SF=1:  J/v)),
+            (0x0F89, 0, make_op!(J|SF=0:  J/v)),
+            (0x0F8A, 0, make_op!(J|PF=1:  J/v)),
+            (0x0F8B, 0, make_op!(J|PF=0:  J/v)),
+            (0x0F8C, 0, make_op!(J|SF!=OF:J/v)),
+            (0x0F8D, 0, make_op!(J|SF==OF:J/v)),
+            (0x0F8E, 0, make_op!(J|ZF=1||SF!=OF: J/v)),
+            (0x0F8F, 0, make_op!(J|ZF=0&&SF==OF: J/v)),
+            (0x0FAF, 0, make_op!(IMul2:   G/v, E/v)),
+            (0x0FB6, 0, make_op!(MoveZX:  G/v, E/b)),
+            (0x0FB7, 0, make_op!(MoveZX:  G/v, E/w)),
+            (0x0FBE, 0, make_op!(MoveSX:  G/v, E/b)),
+            (0x0FBF, 0, make_op!(MoveSX:  G/v, E/w)),
         ];
         for &(ref op, ref ext, ref def) in ops.iter() {
             out.insert((*op, *ext), (*def).clone());
@@ -16,7 +16,7 @@ use failure::{ensure, Fallible};
 use packed_struct::packed_struct;
 use pal::Palette;
 use peff::PE;
-use std::{fs, mem, str};
+use std::{fs, mem, ops::Range, str};
 
 packed_struct!(LayerHeader {
      _0 => unk_ptr_00x100: u32, // Ramp
@@ -72,6 +72,44 @@ packed_struct!(LayerPlaneHeader {
 });
 
 const PALETTE_SLICE_SIZE: usize = 0xC0;
+pub const BAND_SIZE: usize = 0x10;
+
+// Each palette fragment in a LAY is 4 rows of 16 colors. Each row gets copied over a part of the
+// system palette that is otherwise unused, and that terrain samples index into. We have not found
+// anything in the LAY that says where rows go, so this is from comparing against the game.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum TerrainBand {
+    // Nothing in T2 samples points here: possibly the horizon or fog.
+    Horizon,
+    // T2 samples use 0xFF for all water.
+    Water,
+    // Only used by the land in TVIET, as 0xC2 to 0xC7.
+    AlternateLand,
+    // Used by nearly all T2 land samples, as 0xD0 to 0xDA.
+    Land,
+}
+
+impl TerrainBand {
+    // The order to apply bands in: later bands win where they overlap.
+    pub const OVERLAY_ORDER: [TerrainBand; 4] = [
+        TerrainBand::Water,
+        TerrainBand::Horizon,
+        TerrainBand::Land,
+        TerrainBand::AlternateLand,
+    ];
+
+    pub fn lay_row(self) -> usize {
+        match self {
+            TerrainBand::Horizon => 0,
+            TerrainBand::Water => 1,
+            TerrainBand::AlternateLand => 2,
+            TerrainBand::Land => 3,
+        }
+    }
+}
+
+// The number of rows in each palette fragment.
+pub const BAND_COUNT: usize = TerrainBand::OVERLAY_ORDER.len();
 
 pub struct Layer {
     data: Vec<u8>,
@@ -89,6 +127,34 @@ impl Layer {
         self.frag_offsets.len()
     }
 
+    // The palette entries that a band gets copied over.
+    pub fn band_for(band: TerrainBand) -> Range<u8> {
+        let start = match band {
+            TerrainBand::Horizon => 0xE0 - 1,
+            TerrainBand::Water => 0xF0 - 1,
+            TerrainBand::AlternateLand => 0xC0,
+            TerrainBand::Land => 0xD0,
+        };
+        start..start + BAND_SIZE as u8
+    }
+
+    pub fn band_palette(&self, index: usize, band: TerrainBand) -> Fallible<Palette> {
+        let start = band.lay_row() * BAND_SIZE;
+        self.for_index(index)?.slice(start, start + BAND_SIZE)
+    }
+
+    // Apply every band of the given fragment to the system palette.
+    pub fn overlay_palette(&self, index: usize, system_palette: &Palette) -> Fallible<Palette> {
+        let mut palette = system_palette.clone();
+        for &band in &TerrainBand::OVERLAY_ORDER {
+            palette.overlay_at(
+                &self.band_palette(index, band)?,
+                Self::band_for(band).start as usize,
+            )?;
+        }
+        Ok(palette)
+    }
+
     pub fn from_bytes(data: &[u8], palette: &Palette) -> Fallible<Layer> {
         let mut pe = PE::from_bytes(data)?;
         pe.relocate(0x0000_0000)?;
@@ -236,4 +302,29 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn it_assigns_bands() -> Fallible<()> {
+        assert_eq!(Layer::band_for(TerrainBand::AlternateLand), 0xC0..0xD0);
+        assert_eq!(Layer::band_for(TerrainBand::Land), 0xD0..0xE0);
+        assert_eq!(Layer::band_for(TerrainBand::Horizon), 0xDF..0xEF);
+        assert_eq!(Layer::band_for(TerrainBand::Water), 0xEF..0xFF);
+
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:*.LAY".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let system_palette = Palette::from_bytes(&catalog.read_name_sync("PALETTE.PAL")?)?;
+            let layer = Layer::from_bytes(&catalog.read_sync(fid)?, &system_palette)?;
+            let palette = layer.overlay_palette(0, &system_palette)?;
+            for &band in &[TerrainBand::Water, TerrainBand::AlternateLand] {
+                let colors = layer.band_palette(0, band)?;
+                for (i, offset) in Layer::band_for(band).enumerate() {
+                    assert_eq!(palette.rgb(offset as usize)?, colors.rgb(i)?);
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
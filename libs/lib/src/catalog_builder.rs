@@ -12,11 +12,14 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
-use crate::{LibDrawer, Priority, GAME_INFO};
+use crate::{LibDrawer, OverlayDrawer, Priority, GAME_INFO, OVERLAY_PRIORITY};
 use catalog::{Catalog, DirectoryDrawer, FileId};
 use failure::Fallible;
 use glob::{MatchOptions, Pattern};
-use std::{env, fs, path::PathBuf};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
 
 // FA Engine aware lookup of asset files. This can run in several modes:
 //   1) Collect all games as tags under test_data/packed and add lib drawers
@@ -103,6 +106,21 @@ impl CatalogBuilder {
         Ok(catalog)
     }
 
+    // Layer a directory of loose files over the default label and every game,
+    // so that modders can replace individual assets without repacking a LIB.
+    pub fn add_overlay_dir(catalog: &mut Catalog, path: &Path) -> Fallible<()> {
+        catalog.add_drawer(OverlayDrawer::from_directory(OVERLAY_PRIORITY, path)?)?;
+        for game in &GAME_INFO {
+            for label in &[game.packed_label(), game.unpacked_label()] {
+                catalog.add_labeled_drawer(
+                    label,
+                    OverlayDrawer::from_directory(OVERLAY_PRIORITY, path)?,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn build_and_select(inputs: &[String]) -> Fallible<(Catalog, Vec<FileId>)> {
         let catalog = Self::build()?;
         let mut selected = Vec::new();
@@ -138,7 +156,7 @@ impl CatalogBuilder {
         Ok((catalog, selected))
     }
 
-    fn find_test_data_dir(mut cwd: PathBuf) -> Option<PathBuf> {
+    pub(crate) fn find_test_data_dir(mut cwd: PathBuf) -> Option<PathBuf> {
         loop {
             if cwd.join("test_data").exists() {
                 return Some(cwd.join("test_data"));
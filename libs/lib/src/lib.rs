@@ -18,14 +18,16 @@
 
 mod catalog_builder;
 mod game_info;
+mod overlay;
 pub use crate::{
     catalog_builder::CatalogBuilder,
     game_info::{GameInfo, GAME_INFO},
+    overlay::{OverlayDrawer, OVERLAY_PRIORITY},
 };
 
 use catalog::{DrawerFileId, DrawerFileMetadata, DrawerInterface};
 use codepage_437::{BorrowFromCp437, FromCp437, CP437_CONTROL};
-use failure::{ensure, err_msg, Fallible};
+use failure::{ensure, err_msg, Compat, Error, Fail, Fallible};
 use lazy_static::lazy_static;
 use log::trace;
 use memmap::{Mmap, MmapOptions};
@@ -34,11 +36,34 @@ use regex::Regex;
 use std::{
     borrow::Cow,
     collections::HashMap,
-    fs, mem,
+    fs,
+    io::{Cursor, Read, Seek},
+    mem,
     path::{Path, PathBuf},
     str,
 };
 
+#[derive(Debug, Fail)]
+pub enum LibError {
+    #[fail(
+        display = "lib member {} is corrupt: expected {} bytes but found {}",
+        name, expected, actual
+    )]
+    MemberCorrupt {
+        name: String,
+        expected: usize,
+        actual: usize,
+    },
+    #[fail(display = "lib member {} is too short to hold its inflated size", name)]
+    MemberTruncated { name: String },
+    #[fail(display = "lib member {} failed to inflate: {}", name, cause)]
+    MemberInflateFailed {
+        name: String,
+        #[cause]
+        cause: Compat<Error>,
+    },
+}
+
 #[derive(Clone, Debug)]
 pub enum CompressionType {
     None = 0,
@@ -158,6 +183,16 @@ impl PackedFileInfo {
     }
 }
 
+// One file in a lib, as listed in the lib's index.
+#[derive(Clone, Debug)]
+pub struct ManifestEntry {
+    pub name: String,
+    pub offset: usize,
+    pub compression: Option<&'static str>,
+    pub packed_size: u64,
+    pub unpacked_size: u64,
+}
+
 packed_struct!(LibHeader {
     _0 => magic: [u8; 5], // EALIB
     _1 => count: u16
@@ -179,6 +214,11 @@ pub struct LibDrawer {
 
 impl LibDrawer {
     pub fn from_path(priority: i64, path: &Path) -> Fallible<Box<dyn DrawerInterface>> {
+        Ok(Box::new(Self::new(priority, path)?))
+    }
+
+    // Open a single lib directly, rather than as part of a Catalog.
+    pub fn new(priority: i64, path: &Path) -> Fallible<Self> {
         trace!("opening lib file {:?} with priority {}", path, priority);
         let fp = fs::File::open(path)?;
         let map = unsafe { MmapOptions::new().map(&fp)? };
@@ -221,13 +261,51 @@ impl LibDrawer {
             .to_string_lossy()
             .to_string();
 
-        Ok(Box::new(Self {
+        Ok(Self {
             drawer_index,
             index,
             data: map,
             priority,
             name,
-        }))
+        })
+    }
+
+    // List the lib's files, in the order that they are stored.
+    pub fn manifest(&self) -> Fallible<Vec<ManifestEntry>> {
+        let mut out = Vec::with_capacity(self.index.len());
+        for i in 0..self.index.len() {
+            let id = DrawerFileId::from_u32(i as u32);
+            let stat = self.stat_sync(id)?;
+            out.push(ManifestEntry {
+                name: stat.name,
+                offset: self.index[&id].start_offset,
+                compression: stat.compression,
+                packed_size: stat.packed_size,
+                unpacked_size: stat.unpacked_size,
+            });
+        }
+        Ok(out)
+    }
+
+    fn unpacked_size(&self, info: &PackedFileInfo) -> usize {
+        let bytes = &self.data[info.start_offset..info.start_offset + 4];
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize
+    }
+
+    pub fn find_id(&self, name: &str) -> Fallible<DrawerFileId> {
+        let name = name.to_uppercase();
+        self.drawer_index
+            .iter()
+            .find(|(_, n)| **n == name)
+            .map(|(id, _)| *id)
+            .ok_or_else(|| err_msg(format!("lib: no file named {} in {}", name, self.name)))
+    }
+
+    // Uncompressed members are read straight out of the mapped lib without a copy. Compressed
+    // members have to be inflated in full before we can hand out a reader, since neither of
+    // the compression formats can seek.
+    pub fn open(&self, name: &str) -> Fallible<impl Read + Seek + '_> {
+        Ok(Cursor::new(self.read_sync(self.find_id(name)?)?))
     }
 }
 
@@ -294,30 +372,42 @@ impl DrawerInterface for LibDrawer {
     fn read_sync(&self, id: DrawerFileId) -> Fallible<Cow<[u8]>> {
         ensure!(self.index.contains_key(&id));
         let info = &self.index[&id];
-        Ok(match info.compression {
-            CompressionType::None => Cow::from(&self.data[info.start_offset..info.end_offset]),
-            CompressionType::PKWare => {
-                // FIXME: zerocopy
-                let dwords: &[u32] =
-                    unsafe { mem::transmute(&self.data[info.start_offset..info.start_offset + 4]) };
-                let expect_output_size = Some(dwords[0] as usize);
-                Cow::from(pkware::explode(
-                    &self.data[info.start_offset + 4..info.end_offset],
-                    expect_output_size,
-                )?)
-            }
-            CompressionType::LZSS => {
-                // FIXME: zerocopy
-                let dwords: &[u32] =
-                    unsafe { mem::transmute(&self.data[info.start_offset..info.start_offset + 4]) };
-                let expect_output_size = Some(dwords[0] as usize);
-                Cow::from(lzss::explode(
-                    &self.data[info.start_offset + 4..info.end_offset],
-                    expect_output_size,
-                )?)
+        let inflate = match info.compression {
+            CompressionType::None => {
+                return Ok(Cow::from(&self.data[info.start_offset..info.end_offset]))
             }
+            CompressionType::PKWare => pkware::explode,
+            CompressionType::LZSS => lzss::explode,
             CompressionType::PXPK => unimplemented!(),
-        })
+        };
+
+        // Compressed members start with their inflated size. Check what we get back against it
+        // so that a damaged lib is reported here and not as a parse failure far downstream.
+        let name = &self.drawer_index[&id];
+        if info.start_offset + 4 > info.end_offset {
+            return Err(LibError::MemberTruncated {
+                name: name.to_owned(),
+            }
+            .into());
+        }
+        let expected = self.unpacked_size(info);
+        let content = inflate(
+            &self.data[info.start_offset + 4..info.end_offset],
+            Some(expected),
+        )
+        .map_err(|e| LibError::MemberInflateFailed {
+            name: name.to_owned(),
+            cause: e.compat(),
+        })?;
+        if content.len() != expected {
+            return Err(LibError::MemberCorrupt {
+                name: name.to_owned(),
+                expected,
+                actual: content.len(),
+            }
+            .into());
+        }
+        Ok(Cow::from(content))
     }
 }
 
@@ -330,4 +420,164 @@ mod tests {
         let _catalog = CatalogBuilder::build()?;
         Ok(())
     }
+
+    #[test]
+    fn test_overlay_shadows_archive() -> Fallible<()> {
+        let overlay_dir = std::env::temp_dir().join("openfa-test-overlay");
+        fs::create_dir_all(&overlay_dir)?;
+        fs::write(overlay_dir.join("fuel.sh"), b"overlay bytes")?;
+
+        let mut catalog = CatalogBuilder::build()?;
+        let label = GAME_INFO[0].label();
+        catalog.set_default_label(&label);
+        let palette = catalog.read_name_sync("PALETTE.PAL")?.to_vec();
+
+        CatalogBuilder::add_overlay_dir(&mut catalog, &overlay_dir)?;
+        catalog.set_default_label(&label);
+        assert_eq!(&catalog.read_name_sync("FUEL.SH")?[..], b"overlay bytes");
+        assert_eq!(catalog.read_name_sync("PALETTE.PAL")?.to_vec(), palette);
+
+        fs::remove_dir_all(&overlay_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_lists_every_member() -> Fallible<()> {
+        let test_dir = CatalogBuilder::find_test_data_dir(std::env::current_dir()?)
+            .ok_or_else(|| err_msg("no test_data"))?;
+        let pack_dir = test_dir.join("packed").join(GAME_INFO[0].test_dir);
+        for entry in fs::read_dir(&pack_dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .map(|e| e.to_string_lossy().to_ascii_lowercase())
+                != Some("lib".to_owned())
+            {
+                continue;
+            }
+            let drawer = LibDrawer::new(0, &path)?;
+            let manifest = drawer.manifest()?;
+            assert_eq!(manifest.len(), drawer.drawer_index.len());
+            for pair in manifest.windows(2) {
+                assert!(pair[0].offset <= pair[1].offset);
+            }
+            let first = &manifest[0];
+            let content = drawer.read_sync(drawer.find_id(&first.name)?)?;
+            assert_eq!(first.unpacked_size, content.len() as u64);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_member_is_reported() -> Fallible<()> {
+        let test_dir = CatalogBuilder::find_test_data_dir(std::env::current_dir()?)
+            .ok_or_else(|| err_msg("no test_data"))?;
+        let pack_dir = test_dir.join("packed").join(GAME_INFO[0].test_dir);
+        let tmp_path = std::env::temp_dir().join("openfa-test-corrupt.lib");
+        for entry in fs::read_dir(&pack_dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .map(|e| e.to_string_lossy().to_ascii_lowercase())
+                != Some("lib".to_owned())
+            {
+                continue;
+            }
+            let manifest = LibDrawer::new(0, &path)?.manifest()?;
+            let target = if let Some(target) = manifest.iter().find(|e| e.compression.is_some()) {
+                target
+            } else {
+                continue;
+            };
+
+            // Claim one more byte than the member inflates to.
+            let mut data = fs::read(&path)?;
+            let wrong = target.unpacked_size as u32 + 1;
+            data[target.offset..target.offset + 4].copy_from_slice(&wrong.to_le_bytes());
+            fs::write(&tmp_path, &data)?;
+
+            let drawer = LibDrawer::new(0, &tmp_path)?;
+            let err = drawer
+                .read_sync(drawer.find_id(&target.name)?)
+                .expect_err("corrupt member");
+            match err.downcast::<LibError>()? {
+                LibError::MemberCorrupt {
+                    name,
+                    expected,
+                    actual,
+                } => {
+                    assert_eq!(name, target.name);
+                    assert_eq!(expected, wrong as usize);
+                    assert_eq!(actual, target.unpacked_size as usize);
+                }
+                other => panic!("expected a size mismatch, got: {}", other),
+            }
+            drop(drawer);
+            fs::remove_file(&tmp_path)?;
+            break;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_inflate_failure_is_reported() -> Fallible<()> {
+        // A lib with one PKWare member whose stream has an invalid literal mode.
+        let mut data = b"EALIB".to_vec();
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(b"BROKEN.PIC\0\0\0");
+        data.push(CompressionType::PKWare as u8);
+        data.extend_from_slice(&25u32.to_le_bytes());
+        data.extend_from_slice(&16u32.to_le_bytes());
+        data.extend_from_slice(&[0x05, 0x04, 0x00, 0x00]);
+        let tmp_path = std::env::temp_dir().join("openfa-test-inflate.lib");
+        fs::write(&tmp_path, &data)?;
+
+        let drawer = LibDrawer::new(0, &tmp_path)?;
+        let err = drawer
+            .read_sync(drawer.find_id("BROKEN.PIC")?)
+            .expect_err("broken member");
+        match err.downcast::<LibError>()? {
+            LibError::MemberInflateFailed { name, cause } => {
+                assert_eq!(name, "BROKEN.PIC");
+                assert_eq!(cause.to_string(), "invalid header");
+            }
+            other => panic!("expected an inflate failure, got: {}", other),
+        }
+        drop(drawer);
+        fs::remove_file(&tmp_path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_streams_members() -> Fallible<()> {
+        let test_dir = CatalogBuilder::find_test_data_dir(std::env::current_dir()?)
+            .ok_or_else(|| err_msg("no test_data"))?;
+        let pack_dir = test_dir.join("packed").join(GAME_INFO[0].test_dir);
+        for entry in fs::read_dir(&pack_dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .map(|e| e.to_string_lossy().to_ascii_lowercase())
+                != Some("lib".to_owned())
+            {
+                continue;
+            }
+            let drawer = LibDrawer::new(0, &path)?;
+            for name in drawer.drawer_index.values().take(32) {
+                let expect = drawer.read_sync(drawer.find_id(name)?)?.to_vec();
+                let mut reader = drawer.open(name)?;
+                let mut actual = Vec::new();
+                reader.read_to_end(&mut actual)?;
+                assert_eq!(actual, expect);
+
+                if expect.len() > 1 {
+                    let mut tail = Vec::new();
+                    reader.seek(std::io::SeekFrom::Start(1))?;
+                    reader.read_to_end(&mut tail)?;
+                    assert_eq!(&tail[..], &expect[1..]);
+                }
+            }
+        }
+        Ok(())
+    }
 }
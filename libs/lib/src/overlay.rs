@@ -0,0 +1,95 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use catalog::{DrawerFileId, DrawerFileMetadata, DrawerInterface};
+use failure::{ensure, Fallible};
+use log::trace;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Overlays sit above every LIB and installdir drawer so that a loose file
+// will always shadow the packed asset of the same name.
+pub const OVERLAY_PRIORITY: i64 = 1_000;
+
+// Loose files that replace assets without having to repack the original LIBs.
+// Names are upper-cased on index, matching LibDrawer, so that lookups are
+// case-insensitive with respect to whatever the modder named the file.
+pub struct OverlayDrawer {
+    drawer_index: HashMap<DrawerFileId, String>,
+    paths: HashMap<DrawerFileId, PathBuf>,
+    priority: i64,
+    name: String,
+}
+
+impl OverlayDrawer {
+    pub fn from_directory(priority: i64, path: &Path) -> Fallible<Box<dyn DrawerInterface>> {
+        trace!("opening overlay dir {:?} with priority {}", path, priority);
+        ensure!(path.is_dir(), "overlay path must be a directory");
+        let mut drawer_index = HashMap::new();
+        let mut paths = HashMap::new();
+        for (i, raw_entry) in fs::read_dir(path)?.enumerate() {
+            let entry = raw_entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            let dfid = DrawerFileId::from_u32(i as u32);
+            let name = entry.file_name().to_string_lossy().to_uppercase();
+            drawer_index.insert(dfid, name);
+            paths.insert(dfid, entry.path());
+        }
+        Ok(Box::new(Self {
+            drawer_index,
+            paths,
+            priority,
+            name: format!("overlay:{}", path.to_string_lossy()),
+        }))
+    }
+}
+
+impl DrawerInterface for OverlayDrawer {
+    fn index(&self) -> Fallible<HashMap<DrawerFileId, String>> {
+        Ok(self.drawer_index.clone())
+    }
+
+    fn priority(&self) -> i64 {
+        self.priority
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn stat_sync(&self, id: DrawerFileId) -> Fallible<DrawerFileMetadata> {
+        ensure!(self.paths.contains_key(&id));
+        let path = &self.paths[&id];
+        let size = fs::metadata(path)?.len();
+        Ok(DrawerFileMetadata {
+            drawer_file_id: id,
+            name: self.drawer_index[&id].to_owned(),
+            compression: None,
+            packed_size: size,
+            unpacked_size: size,
+            path: Some(path.to_owned()),
+        })
+    }
+
+    fn read_sync(&self, id: DrawerFileId) -> Fallible<Cow<[u8]>> {
+        ensure!(self.paths.contains_key(&id));
+        Ok(Cow::from(fs::read(&self.paths[&id])?))
+    }
+}
@@ -15,14 +15,19 @@
 #![allow(clippy::cognitive_complexity)]
 
 mod obj;
+mod placement;
 mod special;
 mod util;
 mod waypoint;
 
+pub use crate::placement::{Placement, PlacementDrawState, PlacementKind};
+
 use crate::{obj::ObjectInfo, special::SpecialInfo, waypoint::Waypoint};
 use catalog::Catalog;
 use failure::{bail, ensure, err_msg, Fallible};
+use nalgebra::Point3;
 use std::{collections::HashMap, str::FromStr};
+use t2::Terrain;
 use xt::TypeManager;
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
@@ -381,6 +386,46 @@ impl MissionMap {
         &self.objects
     }
 
+    // Resolve every object with a shape to a world transform. The terrain must be the T2 that
+    // this mission was built on.
+    pub fn placements(&self, terrain: &Terrain) -> Fallible<Vec<Placement>> {
+        let mut placements = Vec::with_capacity(self.objects.len());
+        for info in &self.objects {
+            let xt = info.xt();
+            let shape_name = if let Some(shape_name) = xt.ot().shape.as_ref() {
+                shape_name
+            } else {
+                // Hidden entities (e.g. flak emplacements in TVIET) have nothing to draw.
+                continue;
+            };
+            let pos = info.position();
+            let kind = if xt.is_pt() {
+                PlacementKind::Plane
+            } else if xt.is_nt() {
+                PlacementKind::Vehicle
+            } else {
+                PlacementKind::Structure
+            };
+            let y = if kind == PlacementKind::Plane {
+                pos.y
+            } else {
+                terrain.height_at(pos.x, pos.z)
+            };
+            let draw_state = PlacementDrawState {
+                gear_down: kind != PlacementKind::Plane || info.speed() == 0f32,
+            };
+            placements.push(Placement::new(
+                info.name(),
+                shape_name,
+                Point3::new(pos.x, y, pos.z),
+                *info.angle(),
+                kind,
+                draw_state,
+            ));
+        }
+        Ok(placements)
+    }
+
     // These are all of the terrains and map references in the base games.
     // FA:
     //     FA_2.LIB:
@@ -596,4 +641,67 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn it_places_objects_within_the_terrain() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:BAL.MM".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let type_manager = TypeManager::empty();
+            let contents = from_dos_string(catalog.read_sync(fid)?);
+            let mm = MissionMap::from_str(&contents, &type_manager, &catalog)?;
+            let terrain = Terrain::from_bytes(&catalog.read_name_sync(mm.t2_name())?)?;
+            let placements = mm.placements(&terrain)?;
+            assert!(!placements.is_empty());
+            for placement in &placements {
+                let p = placement.position();
+                assert!(p.x >= 0f32 && p.x <= terrain.extent_east_west_in_ft());
+                assert!(p.z >= 0f32 && p.z <= terrain.extent_north_south_in_ft());
+                assert!(p.y >= 0f32);
+                if !placement.airborne() {
+                    assert!(placement.draw_state.gear_down);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_snaps_ground_objects_to_the_terrain() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:BAL.MM".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let type_manager = TypeManager::empty();
+            let contents = from_dos_string(catalog.read_sync(fid)?);
+            let mm = MissionMap::from_str(&contents, &type_manager, &catalog)?;
+            let terrain = Terrain::from_bytes(&catalog.read_name_sync(mm.t2_name())?)?;
+            let (dx, dz) = terrain.sample_spacing_in_ft();
+            let h = |xi: u32, zi: u32| {
+                let xi = xi.min(terrain.width() - 1);
+                let zi = zi.min(terrain.height() - 1);
+                f32::from(terrain.samples[(zi * terrain.width() + xi) as usize].height)
+                    * t2::FEET_PER_HEIGHT_UNIT
+            };
+            for mut placement in mm.placements(&terrain)? {
+                let p = placement.position();
+                if placement.airborne() {
+                    continue;
+                }
+                // Blend the four samples around the object by hand.
+                let (xi, zi) = ((p.x / dx) as u32, (p.z / dz) as u32);
+                let (tx, tz) = (p.x / dx - xi as f32, p.z / dz - zi as f32);
+                let south = h(xi, zi) * (1f32 - tx) + h(xi + 1, zi) * tx;
+                let north = h(xi, zi + 1) * (1f32 - tx) + h(xi + 1, zi + 1) * tx;
+                let expect = south * (1f32 - tz) + north * tz;
+                assert!((p.y - expect).abs() < 0.01 * t2::FEET_PER_HEIGHT_UNIT);
+                placement.align_to_terrain(&terrain);
+                assert_eq!(placement.position(), p);
+                let up = placement.rotation() * nalgebra::Vector3::y();
+                assert!(up.y > 0f32);
+            }
+        }
+        Ok(())
+    }
 }
@@ -266,4 +266,8 @@ impl ObjectInfo {
     pub fn angle(&self) -> &UnitQuaternion<f32> {
         &self.angle
     }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
 }
@@ -0,0 +1,90 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use nalgebra::{Isometry3, Point3, Translation3, UnitQuaternion, Vector3};
+use t2::Terrain;
+
+// How a placed shape is posed when it first appears.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PlacementDrawState {
+    // Planes that start the mission moving are already flying, so have their gear up.
+    pub gear_down: bool,
+}
+
+// What sort of thing a placement is, so that the world can give it the right behavior.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PlacementKind {
+    // A PT: placed at the altitude given in the mission.
+    Plane,
+    // An NT that is not a plane: ground vehicles, ships, and emplacements.
+    Vehicle,
+    // A bare OT: buildings, trees, and other scenery that never moves.
+    Structure,
+}
+
+// A mission object resolved to a shape and a position in the world. Positions are in feet, in
+// the same frame as the MM file: x east, z north from the south-west corner of the T2, and y up.
+#[derive(Clone, Debug)]
+pub struct Placement {
+    pub name: Option<String>,
+    pub shape_name: String,
+    pub transform: Isometry3<f32>,
+    pub kind: PlacementKind,
+    pub draw_state: PlacementDrawState,
+}
+
+impl Placement {
+    pub(crate) fn new(
+        name: Option<String>,
+        shape_name: &str,
+        position: Point3<f32>,
+        rotation: UnitQuaternion<f32>,
+        kind: PlacementKind,
+        draw_state: PlacementDrawState,
+    ) -> Self {
+        Self {
+            name,
+            shape_name: shape_name.to_owned(),
+            transform: Isometry3::from_parts(Translation3::from(position.coords), rotation),
+            kind,
+            draw_state,
+        }
+    }
+
+    // Planes are placed at the altitude given in the mission; everything else sits on the ground.
+    pub fn airborne(&self) -> bool {
+        self.kind == PlacementKind::Plane
+    }
+
+    pub fn position(&self) -> Point3<f32> {
+        Point3::from(self.transform.translation.vector)
+    }
+
+    pub fn rotation(&self) -> &UnitQuaternion<f32> {
+        &self.transform.rotation
+    }
+
+    // Tilt ground objects so that their up vector follows the terrain normal. Most buildings
+    // should stay upright, even on a hillside, so this is left for the caller to opt in to.
+    pub fn align_to_terrain(&mut self, terrain: &Terrain) {
+        if self.airborne() {
+            return;
+        }
+        let p = self.position();
+        let normal = Vector3::from(terrain.normal_at(p.x, p.z));
+        if let Some(tilt) = UnitQuaternion::rotation_between(&Vector3::y(), &normal) {
+            self.transform.rotation = tilt * self.transform.rotation;
+        }
+    }
+}
@@ -48,7 +48,7 @@ impl FromRow for TypeTag {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Eq, PartialEq)]
 pub enum ObjectKind {
     Fighter = 0b1000_0000_0000_0000,
     Bomber = 0b0100_0000_0000_0000,
@@ -278,6 +278,14 @@ impl ObjectType {
     pub fn long_name(&self) -> &str {
         &self.ot_names.long_name
     }
+
+    pub fn category(&self) -> &ObjectKind {
+        &self.obj_class
+    }
+
+    pub fn shape_name(&self) -> Option<&str> {
+        self.shape.as_ref().map(String::as_str)
+    }
 }
 
 #[cfg(test)]
@@ -14,7 +14,22 @@
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
 use failure::{ensure, Fallible};
 use image::{Pixel, Rgb, Rgba};
-use std::{borrow::Cow, fs::File, io::Write};
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::Write,
+    time::{Duration, Instant},
+};
+
+/// The sRGB electro-optical transfer function for one 8-bit channel.
+pub fn srgb_to_linear(c: u8) -> f32 {
+    let c = f32::from(c) / 255f32;
+    if c <= 0.040_45 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
 
 #[derive(Clone)]
 pub struct Palette {
@@ -98,6 +113,18 @@ impl Palette {
         ])
     }
 
+    /// As rgba_f32, but decoded from the palette's sRGB encoding into linear
+    /// color, for use with sRGB render targets and textures.
+    pub fn rgba_f32_srgb(&self, index: usize) -> Fallible<[f32; 4]> {
+        let c = self.rgb(index)?;
+        Ok([
+            srgb_to_linear(c.data[0]),
+            srgb_to_linear(c.data[1]),
+            srgb_to_linear(c.data[2]),
+            1f32,
+        ])
+    }
+
     pub fn rgb(&self, index: usize) -> Fallible<Rgb<u8>> {
         ensure!(index < self.entries.len(), "index outside of palette");
         Ok(self.entries[index])
@@ -121,6 +148,21 @@ impl Palette {
         self.entries[offset][2] = color[2];
     }
 
+    /// Rotate the closed range of entries [start, end] forward by `steps`, as
+    /// the game does for water and glow effects. Entries outside the range
+    /// are left untouched.
+    pub fn cycle_range(&mut self, start: u8, end: u8, steps: u32) -> Fallible<()> {
+        ensure!(start <= end, "cycle range start after end");
+        ensure!(
+            (end as usize) < self.entries.len(),
+            "cycle range outside of palette"
+        );
+        let range = &mut self.entries[start as usize..=end as usize];
+        let len = range.len();
+        range.rotate_right(steps as usize % len);
+        Ok(())
+    }
+
     // Slice from [start to end), half-open.
     pub fn slice(&self, start: usize, end: usize) -> Fallible<Palette> {
         let slice = self.entries[start..end].to_owned();
@@ -218,6 +260,78 @@ impl Palette {
     }
 }
 
+struct CycleRange {
+    start: u8,
+    end: u8,
+    period: Duration,
+    steps: u32,
+}
+
+/// Cycles ranges of a base palette on a timer. Renderers should re-upload
+/// the palette whenever `update` reports a change.
+pub struct PaletteAnimator {
+    base: Palette,
+    current: Palette,
+    ranges: Vec<CycleRange>,
+    start_time: Instant,
+}
+
+impl PaletteAnimator {
+    pub fn new(base: Palette, start_time: Instant) -> Self {
+        Self {
+            current: base.clone(),
+            base,
+            ranges: Vec::new(),
+            start_time,
+        }
+    }
+
+    /// Advance the closed range [start, end] by one entry every `period`.
+    pub fn with_range(mut self, start: u8, end: u8, period: Duration) -> Fallible<Self> {
+        ensure!(
+            period > Duration::from_millis(0),
+            "cycle period must be non-zero"
+        );
+        ensure!(
+            start <= end && (end as usize) < self.base.color_count,
+            "cycle range outside of palette"
+        );
+        self.ranges.push(CycleRange {
+            start,
+            end,
+            period,
+            steps: 0,
+        });
+        Ok(self)
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.current
+    }
+
+    /// Returns true if any range stepped since the last update.
+    pub fn update(&mut self, now: Instant) -> Fallible<bool> {
+        let elapsed = now.saturating_duration_since(self.start_time);
+        let mut changed = false;
+        for range in self.ranges.iter_mut() {
+            let steps = (elapsed.as_nanos() / range.period.as_nanos()) as u32;
+            if steps != range.steps {
+                range.steps = steps;
+                changed = true;
+            }
+        }
+        if changed {
+            // Recompute from the base so that overlapping ranges cannot drift.
+            self.current = self.base.clone();
+            for range in &self.ranges {
+                self.current
+                    .cycle_range(range.start, range.end, range.steps)?;
+            }
+        }
+        Ok(changed)
+    }
+}
+
 impl<'a> From<&'a Palette> for Cow<'a, Palette> {
     #[inline]
     fn from(pal: &'a Palette) -> Cow<'a, Palette> {
@@ -253,6 +367,52 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn it_can_cycle_a_range() -> Fallible<()> {
+        let mut pal = Palette::grayscale()?;
+        pal.cycle_range(4, 7, 1)?;
+        let got = (3..=8)
+            .map(|i| Ok(pal.rgb(i)?[0]))
+            .collect::<Fallible<Vec<u8>>>()?;
+        assert_eq!(got, vec![3, 7, 4, 5, 6, 8]);
+
+        // Wraps within the range.
+        pal.cycle_range(4, 7, 3)?;
+        assert_eq!(pal.rgb(4)?[0], 4);
+        assert!(pal.cycle_range(7, 4, 1).is_err());
+        assert!(Palette::empty()?.cycle_range(0, 3, 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_animate_a_palette() -> Fallible<()> {
+        let start = Instant::now();
+        let mut anim = PaletteAnimator::new(Palette::grayscale()?, start).with_range(
+            0x10,
+            0x13,
+            Duration::from_millis(100),
+        )?;
+        assert!(!anim.update(start + Duration::from_millis(50))?);
+        assert_eq!(anim.palette().rgb(0x10)?[0], 0x10);
+        assert!(anim.update(start + Duration::from_millis(250))?);
+        assert_eq!(anim.palette().rgb(0x10)?[0], 0x12);
+        assert_eq!(anim.palette().rgb(0x14)?[0], 0x14);
+        Ok(())
+    }
+
+    #[test]
+    fn it_linearizes_srgb() -> Fallible<()> {
+        let pal = Palette::grayscale()?;
+        let [r, g, b, a] = pal.rgba_f32_srgb(0x80)?;
+        assert!((r - 0.2158).abs() < 0.001);
+        assert_eq!(r, g);
+        assert_eq!(g, b);
+        assert_eq!(a, 1f32);
+        assert_eq!(pal.rgba_f32_srgb(0)?[0], 0f32);
+        assert!((pal.rgba_f32_srgb(0xFF)?[0] - 1f32).abs() < 0.000_01);
+        Ok(())
+    }
+
     #[test]
     fn it_can_be_empty() -> Fallible<()> {
         let empty = Vec::new();
@@ -189,6 +189,10 @@ impl PE {
         let mut sections = HashMap::new();
         for i in 0..coff.number_of_sections() as usize {
             let section_offset = section_table_offset + i * mem::size_of::<SectionHeader>();
+            ensure!(
+                data.len() >= section_offset + mem::size_of::<SectionHeader>(),
+                "pe file too short for section table"
+            );
             let section_ptr: *const SectionHeader = data[section_offset..].as_ptr() as *const _;
             let section: &SectionHeader = unsafe { &*section_ptr };
             ensure!(
@@ -265,6 +269,11 @@ impl PE {
             );
             let start = section.pointer_to_raw_data() as usize;
             let end = start + section.virtual_size() as usize;
+            ensure!(
+                end <= data.len(),
+                "section {} extends past end of file",
+                name
+            );
             let section_data = &data[start..end];
             if name == "$$DOSX" {
                 ensure!(
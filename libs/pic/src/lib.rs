@@ -39,6 +39,9 @@ packed_struct!(Span {
     _index => index: u32 as usize
 });
 
+// Palette index that FA uses to mark see-through pixels in textures.
+pub const TRANSPARENT_INDEX: u8 = 0xFF;
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum PicFormat {
     Format0,
@@ -103,6 +106,15 @@ impl Pic {
 
     /// Render the PIC in `data` into a raster image. The given palette will be used if the image does not contain its own.
     pub fn decode(palette: &Palette, data: &[u8]) -> Fallible<DynamicImage> {
+        Self::decode_with_transparency(palette, data, Some(TRANSPARENT_INDEX))
+    }
+
+    /// As decode, but pixels with `transparent_index` get alpha 0; None gives a fully opaque image.
+    pub fn decode_with_transparency(
+        palette: &Palette,
+        data: &[u8],
+        transparent_index: Option<u8>,
+    ) -> Fallible<DynamicImage> {
         let header = Header::overlay(data)?;
         let format = PicFormat::from_word(header.format())?;
         Ok(match format {
@@ -114,6 +126,7 @@ impl Pic {
                     header.height(),
                     &palette,
                     &data[header.pixels_offset()..header.pixels_offset() + header.pixels_size()],
+                    transparent_index,
                 )?
             }
             PicFormat::Format1 => {
@@ -124,6 +137,7 @@ impl Pic {
                     &palette,
                     &data[header.spans_offset()..header.spans_offset() + header.spans_size()],
                     &data[header.pixels_offset()..header.pixels_offset() + header.pixels_size()],
+                    transparent_index,
                 )?
             }
         })
@@ -209,12 +223,12 @@ impl Pic {
         height: u32,
         palette: &Palette,
         pixels: &[u8],
+        transparent_index: Option<u8>,
     ) -> Fallible<DynamicImage> {
         let mut imgbuf = image::ImageBuffer::new(width, height);
         for (i, p) in imgbuf.pixels_mut().enumerate() {
-            let pix = pixels[i] as usize;
-            let mut clr = palette.rgba(pix)?;
-            if pix == 0xFF {
+            let mut clr = palette.rgba(pixels[i] as usize)?;
+            if Some(pixels[i]) == transparent_index {
                 clr.data[3] = 0x00;
             }
             *p = clr;
@@ -234,7 +248,7 @@ impl Pic {
             let i = index as u32;
             let pix = *p as usize;
             let mut clr = palette.rgba(pix)?;
-            if pix == 0xFF {
+            if pix == TRANSPARENT_INDEX as usize {
                 clr.data[3] = 0x00;
             }
             into_image.put_pixel(offset_x + i % width, offset_y + i / width, clr);
@@ -254,7 +268,7 @@ impl Pic {
             let i = index as u32;
             let pix = *p as usize;
             let mut clr = palette.rgba(pix)?;
-            if pix == 0xFF {
+            if pix == TRANSPARENT_INDEX as usize {
                 clr.data[3] = 0x00;
             }
             let pos = (offset[0] + i % width, offset[1] + i / width);
@@ -270,6 +284,7 @@ impl Pic {
         palette: &Palette,
         spans: &[u8],
         pixels: &[u8],
+        transparent_index: Option<u8>,
     ) -> Fallible<DynamicImage> {
         let mut imgbuf = image::ImageBuffer::new(width, height);
         assert_eq!(spans.len() % mem::size_of::<Span>(), 0);
@@ -285,8 +300,10 @@ impl Pic {
 
             for (j, column) in (span.start()..=span.end()).enumerate() {
                 let offset = span.index() + j;
-                let pix = pixels[offset] as usize;
-                let clr = palette.rgba(pix)?;
+                let mut clr = palette.rgba(pixels[offset] as usize)?;
+                if Some(pixels[offset]) == transparent_index {
+                    clr.data[3] = 0x00;
+                }
                 imgbuf.put_pixel(column, span.row(), clr);
             }
         }
@@ -322,6 +339,42 @@ mod tests {
         Ok(())
     }
 
+    fn make_format0_pic(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+        let header_size = mem::size_of::<Header>() as u32;
+        let mut data = Vec::new();
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&width.to_le_bytes());
+        data.extend_from_slice(&height.to_le_bytes());
+        data.extend_from_slice(&header_size.to_le_bytes());
+        data.extend_from_slice(&(pixels.len() as u32).to_le_bytes());
+        for _ in 0..6 {
+            data.extend_from_slice(&0u32.to_le_bytes());
+        }
+        assert_eq!(data.len(), header_size as usize);
+        data.extend_from_slice(pixels);
+        data
+    }
+
+    #[test]
+    fn it_maps_transparent_index_to_alpha() -> Fallible<()> {
+        let palette = Palette::grayscale()?;
+        let data = make_format0_pic(2, 2, &[0xFF, 0x01, 0x02, 0xFF]);
+
+        let img = Pic::decode(&palette, &data)?.to_rgba();
+        assert_eq!(img.get_pixel(0, 0).data[3], 0);
+        assert_eq!(img.get_pixel(1, 0).data[3], 255);
+        assert_eq!(img.get_pixel(0, 1).data[3], 255);
+        assert_eq!(img.get_pixel(1, 1).data[3], 0);
+
+        let img = Pic::decode_with_transparency(&palette, &data, Some(0x01))?.to_rgba();
+        assert_eq!(img.get_pixel(0, 0).data[3], 255);
+        assert_eq!(img.get_pixel(1, 0).data[3], 0);
+
+        let img = Pic::decode_with_transparency(&palette, &data, None)?.to_rgba();
+        assert!(img.pixels().all(|p| p.data[3] == 255));
+        Ok(())
+    }
+
     #[test]
     fn it_can_decode_all_pics() -> Fallible<()> {
         let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:*.PIC".to_owned()])?;
@@ -12,9 +12,10 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use catalog::Catalog;
 use codepage_437::{FromCp437, CP437_CONTROL};
 use failure::{ensure, Fallible};
-use fnt::Fnt;
+use fnt::{Fnt, Font};
 use font_common::{upload_texture_luma, FontInterface, GlyphFrame};
 use gpu::GPU;
 use i386::{Interpreter, Reg};
@@ -69,6 +70,24 @@ impl FontInterface for FntFont {
 }
 
 impl FntFont {
+    /// Load `font` from the game archives, or hand back the fallback font
+    /// (e.g. a TTF) if the current game does not ship that FNT.
+    pub fn from_catalog<F>(
+        catalog: &Catalog,
+        font: Font,
+        gpu: &mut GPU,
+        fallback: F,
+    ) -> Fallible<Box<dyn FontInterface>>
+    where
+        F: FnOnce(&mut GPU) -> Fallible<Box<dyn FontInterface>>,
+    {
+        if !catalog.exists(font.file_name()) {
+            trace!("no {} in catalog; using fallback font", font.file_name());
+            return fallback(gpu);
+        }
+        Self::from_fnt(&Fnt::from_catalog(catalog, font)?, gpu)
+    }
+
     pub fn from_fnt(fnt: &Fnt, gpu: &mut GPU) -> Fallible<Box<dyn FontInterface>> {
         trace!("GlyphCacheFNT::new");
 
@@ -17,7 +17,7 @@ use crate::{
     upload::{AnalysisResults, DrawSelection, ShapeUploader, ShapeWidgets, Vertex},
 };
 use catalog::Catalog;
-use failure::Fallible;
+use failure::{ensure, Fallible};
 use gpu::DrawIndirectCommand;
 use lazy_static::lazy_static;
 use pal::Palette;
@@ -25,6 +25,7 @@ use sh::RawShape;
 use std::{
     collections::HashMap,
     mem,
+    ops::Range,
     sync::{Arc, Mutex, RwLock},
 };
 
@@ -38,6 +39,9 @@ const VERTEX_CHUNK_HIGH_WATER_COUNT: usize =
 const VERTEX_CHUNK_BYTES: usize = VERTEX_CHUNK_HIGH_WATER_BYTES + MAX_VERTEX_BYTES;
 const VERTEX_CHUNK_COUNT: usize = VERTEX_CHUNK_BYTES / mem::size_of::<Vertex>();
 
+// No single shape uploads more vertices than this.
+pub const MAX_SHAPE_VERTEX_COUNT: usize = MAX_VERTEX_BYTES / mem::size_of::<Vertex>();
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct ChunkId(u32);
 
@@ -68,6 +72,15 @@ pub struct ChunkFlags {
 }
 
 impl ChunkFlags {
+    pub(crate) fn for_placeholder() -> Self {
+        Self {
+            has_transform: true,
+            has_flags: false,
+            has_anim: false,
+            has_xform: false,
+        }
+    }
+
     pub(crate) fn for_analysis(analysis: &AnalysisResults) -> Self {
         Self {
             has_transform: true,
@@ -103,6 +116,10 @@ impl ChunkPart {
         }
     }
 
+    pub fn vertex_range(&self) -> Range<usize> {
+        self.vertex_start..self.vertex_start + self.vertex_count
+    }
+
     pub fn draw_command(&self, first_instance: u32, instance_count: u32) -> DrawIndirectCommand {
         DrawIndirectCommand {
             first_vertex: self.vertex_start as u32,
@@ -119,6 +136,16 @@ impl ChunkPart {
     pub fn xform_count(&self) -> usize {
         self.xform_count
     }
+
+    // [min, max] corners of the uploaded vertices, in render coordinates.
+    pub fn aabb(&self) -> [[f32; 3]; 2] {
+        *self.shape_widgets.read().unwrap().aabb()
+    }
+
+    pub fn describe_errata(&self) -> String {
+        let widgets = self.shape_widgets.read().unwrap();
+        format!("{}: {}", widgets.name(), widgets.errata().describe())
+    }
 }
 
 pub struct OpenChunk {
@@ -132,6 +159,9 @@ pub struct OpenChunk {
     last_shape_id: u32,
 
     chunk_parts: HashMap<ShapeId, ChunkPart>,
+
+    // How each shape was drawn, so that the chunk can be rebuilt. The placeholder has none.
+    selections: HashMap<ShapeId, DrawSelection>,
 }
 
 impl OpenChunk {
@@ -143,6 +173,25 @@ impl OpenChunk {
             vertex_upload_buffer: Vec::with_capacity(VERTEX_CHUNK_COUNT),
             last_shape_id: 0,
             chunk_parts: HashMap::new(),
+            selections: HashMap::new(),
+        })
+    }
+
+    // Start over on a closed chunk, keeping its chunk id so that shape ids stay valid.
+    pub(crate) fn reopen(chunk: &ClosedChunk) -> Fallible<Self> {
+        Ok(Self {
+            chunk_id: chunk.chunk_id,
+            chunk_flags: chunk.chunk_flags,
+            atlas_builder: MegaAtlas::new()?,
+            vertex_upload_buffer: Vec::with_capacity(VERTEX_CHUNK_COUNT),
+            last_shape_id: chunk
+                .chunk_parts
+                .keys()
+                .map(|shape_id| (shape_id.0).1)
+                .max()
+                .unwrap_or(0),
+            chunk_parts: HashMap::new(),
+            selections: HashMap::new(),
         })
     }
 
@@ -164,6 +213,22 @@ impl OpenChunk {
         palette: &Palette,
         catalog: &Catalog,
     ) -> Fallible<ShapeId> {
+        let shape_id = self.allocate_shape_id();
+        self.upload_shape_as(shape_id, name, analysis, sh, selection, palette, catalog)?;
+        Ok(shape_id)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn upload_shape_as(
+        &mut self,
+        shape_id: ShapeId,
+        name: &str,
+        analysis: AnalysisResults,
+        sh: &RawShape,
+        selection: &DrawSelection,
+        palette: &Palette,
+        catalog: &Catalog,
+    ) -> Fallible<()> {
         let start_vertex = self.vertex_upload_buffer.len();
         let (shape_widgets, mut verts) = ShapeUploader::new(name, palette, catalog).draw_model(
             sh,
@@ -174,9 +239,24 @@ impl OpenChunk {
         self.vertex_upload_buffer.append(&mut verts);
 
         let part = ChunkPart::new(start_vertex, self.vertex_upload_buffer.len(), shape_widgets);
+        self.chunk_parts.insert(shape_id, part);
+        self.selections.insert(shape_id, selection.clone());
+        Ok(())
+    }
+
+    pub(crate) fn upload_placeholder(&mut self, name: &str) -> ShapeId {
         let shape_id = self.allocate_shape_id();
+        self.upload_placeholder_as(shape_id, name);
+        shape_id
+    }
+
+    pub(crate) fn upload_placeholder_as(&mut self, shape_id: ShapeId, name: &str) {
+        let start_vertex = self.vertex_upload_buffer.len();
+        let (shape_widgets, mut verts) = ShapeUploader::placeholder_model(name);
+        self.vertex_upload_buffer.append(&mut verts);
+
+        let part = ChunkPart::new(start_vertex, self.vertex_upload_buffer.len(), shape_widgets);
         self.chunk_parts.insert(shape_id, part);
-        Ok(shape_id)
     }
 
     fn allocate_shape_id(&mut self) -> ShapeId {
@@ -189,6 +269,10 @@ impl OpenChunk {
         self.chunk_id
     }
 
+    pub(crate) fn chunk_flags(&self) -> ChunkFlags {
+        self.chunk_flags
+    }
+
     pub fn part(&self, shape_id: ShapeId) -> &ChunkPart {
         &self.chunk_parts[&shape_id]
     }
@@ -198,11 +282,13 @@ impl OpenChunk {
 pub struct ClosedChunk {
     vertex_buffer: wgpu::Buffer,
     vertex_count: u32,
+    byte_size: usize,
     atlas_bind_group: wgpu::BindGroup,
 
     chunk_id: ChunkId,
     chunk_flags: ChunkFlags,
     chunk_parts: HashMap<ShapeId, ChunkPart>,
+    selections: HashMap<ShapeId, DrawSelection>,
 }
 
 impl ClosedChunk {
@@ -211,7 +297,19 @@ impl ClosedChunk {
         layout: &wgpu::BindGroupLayout,
         sampler: &wgpu::Sampler,
         gpu: &mut gpu::GPU,
+        encoder: &mut wgpu::CommandEncoder,
     ) -> Fallible<Self> {
+        // Draw commands come straight from the part ranges, so make sure they are in bounds.
+        for (shape_id, part) in &chunk.chunk_parts {
+            ensure!(
+                part.vertex_range().end <= chunk.vertex_upload_buffer.len(),
+                "chunk part {:?} vertices {:?} outside of chunk with {} vertices",
+                shape_id,
+                part.vertex_range(),
+                chunk.vertex_upload_buffer.len()
+            );
+        }
+
         let v_size = chunk.vertex_upload_buffer.len() * std::mem::size_of::<Vertex>();
         let a_size = chunk.atlas_builder.atlas_size();
         println!(
@@ -228,7 +326,7 @@ impl ClosedChunk {
             wgpu::BufferUsage::VERTEX,
         );
 
-        let atlas_view = chunk.atlas_builder.finish(gpu)?;
+        let atlas_view = chunk.atlas_builder.finish(gpu, encoder)?;
         let atlas_bind_group = gpu.device().create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("shape-chunk-atlas-bind-group"),
             layout,
@@ -248,9 +346,11 @@ impl ClosedChunk {
         Ok(ClosedChunk {
             vertex_buffer,
             vertex_count: chunk.vertex_upload_buffer.len() as u32,
+            byte_size: v_size + a_size,
             chunk_id: chunk.chunk_id,
             chunk_flags: chunk.chunk_flags,
             chunk_parts: chunk.chunk_parts,
+            selections: chunk.selections,
             atlas_bind_group,
         })
     }
@@ -271,7 +371,21 @@ impl ClosedChunk {
         self.chunk_id
     }
 
+    // GPU bytes held by the vertex buffer and atlas.
+    pub fn byte_size(&self) -> usize {
+        self.byte_size
+    }
+
     pub fn part(&self, shape_id: ShapeId) -> &ChunkPart {
         &self.chunk_parts[&shape_id]
     }
+
+    pub fn shape_ids(&self) -> impl Iterator<Item = &ShapeId> {
+        self.chunk_parts.keys()
+    }
+
+    // None for the placeholder, which is not drawn from a shape file.
+    pub(crate) fn selection(&self, shape_id: ShapeId) -> Option<&DrawSelection> {
+        self.selections.get(&shape_id)
+    }
 }
@@ -16,13 +16,14 @@ use crate::upload::ShapeUploader;
 use crate::{
     chunk::{ChunkFlags, ChunkId, ChunkPart, ClosedChunk, OpenChunk, ShapeId},
     texture_atlas::MegaAtlas,
-    upload::DrawSelection,
+    upload::{AnalysisResults, DrawSelection, MemoryLayout},
 };
 use catalog::Catalog;
-use failure::{err_msg, Fallible};
+use failure::{ensure, err_msg, Fallible};
+use log::warn;
 use pal::Palette;
 use sh::RawShape;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 pub struct ShapeChunkBuffer {
     layout: wgpu::BindGroupLayout,
@@ -31,8 +32,24 @@ pub struct ShapeChunkBuffer {
     name_to_shape_map: HashMap<String, ShapeId>,
     shape_to_chunk_map: HashMap<ShapeId, ChunkId>,
 
+    // The bytes of every shape replaced by reload_shape. Rebuilding a chunk reads
+    // these in preference to the catalog, so that earlier reloads are kept.
+    reloaded_data: HashMap<ShapeId, Vec<u8>>,
+
     open_chunks: HashMap<ChunkFlags, OpenChunk>,
     closed_chunks: HashMap<ChunkId, ClosedChunk>,
+
+    // If set, shapes that fail to load are drawn as a placeholder instead of failing.
+    use_placeholder: bool,
+    placeholder: Option<(ChunkId, ShapeId)>,
+
+    // Where shape code being analyzed sees memory outside of the shape itself.
+    memory_layout: MemoryLayout,
+
+    // Closed chunks past this many bytes may be evicted, least recently used first.
+    byte_budget: Option<usize>,
+    use_clock: u64,
+    chunk_last_used: HashMap<ChunkId, u64>,
 }
 
 impl ShapeChunkBuffer {
@@ -42,16 +59,98 @@ impl ShapeChunkBuffer {
             sampler: MegaAtlas::make_sampler(device),
             name_to_shape_map: HashMap::new(),
             shape_to_chunk_map: HashMap::new(),
+            reloaded_data: HashMap::new(),
             open_chunks: HashMap::new(),
             closed_chunks: HashMap::new(),
+            use_placeholder: false,
+            placeholder: None,
+            memory_layout: Default::default(),
+            byte_budget: None,
+            use_clock: 0,
+            chunk_last_used: HashMap::new(),
         })
     }
 
+    pub fn set_use_placeholder(&mut self, use_placeholder: bool) {
+        self.use_placeholder = use_placeholder;
+    }
+
+    pub fn placeholder_shape(&self) -> Option<ShapeId> {
+        self.placeholder.map(|(_, shape_id)| shape_id)
+    }
+
+    pub fn set_byte_budget(&mut self, byte_budget: Option<usize>) {
+        self.byte_budget = byte_budget;
+    }
+
+    pub fn set_memory_layout(&mut self, memory_layout: MemoryLayout) {
+        self.memory_layout = memory_layout;
+    }
+
+    pub fn closed_byte_size(&self) -> usize {
+        self.closed_chunks.values().map(|c| c.byte_size()).sum()
+    }
+
+    fn touch(&mut self, chunk_id: ChunkId) {
+        self.use_clock += 1;
+        self.chunk_last_used.insert(chunk_id, self.use_clock);
+    }
+
+    /// Free least recently used closed chunks until we are under budget. Chunks
+    /// in `live_chunks` still have instances and are never evicted. Shapes in an
+    /// evicted chunk are forgotten, so the next upload_shape re-uploads them.
+    pub fn evict_over_budget(&mut self, live_chunks: &HashSet<ChunkId>) -> Vec<ChunkId> {
+        let budget = match self.byte_budget {
+            Some(budget) => budget,
+            None => return Vec::new(),
+        };
+        let mut candidates = self
+            .closed_chunks
+            .keys()
+            .filter(|chunk_id| !live_chunks.contains(chunk_id))
+            .map(|&chunk_id| {
+                (
+                    self.chunk_last_used.get(&chunk_id).cloned().unwrap_or(0),
+                    chunk_id,
+                )
+            })
+            .collect::<Vec<_>>();
+        candidates.sort();
+
+        let mut total = self.closed_byte_size();
+        let mut evicted = Vec::new();
+        for (_, chunk_id) in candidates {
+            if total <= budget {
+                break;
+            }
+            let chunk = self
+                .closed_chunks
+                .remove(&chunk_id)
+                .expect("a closed chunk");
+            total -= chunk.byte_size();
+            for shape_id in chunk.shape_ids() {
+                self.shape_to_chunk_map.remove(shape_id);
+                self.reloaded_data.remove(shape_id);
+            }
+            let shape_to_chunk_map = &self.shape_to_chunk_map;
+            self.name_to_shape_map
+                .retain(|_, shape_id| shape_to_chunk_map.contains_key(shape_id));
+            self.chunk_last_used.remove(&chunk_id);
+            evicted.push(chunk_id);
+        }
+        evicted
+    }
+
+    /// Close every open chunk, recording all of their texture uploads into a single
+    /// submission. There is nothing to wait on afterwards: the queue runs the copies
+    /// before any later submission, so the chunks may be drawn from immediately.
     pub fn finish_open_chunks(&mut self, gpu: &mut gpu::GPU) -> Fallible<()> {
+        let mut encoder = Self::make_upload_encoder(gpu);
         let keys = self.open_chunks.keys().cloned().collect::<Vec<_>>();
         for chunk_flags in &keys {
-            self.finish_open_chunk(*chunk_flags, gpu)?;
+            self.close_open_chunk(*chunk_flags, gpu, &mut encoder)?;
         }
+        gpu.queue_mut().submit(&[encoder.finish()]);
         Ok(())
     }
 
@@ -59,16 +158,44 @@ impl ShapeChunkBuffer {
         &mut self,
         chunk_flags: ChunkFlags,
         gpu: &mut gpu::GPU,
+    ) -> Fallible<()> {
+        let mut encoder = Self::make_upload_encoder(gpu);
+        self.close_open_chunk(chunk_flags, gpu, &mut encoder)?;
+        gpu.queue_mut().submit(&[encoder.finish()]);
+        Ok(())
+    }
+
+    fn make_upload_encoder(gpu: &gpu::GPU) -> wgpu::CommandEncoder {
+        gpu.device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("shape-chunk-upload-command-encoder"),
+            })
+    }
+
+    fn close_open_chunk(
+        &mut self,
+        chunk_flags: ChunkFlags,
+        gpu: &mut gpu::GPU,
+        encoder: &mut wgpu::CommandEncoder,
     ) -> Fallible<()> {
         let open_chunk = self.open_chunks.remove(&chunk_flags).expect("a chunk");
         if open_chunk.chunk_is_empty() {
             return Ok(());
         }
-        let chunk = ClosedChunk::new(open_chunk, &self.layout, &self.sampler, gpu)?;
+        let chunk = ClosedChunk::new(open_chunk, &self.layout, &self.sampler, gpu, encoder)?;
+        self.touch(chunk.chunk_id());
         self.closed_chunks.insert(chunk.chunk_id(), chunk);
         Ok(())
     }
 
+    /// The chunk holding the shape, once that chunk has been finished.
+    pub fn closed_chunk_for_shape(&self, shape_id: ShapeId) -> Option<ChunkId> {
+        self.shape_to_chunk_map
+            .get(&shape_id)
+            .filter(|chunk_id| self.closed_chunks.contains_key(*chunk_id))
+            .cloned()
+    }
+
     pub fn upload_shape(
         &mut self,
         name: &str,
@@ -79,11 +206,193 @@ impl ShapeChunkBuffer {
     ) -> Fallible<(ChunkId, ShapeId)> {
         if let Some(&shape_id) = self.name_to_shape_map.get(name) {
             let chunk_id = self.shape_to_chunk_map[&shape_id];
+            self.touch(chunk_id);
             return Ok((chunk_id, shape_id));
         }
 
+        match self.upload_new_shape(name, selection, palette, catalog, gpu) {
+            Ok(ids) => Ok(ids),
+            Err(e) if self.use_placeholder => {
+                warn!("using placeholder for {}: {}", name, e);
+                let (chunk_id, shape_id) = self.upload_placeholder()?;
+                self.name_to_shape_map.insert(name.to_owned(), shape_id);
+                Ok((chunk_id, shape_id))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // The placeholder is uploaded once and shared by every shape that fails.
+    fn upload_placeholder(&mut self) -> Fallible<(ChunkId, ShapeId)> {
+        if let Some(ids) = self.placeholder {
+            if self.shape_to_chunk_map.contains_key(&ids.1) {
+                return Ok(ids);
+            }
+        }
+        let chunk_flags = ChunkFlags::for_placeholder();
+        if !self.open_chunks.contains_key(&chunk_flags) {
+            self.open_chunks
+                .insert(chunk_flags, OpenChunk::new(chunk_flags)?);
+        }
+        let chunk = self
+            .open_chunks
+            .get_mut(&chunk_flags)
+            .expect("an open chunk");
+        let chunk_id = chunk.chunk_id();
+        let shape_id = chunk.upload_placeholder("placeholder");
+        self.shape_to_chunk_map.insert(shape_id, chunk_id);
+        self.placeholder = Some((chunk_id, shape_id));
+        Ok((chunk_id, shape_id))
+    }
+
+    fn upload_new_shape(
+        &mut self,
+        name: &str,
+        selection: DrawSelection,
+        palette: &Palette,
+        catalog: &Catalog,
+        gpu: &mut gpu::GPU,
+    ) -> Fallible<(ChunkId, ShapeId)> {
         let sh = RawShape::from_bytes(&catalog.read_name_sync(&name)?)?;
-        let analysis = ShapeUploader::analyze_model(name, &sh, &selection)?;
+        let analysis = ShapeUploader::analyze_model(name, &sh, &selection, &self.memory_layout)?;
+        self.upload_analyzed_shape(name, sh, analysis, &selection, palette, catalog, gpu)
+    }
+
+    /// Upload many shapes at once. Results are in the same order as `names`. Shapes
+    /// are parsed on this thread: RawShape holds pointers into its own code, so it
+    /// cannot be handed over from a worker.
+    pub fn upload_shapes(
+        &mut self,
+        names: &[&str],
+        selection: DrawSelection,
+        palette: &Palette,
+        catalog: &Catalog,
+        gpu: &mut gpu::GPU,
+    ) -> Fallible<Vec<(ChunkId, ShapeId)>> {
+        self.upload_shapes_with_progress(names, selection, palette, catalog, gpu, &mut |_, _| {})
+    }
+
+    /// As upload_shapes, but calls `progress` with the number of shapes done and
+    /// the total after each shape is uploaded, for use by loading screens.
+    pub fn upload_shapes_with_progress(
+        &mut self,
+        names: &[&str],
+        selection: DrawSelection,
+        palette: &Palette,
+        catalog: &Catalog,
+        gpu: &mut gpu::GPU,
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Fallible<Vec<(ChunkId, ShapeId)>> {
+        // Parse each distinct name once; repeats share the first upload.
+        let mut uploaded = HashMap::new();
+        let mut out = Vec::with_capacity(names.len());
+        for name in names {
+            let ids = match uploaded.get(name) {
+                Some(&ids) => ids,
+                None => {
+                    let ids = self.upload_shape(name, selection.clone(), palette, catalog, gpu)?;
+                    uploaded.insert(name, ids);
+                    ids
+                }
+            };
+            out.push(ids);
+            progress(out.len(), names.len());
+        }
+        Ok(out)
+    }
+
+    /// Replace the shape registered under `name` with one parsed from `data`. The chunk
+    /// holding it is rebuilt under the same chunk and shape ids, so instances keep their
+    /// ids and only need fresh draw commands. The old vertex buffer and atlas are freed.
+    /// Every other shape in the chunk is parsed again, so this is meant for editing tools
+    /// rather than for use every frame. The new shape must animate in the same ways as
+    /// the old one, since that decides which chunk it can live in.
+    pub fn reload_shape(
+        &mut self,
+        name: &str,
+        data: &[u8],
+        selection: DrawSelection,
+        palette: &Palette,
+        catalog: &Catalog,
+        gpu: &mut gpu::GPU,
+    ) -> Fallible<(ChunkId, ShapeId)> {
+        let shape_id = self.shape_for(name)?;
+        ensure!(
+            Some(shape_id) != self.placeholder_shape(),
+            "cannot reload {}: it is drawn with the shared placeholder",
+            name
+        );
+        let chunk_id = self.shape_to_chunk_map[&shape_id];
+
+        // Rebuild from a closed chunk, so that we see every part that is in it.
+        self.finish_open_chunks(gpu)?;
+        let old_chunk = &self.closed_chunks[&chunk_id];
+        let mut chunk = OpenChunk::reopen(old_chunk)?;
+
+        let sh = RawShape::from_bytes(data)?;
+        let analysis = ShapeUploader::analyze_model(name, &sh, &selection, &self.memory_layout)?;
+        ensure!(
+            ChunkFlags::for_analysis(&analysis) == chunk.chunk_flags(),
+            "cannot reload {}: the new shape does not animate like the old one",
+            name
+        );
+        chunk.upload_shape_as(shape_id, name, analysis, &sh, &selection, palette, catalog)?;
+
+        for &part_id in old_chunk.shape_ids() {
+            if part_id == shape_id {
+                continue;
+            }
+            let part_name = old_chunk
+                .part(part_id)
+                .widgets()
+                .read()
+                .unwrap()
+                .name()
+                .to_owned();
+            let part_selection = match old_chunk.selection(part_id) {
+                Some(part_selection) => part_selection,
+                None => {
+                    chunk.upload_placeholder_as(part_id, &part_name);
+                    continue;
+                }
+            };
+            let sh = match self.reloaded_data.get(&part_id) {
+                Some(part_data) => RawShape::from_bytes(part_data)?,
+                None => RawShape::from_bytes(&catalog.read_name_sync(&part_name)?)?,
+            };
+            let analysis =
+                ShapeUploader::analyze_model(&part_name, &sh, part_selection, &self.memory_layout)?;
+            chunk.upload_shape_as(
+                part_id,
+                &part_name,
+                analysis,
+                &sh,
+                part_selection,
+                palette,
+                catalog,
+            )?;
+        }
+
+        let mut encoder = Self::make_upload_encoder(gpu);
+        let chunk = ClosedChunk::new(chunk, &self.layout, &self.sampler, gpu, &mut encoder)?;
+        gpu.queue_mut().submit(&[encoder.finish()]);
+        self.closed_chunks.insert(chunk_id, chunk);
+        self.reloaded_data.insert(shape_id, data.to_vec());
+        self.touch(chunk_id);
+        Ok((chunk_id, shape_id))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn upload_analyzed_shape(
+        &mut self,
+        name: &str,
+        sh: RawShape,
+        analysis: AnalysisResults,
+        selection: &DrawSelection,
+        palette: &Palette,
+        catalog: &Catalog,
+        gpu: &mut gpu::GPU,
+    ) -> Fallible<(ChunkId, ShapeId)> {
         let chunk_flags = ChunkFlags::for_analysis(&analysis);
 
         if let Some(chunk) = self.open_chunks.get(&chunk_flags) {
@@ -102,7 +411,7 @@ impl ShapeChunkBuffer {
             .open_chunks
             .get_mut(&chunk_flags)
             .expect("an open chunk")
-            .upload_shape(name, analysis, &sh, &selection, palette, catalog)?;
+            .upload_shape(name, analysis, &sh, selection, palette, catalog)?;
 
         self.name_to_shape_map.insert(name.to_owned(), shape_id);
         self.shape_to_chunk_map.insert(shape_id, chunk_id);
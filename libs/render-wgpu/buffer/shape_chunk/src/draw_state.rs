@@ -12,7 +12,7 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
-use crate::upload::{ShapeErrata, VertexFlags};
+use crate::upload::{Clock, ShapeErrata, VertexFlags};
 use animate::{Animation, LinearAnimationTemplate};
 use bitflags::bitflags;
 use failure::{bail, Fallible};
@@ -308,17 +308,17 @@ impl DrawState {
         self.wing_sweep_pos += self.wing_sweep_delta;
     }
 
-    pub fn build_mask_into(&self, start: &Instant, buffer: &mut [u32]) -> Fallible<()> {
-        let flags = self.build_mask(start)?;
+    pub fn build_mask_into(&self, clock: &Clock, buffer: &mut [u32]) -> Fallible<()> {
+        let flags = self.build_mask(clock)?;
         buffer[0] = (flags & 0xFFFF_FFFF) as u32;
         buffer[1] = (flags >> 32) as u32;
         Ok(())
     }
 
-    pub fn build_mask(&self, start: &Instant) -> Fallible<u64> {
+    pub fn build_mask(&self, clock: &Clock) -> Fallible<u64> {
         let mut mask = VertexFlags::STATIC | VertexFlags::BLEND_TEXTURE;
 
-        let elapsed = start.elapsed().as_millis() as usize;
+        let elapsed = clock.elapsed().as_millis() as usize;
         let frame_off = elapsed / ANIMATION_FRAME_TIME;
         mask |= VertexFlags::ANIM_FRAME_0_2.displacement(frame_off % 2)?;
         mask |= VertexFlags::ANIM_FRAME_0_3.displacement(frame_off % 3)?;
@@ -18,10 +18,12 @@ mod draw_state;
 mod texture_atlas;
 mod upload;
 
-pub use chunk::{ChunkId, ChunkPart, ClosedChunk, OpenChunk, ShapeId};
+pub use chunk::{ChunkId, ChunkPart, ClosedChunk, OpenChunk, ShapeId, MAX_SHAPE_VERTEX_COUNT};
 pub use chunk_manager::ShapeChunkBuffer;
 pub use draw_state::DrawState;
-pub use upload::{DrawSelection, ShapeErrata, ShapeWidgets, Vertex};
+pub use upload::{
+    flag_names, Clock, DrawSelection, MemoryLayout, ShapeErrata, ShapeWidgets, Vertex,
+};
 
 #[cfg(test)]
 mod test {
@@ -32,7 +34,7 @@ mod test {
     use lib::CatalogBuilder;
     use log::trace;
     use pal::Palette;
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet};
 
     #[test]
     fn test_load_all() -> Fallible<()> {
@@ -105,4 +107,317 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_upload_shapes_in_a_batch() -> Fallible<()> {
+        let input = InputSystem::new(vec![])?;
+        let mut gpu = GPU::new(&input, Default::default())?;
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:*.SH".to_owned()])?;
+        let label = catalog.file_label(inputs[0])?;
+        catalog.set_default_label(&label);
+        let palette = Palette::from_bytes(&catalog.read_name_sync("PALETTE.PAL")?)?;
+
+        let mut names = Vec::new();
+        for &fid in inputs.iter().take(50) {
+            names.push(catalog.stat_sync(fid)?.name);
+        }
+        let mut names = names.iter().map(|n| n.as_str()).collect::<Vec<_>>();
+        names.push(names[0]);
+
+        let mut chunk_man = ShapeChunkBuffer::new(gpu.device())?;
+        chunk_man.set_use_placeholder(true);
+        let ids = chunk_man.upload_shapes(
+            &names,
+            DrawSelection::NormalModel,
+            &palette,
+            &catalog,
+            &mut gpu,
+        )?;
+        chunk_man.finish_open_chunks(&mut gpu)?;
+
+        assert_eq!(ids.len(), names.len());
+        assert_eq!(ids.first(), ids.last());
+        let placeholder = chunk_man.placeholder_shape();
+        let mut seen = HashSet::new();
+        for (name, (_chunk_id, shape_id)) in names.iter().zip(&ids[..ids.len() - 1]) {
+            assert_eq!(chunk_man.shape_for(name)?, *shape_id);
+            assert!(chunk_man.part(*shape_id).vertex_range().end > 0);
+            if Some(*shape_id) != placeholder {
+                assert!(seen.insert(*shape_id));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_upload_reports_progress() -> Fallible<()> {
+        let input = InputSystem::new(vec![])?;
+        let mut gpu = GPU::new(&input, Default::default())?;
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let palette = Palette::from_bytes(&catalog.read_name_sync("PALETTE.PAL")?)?;
+            let mut chunk_man = ShapeChunkBuffer::new(gpu.device())?;
+            let names = ["F18.SH", "FUEL.SH", "F18.SH"];
+            let mut reports = Vec::new();
+            chunk_man.upload_shapes_with_progress(
+                &names,
+                DrawSelection::NormalModel,
+                &palette,
+                &catalog,
+                &mut gpu,
+                &mut |done, total| reports.push((done, total)),
+            )?;
+            assert_eq!(reports, vec![(1, 3), (2, 3), (3, 3)]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_finish_closes_all_chunks_in_one_batch() -> Fallible<()> {
+        let input = InputSystem::new(vec![])?;
+        let mut gpu = GPU::new(&input, Default::default())?;
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let palette = Palette::from_bytes(&catalog.read_name_sync("PALETTE.PAL")?)?;
+            let mut chunk_man = ShapeChunkBuffer::new(gpu.device())?;
+            let mut shape_ids = Vec::new();
+            for name in &["F18.SH", "FUEL.SH"] {
+                let (_, shape_id) = chunk_man.upload_shape(
+                    name,
+                    DrawSelection::NormalModel,
+                    &palette,
+                    &catalog,
+                    &mut gpu,
+                )?;
+                assert_eq!(chunk_man.closed_chunk_for_shape(shape_id), None);
+                shape_ids.push(shape_id);
+            }
+            chunk_man.finish_open_chunks(&mut gpu)?;
+            gpu.device().poll(wgpu::Maintain::Wait);
+            for shape_id in &shape_ids {
+                let chunk_id = chunk_man
+                    .closed_chunk_for_shape(*shape_id)
+                    .expect("a closed chunk");
+                assert!(chunk_man.chunk(chunk_id).vertex_count() > 0);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_reload_keeps_chunk_flags_and_earlier_reloads() -> Fallible<()> {
+        let input = InputSystem::new(vec![])?;
+        let mut gpu = GPU::new(&input, Default::default())?;
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let palette = Palette::from_bytes(&catalog.read_name_sync("PALETTE.PAL")?)?;
+            let mut chunk_man = ShapeChunkBuffer::new(gpu.device())?;
+            chunk_man.set_use_placeholder(true);
+
+            // Fill the chunk that FUEL lands in with other shapes that share its flags.
+            let mut names = vec!["F18.SH".to_owned(), "FUEL.SH".to_owned()];
+            for other in catalog
+                .find_labeled_matching(&label, "*.SH")?
+                .iter()
+                .take(40)
+            {
+                names.push(catalog.stat_sync(*other)?.name);
+            }
+            let names = names.iter().map(|n| n.as_str()).collect::<Vec<_>>();
+            chunk_man.upload_shapes(
+                &names,
+                DrawSelection::NormalModel,
+                &palette,
+                &catalog,
+                &mut gpu,
+            )?;
+            chunk_man.finish_open_chunks(&mut gpu)?;
+
+            let f18_id = chunk_man.shape_for("F18.SH")?;
+            let fuel_id = chunk_man.shape_for("FUEL.SH")?;
+            let fuel_chunk = chunk_man.closed_chunk_for_shape(fuel_id).unwrap();
+            let fuel_len = chunk_man.part(fuel_id).vertex_range().len();
+            assert_ne!(chunk_man.closed_chunk_for_shape(f18_id), Some(fuel_chunk));
+
+            // F18 animates, so it cannot stand in for FUEL.
+            let f18_data = catalog.read_name_sync("F18.SH")?.to_vec();
+            assert!(chunk_man
+                .reload_shape(
+                    "FUEL.SH",
+                    &f18_data,
+                    DrawSelection::NormalModel,
+                    &palette,
+                    &catalog,
+                    &mut gpu,
+                )
+                .is_err());
+            assert_eq!(chunk_man.part(fuel_id).vertex_range().len(), fuel_len);
+
+            // Another shape from FUEL's chunk, with a different size, can.
+            let other = names
+                .iter()
+                .find(|name| {
+                    let shape_id = chunk_man.shape_for(name).unwrap();
+                    **name != "FUEL.SH"
+                        && Some(shape_id) != chunk_man.placeholder_shape()
+                        && chunk_man.closed_chunk_for_shape(shape_id) == Some(fuel_chunk)
+                        && chunk_man.part(shape_id).vertex_range().len() != fuel_len
+                })
+                .expect("a differently sized shape in FUEL's chunk");
+            let other_data = catalog.read_name_sync(other)?.to_vec();
+            chunk_man.reload_shape(
+                "FUEL.SH",
+                &other_data,
+                DrawSelection::NormalModel,
+                &palette,
+                &catalog,
+                &mut gpu,
+            )?;
+            let other_len = chunk_man.part_for(other)?.vertex_range().len();
+            assert_eq!(chunk_man.part(fuel_id).vertex_range().len(), other_len);
+
+            // Reloading a sibling rebuilds FUEL from its reloaded bytes, not the catalog.
+            chunk_man.reload_shape(
+                other,
+                &other_data,
+                DrawSelection::NormalModel,
+                &palette,
+                &catalog,
+                &mut gpu,
+            )?;
+            assert_eq!(chunk_man.part(fuel_id).vertex_range().len(), other_len);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_evicts_unreferenced_chunks() -> Fallible<()> {
+        let input = InputSystem::new(vec![])?;
+        let mut gpu = GPU::new(&input, Default::default())?;
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let palette = Palette::from_bytes(&catalog.read_name_sync("PALETTE.PAL")?)?;
+            let mut chunk_man = ShapeChunkBuffer::new(gpu.device())?;
+            chunk_man.set_byte_budget(Some(1));
+
+            // Close each upload so that every shape gets its own chunk.
+            let mut chunk_ids = Vec::new();
+            for name in &["F18.SH", "FUEL.SH"] {
+                let (chunk_id, _) = chunk_man.upload_shape(
+                    name,
+                    DrawSelection::NormalModel,
+                    &palette,
+                    &catalog,
+                    &mut gpu,
+                )?;
+                chunk_man.finish_open_chunks(&mut gpu)?;
+                chunk_ids.push(chunk_id);
+            }
+
+            // Only FUEL is in use, so F18 goes.
+            let live = vec![chunk_ids[1]].into_iter().collect::<HashSet<_>>();
+            let evicted = chunk_man.evict_over_budget(&live);
+            assert_eq!(evicted, vec![chunk_ids[0]]);
+            assert!(chunk_man.shape_for("F18.SH").is_err());
+            assert!(chunk_man.part_for("FUEL.SH").is_ok());
+
+            // An evicted shape comes back on demand.
+            let (chunk_id, _) = chunk_man.upload_shape(
+                "F18.SH",
+                DrawSelection::NormalModel,
+                &palette,
+                &catalog,
+                &mut gpu,
+            )?;
+            assert_ne!(chunk_id, chunk_ids[0]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_placeholder_for_broken_shapes() -> Fallible<()> {
+        let input = InputSystem::new(vec![])?;
+        let mut gpu = GPU::new(&input, Default::default())?;
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let palette = Palette::from_bytes(&catalog.read_name_sync("PALETTE.PAL")?)?;
+            let mut chunk_man = ShapeChunkBuffer::new(gpu.device())?;
+            assert!(chunk_man
+                .upload_shape(
+                    "BROKEN.SH",
+                    DrawSelection::NormalModel,
+                    &palette,
+                    &catalog,
+                    &mut gpu,
+                )
+                .is_err());
+
+            chunk_man.set_use_placeholder(true);
+            let (_, broken0) = chunk_man.upload_shape(
+                "BROKEN.SH",
+                DrawSelection::NormalModel,
+                &palette,
+                &catalog,
+                &mut gpu,
+            )?;
+            let (_, broken1) = chunk_man.upload_shape(
+                "ALSO_BROKEN.SH",
+                DrawSelection::NormalModel,
+                &palette,
+                &catalog,
+                &mut gpu,
+            )?;
+            assert_eq!(chunk_man.placeholder_shape(), Some(broken0));
+            assert_eq!(broken0, broken1);
+
+            chunk_man.finish_open_chunks(&mut gpu)?;
+            assert_eq!(chunk_man.part(broken0).draw_command(0, 1).vertex_count, 12);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_draw_command_covers_part() -> Fallible<()> {
+        let input = InputSystem::new(vec![])?;
+        let mut gpu = GPU::new(&input, Default::default())?;
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let palette = Palette::from_bytes(&catalog.read_name_sync("PALETTE.PAL")?)?;
+            let mut chunk_man = ShapeChunkBuffer::new(gpu.device())?;
+            let (chunk_id, shape_id) = chunk_man.upload_shape(
+                "F18.SH",
+                DrawSelection::NormalModel,
+                &palette,
+                &catalog,
+                &mut gpu,
+            )?;
+            chunk_man.finish_open_chunks(&mut gpu)?;
+
+            let part = chunk_man.part(shape_id);
+            let cmd = part.draw_command(3, 1);
+            assert_eq!(cmd.first_vertex as usize, part.vertex_range().start);
+            assert_eq!(cmd.vertex_count as usize, part.vertex_range().len());
+            assert_eq!(cmd.vertex_count % 3, 0);
+            assert_eq!(cmd.first_instance, 3);
+            assert!(part.vertex_range().end <= chunk_man.chunk(chunk_id).vertex_count() as usize);
+
+            let [lo, hi] = part.aabb();
+            for i in 0..3 {
+                assert!(lo[i] < hi[i]);
+            }
+        }
+        Ok(())
+    }
 }
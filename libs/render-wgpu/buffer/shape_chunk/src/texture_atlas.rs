@@ -51,6 +51,10 @@ impl Frame {
         }
     }
 
+    // All facet texture coordinates go through here on their way to the GPU.
+    // The frame's corners already sit inside the 1px gutter around each
+    // texture, so raw pixel coords in [0, width] x [0, height] stay within the
+    // source image. Raw t counts up from the bottom of the image.
     pub(crate) fn tex_coord_at(&self, raw: [u16; 2]) -> [f32; 2] {
         // The raw coords are in terms of bitmap pixels, so normalize first.
         let n = TexCoord {
@@ -153,7 +157,12 @@ impl MegaAtlas {
         Ok(self.frames[name].clone())
     }
 
-    pub(crate) fn finish(self, gpu: &mut gpu::GPU) -> Fallible<wgpu::TextureView> {
+    // Queue the copies into the texture on `encoder`. They take effect when the caller submits it.
+    pub(crate) fn finish(
+        self,
+        gpu: &mut gpu::GPU,
+        encoder: &mut wgpu::CommandEncoder,
+    ) -> Fallible<wgpu::TextureView> {
         if DUMP_ATLAS {
             for (layer, buffer) in self.images.iter().enumerate() {
                 let mut img = DynamicImage::new_rgba8(ATLAS_WIDTH as u32, ATLAS_HEIGHT as u32);
@@ -176,11 +185,11 @@ impl MegaAtlas {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
             usage: wgpu::TextureUsage::all(),
         });
         let texture_view = texture.create_view(&wgpu::TextureViewDescriptor {
-            format: wgpu::TextureFormat::Rgba8Unorm,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
             dimension: wgpu::TextureViewDimension::D2Array,
             aspect: wgpu::TextureAspect::All,
             base_mip_level: 0,
@@ -189,11 +198,6 @@ impl MegaAtlas {
             array_layer_count: self.images.len() as u32,
         });
 
-        let mut encoder = gpu
-            .device()
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("shape-chunk-texture-atlas-uploader-command-encoder"),
-            });
         for (i, layer) in self.images.iter().enumerate() {
             let buffer = gpu.push_buffer(
                 "shape-chunk-texture-atlas-upload-buffer",
@@ -217,7 +221,6 @@ impl MegaAtlas {
                 extent,
             );
         }
-        gpu.queue_mut().submit(&[encoder.finish()]);
 
         Ok(texture_view)
     }
@@ -264,3 +267,21 @@ impl MegaAtlas {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tex_coord_corners_map_to_frame_corners() {
+        let frame = Frame {
+            coord0: TexCoord { s: 0.25, t: 0.5 },
+            coord1: TexCoord { s: 0.5, t: 0.75 },
+            width: 256f32,
+            height: 128f32,
+        };
+        assert_eq!(frame.tex_coord_at([0, 0]), [0.25, 0.75]);
+        assert_eq!(frame.tex_coord_at([256, 128]), [0.5, 0.5]);
+        assert_eq!(frame.tex_coord_at([128, 64]), [0.375, 0.625]);
+    }
+}
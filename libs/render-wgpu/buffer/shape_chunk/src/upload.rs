@@ -30,13 +30,74 @@ use std::{
     collections::{HashMap, HashSet},
     f32::{INFINITY, NEG_INFINITY},
     mem,
+    ops::Range,
     sync::{Arc, RwLock},
-    time::Instant,
+    time::{Duration, Instant},
 };
 use zerocopy::{AsBytes, FromBytes};
 
 const MAX_XFORM_ID: u32 = 32;
 
+// How many times wider than thick a shape must be to count as flat.
+const THIN_SHAPE_RATIO: f32 = 64f32;
+
+// Where we pretend the per-object instance struct lives when a shape reads
+// brentObjId, unless told otherwise.
+const INSTANCE_BASE: u32 = 0x0006_0000;
+
+// Where the interpreters that run shape code find memory that is not part of the
+// shape itself. The code is always at SHAPE_LOAD_BASE, as RawShape relocates it
+// there when parsing.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MemoryLayout {
+    // The per-object instance struct that brentObjId points at.
+    pub instance_base: u32,
+}
+
+impl Default for MemoryLayout {
+    fn default() -> Self {
+        Self {
+            instance_base: INSTANCE_BASE,
+        }
+    }
+}
+
+impl MemoryLayout {
+    pub const INSTANCE_SIZE: usize = 0x100;
+
+    pub fn new(instance_base: u32) -> Self {
+        Self { instance_base }
+    }
+
+    pub fn instance_range(&self) -> Range<u32> {
+        self.instance_base..self.instance_base + Self::INSTANCE_SIZE as u32
+    }
+
+    pub fn code_range(sh: &RawShape) -> Range<u32> {
+        SHAPE_LOAD_BASE..SHAPE_LOAD_BASE + sh.pe.code.len() as u32
+    }
+
+    fn check(&self, sh: &RawShape) -> Fallible<()> {
+        let code = Self::code_range(sh);
+        let instance = self.instance_range();
+        ensure!(
+            instance.end <= code.start || code.end <= instance.start,
+            "instance struct at {:08X} overlaps shape code at {:08X}",
+            instance.start,
+            code.start
+        );
+        Ok(())
+    }
+
+    fn map_instance(&self, interp: &mut Interpreter, sh: &RawShape) -> Fallible<()> {
+        interp.map_writable(self.instance_base, vec![0u8; Self::INSTANCE_SIZE])?;
+        if let Ok(brent_obj_id) = sh.lookup_trampoline_by_name("brentObjId") {
+            interp.map_value(brent_obj_id.mem_location, self.instance_base);
+        }
+        Ok(())
+    }
+}
+
 bitflags! {
     pub struct VertexFlags: u64 {
         const NONE                 = 0x0000_0000_0000_0000;
@@ -124,6 +185,72 @@ impl VertexFlags {
     }
 }
 
+const VERTEX_FLAG_NAMES: [(VertexFlags, &str); 53] = [
+    (VertexFlags::BLEND_TEXTURE, "BLEND_TEXTURE"),
+    (VertexFlags::STATIC, "STATIC"),
+    (VertexFlags::AFTERBURNER_ON, "AFTERBURNER_ON"),
+    (VertexFlags::AFTERBURNER_OFF, "AFTERBURNER_OFF"),
+    (VertexFlags::RIGHT_FLAP_DOWN, "RIGHT_FLAP_DOWN"),
+    (VertexFlags::RIGHT_FLAP_UP, "RIGHT_FLAP_UP"),
+    (VertexFlags::LEFT_FLAP_DOWN, "LEFT_FLAP_DOWN"),
+    (VertexFlags::LEFT_FLAP_UP, "LEFT_FLAP_UP"),
+    (VertexFlags::HOOK_EXTENDED, "HOOK_EXTENDED"),
+    (VertexFlags::HOOK_RETRACTED, "HOOK_RETRACTED"),
+    (VertexFlags::GEAR_UP, "GEAR_UP"),
+    (VertexFlags::GEAR_DOWN, "GEAR_DOWN"),
+    (VertexFlags::BRAKE_EXTENDED, "BRAKE_EXTENDED"),
+    (VertexFlags::BRAKE_RETRACTED, "BRAKE_RETRACTED"),
+    (VertexFlags::BAY_CLOSED, "BAY_CLOSED"),
+    (VertexFlags::BAY_OPEN, "BAY_OPEN"),
+    (VertexFlags::RUDDER_CENTER, "RUDDER_CENTER"),
+    (VertexFlags::RUDDER_LEFT, "RUDDER_LEFT"),
+    (VertexFlags::RUDDER_RIGHT, "RUDDER_RIGHT"),
+    (VertexFlags::LEFT_AILERON_CENTER, "LEFT_AILERON_CENTER"),
+    (VertexFlags::LEFT_AILERON_UP, "LEFT_AILERON_UP"),
+    (VertexFlags::LEFT_AILERON_DOWN, "LEFT_AILERON_DOWN"),
+    (VertexFlags::RIGHT_AILERON_CENTER, "RIGHT_AILERON_CENTER"),
+    (VertexFlags::RIGHT_AILERON_UP, "RIGHT_AILERON_UP"),
+    (VertexFlags::RIGHT_AILERON_DOWN, "RIGHT_AILERON_DOWN"),
+    (VertexFlags::SLATS_DOWN, "SLATS_DOWN"),
+    (VertexFlags::SLATS_UP, "SLATS_UP"),
+    (VertexFlags::PLAYER_ALIVE, "PLAYER_ALIVE"),
+    (VertexFlags::PLAYER_DEAD, "PLAYER_DEAD"),
+    (VertexFlags::ANIM_FRAME_0_2, "ANIM_FRAME_0_2"),
+    (VertexFlags::ANIM_FRAME_1_2, "ANIM_FRAME_1_2"),
+    (VertexFlags::ANIM_FRAME_0_3, "ANIM_FRAME_0_3"),
+    (VertexFlags::ANIM_FRAME_1_3, "ANIM_FRAME_1_3"),
+    (VertexFlags::ANIM_FRAME_2_3, "ANIM_FRAME_2_3"),
+    (VertexFlags::ANIM_FRAME_0_4, "ANIM_FRAME_0_4"),
+    (VertexFlags::ANIM_FRAME_1_4, "ANIM_FRAME_1_4"),
+    (VertexFlags::ANIM_FRAME_2_4, "ANIM_FRAME_2_4"),
+    (VertexFlags::ANIM_FRAME_3_4, "ANIM_FRAME_3_4"),
+    (VertexFlags::ANIM_FRAME_0_6, "ANIM_FRAME_0_6"),
+    (VertexFlags::ANIM_FRAME_1_6, "ANIM_FRAME_1_6"),
+    (VertexFlags::ANIM_FRAME_2_6, "ANIM_FRAME_2_6"),
+    (VertexFlags::ANIM_FRAME_3_6, "ANIM_FRAME_3_6"),
+    (VertexFlags::ANIM_FRAME_4_6, "ANIM_FRAME_4_6"),
+    (VertexFlags::ANIM_FRAME_5_6, "ANIM_FRAME_5_6"),
+    (VertexFlags::SAM_COUNT_0, "SAM_COUNT_0"),
+    (VertexFlags::SAM_COUNT_1, "SAM_COUNT_1"),
+    (VertexFlags::SAM_COUNT_2, "SAM_COUNT_2"),
+    (VertexFlags::SAM_COUNT_3, "SAM_COUNT_3"),
+    (VertexFlags::EJECT_STATE_0, "EJECT_STATE_0"),
+    (VertexFlags::EJECT_STATE_1, "EJECT_STATE_1"),
+    (VertexFlags::EJECT_STATE_2, "EJECT_STATE_2"),
+    (VertexFlags::EJECT_STATE_3, "EJECT_STATE_3"),
+    (VertexFlags::EJECT_STATE_4, "EJECT_STATE_4"),
+];
+
+// Takes flags in the same [low, high] u32 pair layout that we upload to the GPU.
+pub fn flag_names(flags: [u32; 2]) -> Vec<&'static str> {
+    let bits = u64::from(flags[0]) | (u64::from(flags[1]) << 32);
+    VERTEX_FLAG_NAMES
+        .iter()
+        .filter(|(flag, _)| bits & flag.bits() != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
 #[repr(C)]
 #[derive(AsBytes, FromBytes, Copy, Clone, Debug)]
 pub struct Vertex {
@@ -223,16 +350,53 @@ impl Default for Vertex {
     }
 }
 
-#[derive(Clone, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum DrawSelection {
     DamageModel,
     NormalModel,
+
+    // Skip x86 interpretation entirely and follow no conditional jumps, so that
+    // every vertex buffer and facet in the shape is uploaded as static geometry.
+    // This is nonsensical to look at, but works even for shapes that the
+    // interpreter cannot handle.
+    AllGeometry,
 }
 
 impl DrawSelection {
     pub fn is_damage(&self) -> bool {
         self == &DrawSelection::DamageModel
     }
+
+    pub fn interprets_code(&self) -> bool {
+        self != &DrawSelection::AllGeometry
+    }
+}
+
+// Where shape code reading _currentTicks gets its time from.
+#[derive(Clone, Copy, Debug)]
+pub enum Clock {
+    // Ticks of wall time since start, as the game counts them.
+    Real { start: Instant, now: Instant },
+    // A constant tick count, so that a specific animation frame can be drawn
+    // reproducibly.
+    Fixed(u32),
+}
+
+impl Clock {
+    pub fn current_ticks(&self) -> u32 {
+        match self {
+            Clock::Real { start, now } => (((*now - *start).as_millis() as u32) >> 4) & 0x0FFF,
+            Clock::Fixed(ticks) => *ticks,
+        }
+    }
+
+    // Wall time since start, or the time that a fixed tick count stands for.
+    pub fn elapsed(&self) -> Duration {
+        match self {
+            Clock::Real { start, now } => *now - *start,
+            Clock::Fixed(ticks) => Duration::from_millis(u64::from(*ticks) << 4),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -255,9 +419,32 @@ pub struct ShapeErrata {
     pub has_frame_animation: bool,
     pub has_xform_animation: bool,
     pub num_xform_animations: u8,
+    // Flat or paper-thin shapes (decals, runways, signs) are single sided and
+    // vanish from behind under backface culling, so draw them without it.
+    pub no_cull: bool,
 }
 
 impl ShapeErrata {
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if self.no_upper_aileron {
+            parts.push("no_upper_aileron".to_owned());
+        }
+        if self.has_frame_animation {
+            parts.push("frame_animation".to_owned());
+        }
+        if self.has_xform_animation {
+            parts.push(format!("xform_animation({})", self.num_xform_animations));
+        }
+        if self.no_cull {
+            parts.push("no_cull".to_owned());
+        }
+        if parts.is_empty() {
+            return "none".to_owned();
+        }
+        parts.join(", ")
+    }
+
     fn from_flags(analysis: &AnalysisResults) -> Self {
         let flags = analysis.prop_man.seen_flags;
         Self {
@@ -267,8 +454,21 @@ impl ShapeErrata {
             has_frame_animation: analysis.has_frame_animation,
             has_xform_animation: !analysis.transformers.is_empty(),
             num_xform_animations: analysis.transformers.len() as u8,
+            no_cull: false,
         }
     }
+
+    // A shape is thin if its smallest extent is tiny next to its largest.
+    fn is_thin(aabb_min: &[f32; 3], aabb_max: &[f32; 3]) -> bool {
+        let extents = [
+            aabb_max[0] - aabb_min[0],
+            aabb_max[1] - aabb_min[1],
+            aabb_max[2] - aabb_min[2],
+        ];
+        let thinnest = extents.iter().cloned().fold(INFINITY, f32::min);
+        let widest = extents.iter().cloned().fold(NEG_INFINITY, f32::max);
+        widest > 0f32 && thinnest * THIN_SHAPE_RATIO <= widest
+    }
 }
 
 // TODO: this should be a sibling of ShapeUploader in such a way that they can share
@@ -473,9 +673,16 @@ impl ProgramCounter {
         &sh.instrs[self.instr_offset.wrapping_add(offset as usize)]
     }
 
+    // Use the stored offset of the next instruction rather than summing sizes, so
+    // that we stay in sync with bytes_to_index if the parse ever has gaps.
     fn advance(&mut self, sh: &RawShape) {
-        self.byte_offset += self.current_instr(sh).size();
+        let end_offset = self.byte_offset + self.current_instr(sh).size();
         self.instr_offset += 1;
+        self.byte_offset = sh
+            .instrs
+            .get(self.instr_offset)
+            .map(|instr| instr.at_offset())
+            .unwrap_or(end_offset);
     }
 }
 
@@ -559,12 +766,7 @@ impl Transformer {
         self.xform_id as usize
     }
 
-    pub fn transform(
-        &mut self,
-        draw_state: &DrawState,
-        start: &Instant,
-        now: &Instant,
-    ) -> Fallible<[f32; 6]> {
+    pub fn transform(&mut self, draw_state: &DrawState, clock: &Clock) -> Fallible<[f32; 6]> {
         fn fa2r(d: f32) -> f32 {
             d * std::f32::consts::PI / 8192f32
         }
@@ -572,9 +774,7 @@ impl Transformer {
         let vm = &mut self.vm;
         for input in &self.inputs {
             let (loc, value) = match input {
-                TransformInput::CurrentTicks(loc) => {
-                    (*loc, (((*now - *start).as_millis() as u32) >> 4) & 0x0FFF)
-                }
+                TransformInput::CurrentTicks(loc) => (*loc, clock.current_ticks()),
                 TransformInput::GearPosition(loc) => (*loc, draw_state.x86_gear_position()),
                 TransformInput::GearDown(loc) => (*loc, draw_state.x86_gear_down()),
                 TransformInput::BayPosition(loc) => (*loc, draw_state.x86_bay_position()),
@@ -657,13 +857,12 @@ impl ShapeWidgets {
     pub fn animate_into(
         &mut self,
         draw_state: &DrawState,
-        start: &Instant,
-        now: &Instant,
+        clock: &Clock,
         buffer: &mut [[f32; 6]],
     ) -> Fallible<()> {
         assert!(buffer.len() >= self.num_xforms());
         for (offset, transformer) in self.transformers.iter_mut().enumerate() {
-            let xform = transformer.transform(draw_state, start, now)?;
+            let xform = transformer.transform(draw_state, clock)?;
             buffer[offset].copy_from_slice(&xform);
         }
         Ok(())
@@ -809,7 +1008,7 @@ impl<'a> ShapeUploader<'a> {
                     v.flags0 = (flags.bits() & 0xFFFF_FFFF) as u32;
                     v.flags1 = (flags.bits() >> 32) as u32;
                 }
-                v.color = self.palette.rgba_f32(facet.color as usize)?;
+                v.color = self.palette.rgba_f32_srgb(facet.color as usize)?;
                 if facet.flags.contains(FacetFlags::FILL_BACKGROUND)
                     || facet.flags.contains(FacetFlags::UNK1)
                     || facet.flags.contains(FacetFlags::UNK5)
@@ -876,17 +1075,26 @@ impl<'a> ShapeUploader<'a> {
         pc: &ProgramCounter,
         x86: &X86Code,
         sh: &RawShape,
+        layout: &MemoryLayout,
         prop_man: &mut BufferPropsManager,
         transformers: &mut Vec<Transformer>,
     ) -> Fallible<()> {
         let next_instr = pc.relative_instr(1, sh);
 
         if next_instr.magic() == "Unmask" {
-            return Self::handle_unmask_property(pc, x86, sh, prop_man);
+            return Self::handle_unmask_property(pc, x86, sh, layout, prop_man);
         }
 
         if next_instr.magic() == "XformUnmask" {
-            return Self::handle_transformer_property(name, pc, x86, sh, prop_man, transformers);
+            return Self::handle_transformer_property(
+                name,
+                pc,
+                x86,
+                sh,
+                layout,
+                prop_man,
+                transformers,
+            );
         }
 
         // TODO: figure out what is landing here and see how important it is.
@@ -898,6 +1106,7 @@ impl<'a> ShapeUploader<'a> {
         pc: &ProgramCounter,
         x86: &X86Code,
         sh: &RawShape,
+        layout: &MemoryLayout,
         prop_man: &mut BufferPropsManager,
     ) -> Fallible<()> {
         let memrefs = Self::find_external_references(x86, sh);
@@ -907,7 +1116,7 @@ impl<'a> ShapeUploader<'a> {
         );
         let (&name, trampoline) = memrefs.iter().next().expect("checked next");
         if TOGGLE_TABLE.contains_key(name) {
-            Self::update_buffer_properties_for_toggle(trampoline, pc, x86, sh, prop_man)?;
+            Self::update_buffer_properties_for_toggle(trampoline, pc, x86, sh, layout, prop_man)?;
         } else if name == "brentObjId" {
             let callrefs = Self::find_external_calls(x86, sh)?;
             ensure!(callrefs.len() == 2, "expected one call");
@@ -919,7 +1128,9 @@ impl<'a> ShapeUploader<'a> {
                 callrefs.contains_key("@HARDNumLoaded@8"),
                 "expected call to @HARDNumLoaded@8"
             );
-            Self::update_buffer_properties_for_num_loaded(trampoline, pc, x86, sh, prop_man)?;
+            Self::update_buffer_properties_for_num_loaded(
+                trampoline, pc, x86, sh, layout, prop_man,
+            )?;
         } else {
             bail!("unknown memory read: {}", name)
         }
@@ -931,6 +1142,7 @@ impl<'a> ShapeUploader<'a> {
         pc: &ProgramCounter,
         x86: &X86Code,
         sh: &RawShape,
+        layout: &MemoryLayout,
         prop_man: &mut BufferPropsManager,
     ) -> Fallible<()> {
         let unmask = pc.relative_instr(1, sh);
@@ -941,6 +1153,7 @@ impl<'a> ShapeUploader<'a> {
         let mut interp = i386::Interpreter::new();
         interp.add_code(x86.bytecode.clone());
         interp.add_code(trailer.unwrap_x86()?.bytecode.clone());
+        layout.map_instance(&mut interp, sh)?;
         let do_start_interp = sh.lookup_trampoline_by_name("do_start_interp")?;
         interp.add_trampoline(do_start_interp.mem_location, &do_start_interp.name, 1);
 
@@ -968,6 +1181,7 @@ impl<'a> ShapeUploader<'a> {
         pc: &ProgramCounter,
         x86: &X86Code,
         sh: &RawShape,
+        layout: &MemoryLayout,
         prop_man: &mut BufferPropsManager,
     ) -> Fallible<()> {
         ensure!(
@@ -983,20 +1197,15 @@ impl<'a> ShapeUploader<'a> {
         let mut interp = i386::Interpreter::new();
         interp.add_code(x86.bytecode.clone());
         interp.add_code(trailer.unwrap_x86()?.bytecode.clone());
-        interp.map_value(brent_obj_id.mem_location, 0x60000);
+        layout.map_instance(&mut interp, sh)?;
         let do_start_interp = sh.lookup_trampoline_by_name("do_start_interp")?;
         interp.add_trampoline(do_start_interp.mem_location, &do_start_interp.name, 1);
         let num_loaded = sh.lookup_trampoline_by_name("@HARDNumLoaded@8")?;
         interp.add_trampoline(num_loaded.mem_location, &num_loaded.name, 1);
 
         for &(value, flags) in &TOGGLE_TABLE["_SAMcount"] {
-            let exit_info = interp.interpret(x86.code_offset(0xAA00_0000u32))?;
-            let (name, args) = exit_info.ok_trampoline()?;
-            ensure!(name == "@HARDNumLoaded@8", "unexpected num_loaded request");
-            ensure!(args.len() == 1, "unexpected arg count");
-            interp.set_register_value(i386::Reg::EAX, value);
-
-            let exit_info = interp.interpret(interp.eip())?;
+            interp.register_return_value(&num_loaded.name, Box::new(move || value));
+            let exit_info = interp.interpret(x86.code_offset(SHAPE_LOAD_BASE))?;
             let (name, args) = exit_info.ok_trampoline()?;
             ensure!(name == "do_start_interp", "unexpected trampoline return");
             ensure!(args.len() == 1, "unexpected arg count");
@@ -1016,6 +1225,7 @@ impl<'a> ShapeUploader<'a> {
         pc: &ProgramCounter,
         x86: &X86Code,
         sh: &RawShape,
+        layout: &MemoryLayout,
         prop_man: &mut BufferPropsManager,
         transformers: &mut Vec<Transformer>,
     ) -> Fallible<()> {
@@ -1028,6 +1238,7 @@ impl<'a> ShapeUploader<'a> {
         if let Instr::X86Code(trailer) = maybe_trailer {
             interp.add_code(trailer.bytecode.clone());
         }
+        layout.map_instance(&mut interp, sh)?;
         let do_start_interp = sh.lookup_trampoline_by_name("do_start_interp")?;
         interp.add_trampoline(do_start_interp.mem_location, &do_start_interp.name, 1);
 
@@ -1197,10 +1408,13 @@ impl<'a> ShapeUploader<'a> {
         let mut verts = Vec::new();
         mem::swap(&mut verts, &mut self.vertices);
 
+        let mut errata = ShapeErrata::from_flags(&analysis);
+        errata.no_cull = ShapeErrata::is_thin(&self.aabb_min, &self.aabb_max);
+
         Ok((
             Arc::new(RwLock::new(ShapeWidgets::new(
                 self.name,
-                ShapeErrata::from_flags(&analysis),
+                errata,
                 analysis.transformers,
                 [self.aabb_min, self.aabb_max],
             ))),
@@ -1208,23 +1422,56 @@ impl<'a> ShapeUploader<'a> {
         ))
     }
 
+    // A magenta tetrahedron to stand in for shapes that failed to load.
+    pub(crate) fn placeholder_model(name: &str) -> (Arc<RwLock<ShapeWidgets>>, Vec<Vertex>) {
+        const S: f32 = 8f32;
+        let corners = [[S, S, S], [S, -S, -S], [-S, S, -S], [-S, -S, S]];
+        let faces = [[0, 1, 2], [0, 3, 1], [0, 2, 3], [1, 3, 2]];
+        let flags = VertexFlags::STATIC.bits();
+        let mut verts = Vec::with_capacity(faces.len() * 3);
+        for face in &faces {
+            for &i in face {
+                verts.push(Vertex {
+                    position: corners[i],
+                    color: [1f32, 0f32, 1f32, 1f32],
+                    tex_coord: [0f32, 0f32],
+                    flags0: (flags & 0xFFFF_FFFF) as u32,
+                    flags1: (flags >> 32) as u32,
+                    xform_id: MAX_XFORM_ID,
+                });
+            }
+        }
+        let errata = ShapeErrata {
+            no_upper_aileron: false,
+            has_frame_animation: false,
+            has_xform_animation: false,
+            num_xform_animations: 0,
+            no_cull: false,
+        };
+        let widgets = ShapeWidgets::new(name, errata, Vec::new(), [[-S; 3], [S; 3]]);
+        (Arc::new(RwLock::new(widgets)), verts)
+    }
+
     pub(crate) fn analyze_model(
         name: &str,
         sh: &RawShape,
         selection: &DrawSelection,
+        layout: &MemoryLayout,
     ) -> Fallible<AnalysisResults> {
+        layout.check(sh)?;
         let mut result: AnalysisResults = Default::default();
         let mut callback = |pc: &ProgramCounter, instr: &Instr| {
             match instr {
                 Instr::JumpToFrame(_) => {
                     result.has_frame_animation = true;
                 }
-                Instr::X86Code(ref x86) => {
+                Instr::X86Code(ref x86) if selection.interprets_code() => {
                     Self::maybe_update_buffer_properties(
                         name,
                         &pc,
                         x86,
                         sh,
+                        layout,
                         &mut result.prop_man,
                         &mut result.transformers,
                     )?;
@@ -1261,7 +1508,7 @@ impl<'a> ShapeUploader<'a> {
         let mut pc = ProgramCounter::new(sh.instrs.len());
         while pc.valid() {
             if let Some(byte_offset) = damage_model_byte_offset {
-                if pc.matches_byte(byte_offset) && *selection != DrawSelection::DamageModel {
+                if pc.matches_byte(byte_offset) && *selection == DrawSelection::NormalModel {
                     pc.set_byte_offset(end_byte_offset.unwrap(), sh)?;
                 }
             }
@@ -1278,7 +1525,11 @@ impl<'a> ShapeUploader<'a> {
             match instr {
                 Instr::Header(_) => {}
                 Instr::PtrToObjEnd(end) => end_byte_offset = Some(end.end_byte_offset()),
-                Instr::EndOfObject(_end) => break,
+                Instr::EndOfObject(_end) => {
+                    if selection.interprets_code() {
+                        break;
+                    }
+                }
 
                 Instr::Jump(jump) => {
                     pc.set_byte_offset(jump.target_byte_offset(), sh)?;
@@ -1291,10 +1542,10 @@ impl<'a> ShapeUploader<'a> {
                         continue;
                     }
                 }
-                Instr::JumpToDetail(detail) => {
+                Instr::JumpToDetail(detail) if selection.interprets_code() => {
                     section_close_byte_offset = Some(detail.target_byte_offset());
                 }
-                Instr::JumpToLOD(lod) => {
+                Instr::JumpToLOD(lod) if selection.interprets_code() => {
                     section_close_byte_offset = Some(lod.target_byte_offset());
                 }
                 Instr::JumpToFrame(frame) => {
@@ -1314,9 +1565,204 @@ impl<'a> ShapeUploader<'a> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use lib::CatalogBuilder;
 
     #[test]
     fn test_vertex_offsets() {
         let _ = Vertex::descriptor();
     }
+
+    #[test]
+    fn test_all_geometry_skips_interpretation() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let palette = Palette::from_bytes(&catalog.read_name_sync("PALETTE.PAL")?)?;
+            let sh = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+
+            let mut counts = Vec::new();
+            for selection in &[DrawSelection::NormalModel, DrawSelection::AllGeometry] {
+                let analysis =
+                    ShapeUploader::analyze_model("F18.SH", &sh, selection, &Default::default())?;
+                let mut atlas = MegaAtlas::new()?;
+                let (_, verts) = ShapeUploader::new("F18.SH", &palette, &catalog)
+                    .draw_model(&sh, analysis, selection, &mut atlas)?;
+                counts.push(verts.len());
+            }
+            assert!(counts[1] > 0);
+            assert!(counts[1] >= counts[0]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_layout_keeps_instance_clear_of_code() -> Fallible<()> {
+        let (mut catalog, inputs) =
+            CatalogBuilder::build_and_select(&["*:F18.SH".to_owned(), "*:T80.SH".to_owned()])?;
+        let selection = DrawSelection::NormalModel;
+        let mut instances = Vec::new();
+        for (i, &fid) in inputs.iter().enumerate() {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let name = catalog.stat_sync(fid)?.name;
+            let sh = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            let layout =
+                MemoryLayout::new(INSTANCE_BASE + (i * MemoryLayout::INSTANCE_SIZE) as u32);
+            let analysis = ShapeUploader::analyze_model(&name, &sh, &selection, &layout)?;
+
+            let code = MemoryLayout::code_range(&sh);
+            let instance = layout.instance_range();
+            assert!(instance.end <= code.start || code.end <= instance.start);
+            for transformer in &analysis.transformers {
+                assert!(transformer
+                    .vm
+                    .dump_mapped_regions()
+                    .contains(&(layout.instance_base, MemoryLayout::INSTANCE_SIZE)));
+            }
+            instances.push(instance);
+
+            // An instance struct on top of the code is refused.
+            let bad_layout = MemoryLayout::new(SHAPE_LOAD_BASE);
+            assert!(ShapeUploader::analyze_model(&name, &sh, &selection, &bad_layout).is_err());
+        }
+        for (i, a) in instances.iter().enumerate() {
+            for b in &instances[i + 1..] {
+                assert!(a.end <= b.start || b.end <= a.start);
+            }
+        }
+        Ok(())
+    }
+
+    fn is_tick_driven(transformer: &Transformer) -> bool {
+        transformer.inputs.iter().any(|input| {
+            if let TransformInput::CurrentTicks(_) = input {
+                true
+            } else {
+                false
+            }
+        })
+    }
+
+    #[test]
+    fn test_fixed_clock_animates_deterministically() -> Fallible<()> {
+        // Pin the test to the first shape, by name, whose xforms are driven
+        // by _currentTicks.
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:*.SH".to_owned()])?;
+        let mut candidates = Vec::new();
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            candidates.push((catalog.stat_sync(fid)?.name, label, fid));
+        }
+        candidates.sort();
+        let selection = DrawSelection::NormalModel;
+        let mut pinned = None;
+        for (name, label, fid) in candidates {
+            let sh = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            if let Ok(analysis) =
+                ShapeUploader::analyze_model(&name, &sh, &selection, &Default::default())
+            {
+                if analysis.transformers.iter().any(is_tick_driven) {
+                    pinned = Some((name, label, sh, analysis));
+                    break;
+                }
+            }
+        }
+        let (name, label, sh, analysis) = pinned.expect("a shape animated by _currentTicks");
+        catalog.set_default_label(&label);
+        let palette = Palette::from_bytes(&catalog.read_name_sync("PALETTE.PAL")?)?;
+        let tick_driven = analysis
+            .transformers
+            .iter()
+            .map(is_tick_driven)
+            .collect::<Vec<bool>>();
+
+        let mut atlas = MegaAtlas::new()?;
+        let (widgets, _) = ShapeUploader::new(&name, &palette, &catalog)
+            .draw_model(&sh, analysis, &selection, &mut atlas)?;
+        let mut widgets = widgets.read().unwrap().clone();
+        assert_eq!(widgets.num_xforms(), tick_driven.len());
+        let draw_state = DrawState::new(widgets.errata());
+
+        let start = Instant::now();
+        let clocks = [
+            Clock::Fixed(0),
+            Clock::Fixed(0x400),
+            Clock::Fixed(0),
+            Clock::Real {
+                start,
+                now: start + Duration::from_millis(0x400 << 4),
+            },
+        ];
+        let mut frames = Vec::new();
+        for clock in &clocks {
+            let mut buffer = vec![[0f32; 6]; widgets.num_xforms()];
+            widgets.animate_into(&draw_state, clock, &mut buffer)?;
+            frames.push(buffer);
+        }
+
+        // The same tick count always gives the same frame, whichever clock it
+        // comes from.
+        assert_eq!(frames[0], frames[2], "{} is not repeatable", name);
+        assert_eq!(
+            frames[1], frames[3],
+            "{} real and fixed clocks differ",
+            name
+        );
+
+        // Only the xforms that read _currentTicks move with the clock.
+        for (i, &ticks) in tick_driven.iter().enumerate() {
+            if !ticks {
+                assert_eq!(frames[0][i], frames[1][i], "{} xform {} moved", name, i);
+            }
+        }
+        assert!(
+            tick_driven
+                .iter()
+                .enumerate()
+                .any(|(i, &ticks)| ticks && frames[0][i] != frames[1][i]),
+            "{} did not move between ticks 0 and 0x400",
+            name
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_clock_elapsed() {
+        let start = Instant::now();
+        let real = Clock::Real {
+            start,
+            now: start + Duration::from_millis(0x123 << 4),
+        };
+        assert_eq!(real.current_ticks(), 0x123);
+        assert_eq!(Clock::Fixed(0x123).current_ticks(), 0x123);
+        assert_eq!(Clock::Fixed(0x123).elapsed(), real.elapsed());
+    }
+
+    #[test]
+    fn test_flag_names() {
+        let flags = VertexFlags::STATIC | VertexFlags::GEAR_DOWN | VertexFlags::SAM_COUNT_3;
+        let bits = flags.bits();
+        let names = flag_names([(bits & 0xFFFF_FFFF) as u32, (bits >> 32) as u32]);
+        assert_eq!(names, vec!["STATIC", "GEAR_DOWN", "SAM_COUNT_3"]);
+
+        let errata = ShapeErrata {
+            no_upper_aileron: true,
+            has_frame_animation: false,
+            has_xform_animation: true,
+            num_xform_animations: 2,
+            no_cull: false,
+        };
+        let widgets = Arc::new(RwLock::new(ShapeWidgets::new(
+            "F22.SH",
+            errata,
+            Vec::new(),
+            [[0f32; 3]; 2],
+        )));
+        let part = crate::ChunkPart::new(0, 3, widgets);
+        assert_eq!(
+            part.describe_errata(),
+            "F22.SH: no_upper_aileron, xform_animation(2)"
+        );
+    }
 }
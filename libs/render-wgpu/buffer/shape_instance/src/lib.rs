@@ -15,7 +15,8 @@
 mod components;
 
 pub use components::*;
-pub use shape_chunk::{DrawSelection, DrawState};
+pub use sh::SHAPE_UNIT_TO_FEET;
+pub use shape_chunk::{Clock, DrawSelection, DrawState};
 
 use catalog::Catalog;
 use failure::Fallible;
@@ -26,24 +27,29 @@ use log::trace;
 use pal::Palette;
 use shape_chunk::{ChunkId, ChunkPart, ShapeChunkBuffer, ShapeErrata, ShapeId, ShapeWidgets};
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     collections::{hash_map::Entry, HashMap},
     mem,
-    sync::Arc,
-    time::Instant,
+    ops::Range,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
 use universe::component::{Rotation, Scale, Transform};
 
-pub const SHAPE_UNIT_TO_FEET: f32 = 4f32;
-
 const BLOCK_SIZE: usize = 1 << 10;
 
 type TransformType = [f32; 8];
 
 thread_local! {
     pub static WIDGET_CACHE: RefCell<HashMap<ShapeId, ShapeWidgets>> = RefCell::new(HashMap::new());
+    static WIDGET_CACHE_GENERATION: Cell<usize> = Cell::new(0);
 }
 
+// Bumped when a shape is reloaded, so that every thread drops its cached widgets.
+static WIDGET_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct BlockId(u32);
 
@@ -69,6 +75,65 @@ impl SlotId {
     }
 }
 
+// Tracks the span of a scratch buffer that changed since the last upload.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct DirtyRange {
+    range: Option<Range<usize>>,
+
+    // Set until the first upload. wgpu does not zero new buffers, so the whole
+    // scratch has to go up once before unchanged values can be skipped.
+    initial: bool,
+}
+
+impl DirtyRange {
+    fn all(len: usize) -> Self {
+        Self {
+            range: Some(0..len),
+            initial: true,
+        }
+    }
+
+    fn mark(&mut self, index: usize) {
+        self.mark_range(index..index + 1);
+    }
+
+    fn mark_range(&mut self, range: Range<usize>) {
+        if range.start >= range.end {
+            return;
+        }
+        self.range = Some(match self.range.take() {
+            Some(prior) => prior.start.min(range.start)..prior.end.max(range.end),
+            None => range,
+        });
+    }
+
+    // The dirty span, clipped to the live part of the scratch. Only this span gets
+    // copied, to the same element offset in the GPU buffer. The first upload is
+    // never clipped, so that slots allocated later start out matching the scratch.
+    fn take_upload_range(&mut self, len: usize) -> Option<Range<usize>> {
+        let r = self.range.take()?;
+        if mem::replace(&mut self.initial, false) {
+            return Some(r);
+        }
+        let end = r.end.min(len);
+        if r.start < end {
+            Some(r.start..end)
+        } else {
+            None
+        }
+    }
+}
+
+// The span of each scratch buffer in a block to copy up this frame.
+#[derive(Debug)]
+struct UploadRanges {
+    command: Option<Range<usize>>,
+    transform: Option<Range<usize>>,
+    flag: Option<Range<usize>>,
+    xform_index: Option<Range<usize>>,
+    xform: Option<Range<usize>>,
+}
+
 // Fixed reservation blocks for upload of a number of entities. Unfortunately, because of
 // xforms, we don't know exactly how many instances will fit in any given block.
 pub struct InstanceBlock {
@@ -78,6 +143,10 @@ pub struct InstanceBlock {
     // Weak reference to the associated chunk in the Manager.
     chunk_id: ChunkId,
 
+    // Whether the shapes in this block draw without backface culling. Blocks
+    // never mix the two so that each can be drawn with a single pipeline.
+    no_cull: bool,
+
     // Current allocation head.
     next_slot: u32,
 
@@ -85,8 +154,15 @@ pub struct InstanceBlock {
     // know the actual offset into the relevant buffer.
     slot_map: Box<[usize; BLOCK_SIZE]>,
 
-    // Flag set whenever the slot map changes and we need to re-up the command_buffer.
-    slots_dirty: bool,
+    // The shape drawn by each slot, so that draw commands can be rebuilt on reload.
+    slot_shapes: Vec<ShapeId>,
+
+    // Changed spans of each scratch buffer that need to be re-uploaded.
+    command_dirty: DirtyRange,
+    transform_dirty: DirtyRange,
+    flag_dirty: DirtyRange,
+    xform_index_dirty: DirtyRange,
+    xform_dirty: DirtyRange,
 
     // Cursor for insertion into the xforms buffer.
     xform_cursor: usize,
@@ -115,6 +191,7 @@ impl InstanceBlock {
     fn new(
         block_id: BlockId,
         chunk_id: ChunkId,
+        no_cull: bool,
         layout: &wgpu::BindGroupLayout,
         device: &wgpu::Device,
     ) -> Fallible<Self> {
@@ -201,9 +278,16 @@ impl InstanceBlock {
             block_id,
             next_slot: 0,
             slot_map: Box::new([0; BLOCK_SIZE]),
-            slots_dirty: false,
+            slot_shapes: Vec::with_capacity(BLOCK_SIZE),
+            // New blocks need a full upload once.
+            command_dirty: DirtyRange::all(BLOCK_SIZE),
+            transform_dirty: DirtyRange::all(BLOCK_SIZE),
+            flag_dirty: DirtyRange::all(BLOCK_SIZE),
+            xform_index_dirty: DirtyRange::all(BLOCK_SIZE),
+            xform_dirty: DirtyRange::all(14 * BLOCK_SIZE),
             xform_cursor: 0,
             chunk_id,
+            no_cull,
             command_buffer_scratch: [DrawIndirectCommand {
                 vertex_count: 0,
                 instance_count: 0,
@@ -239,7 +323,7 @@ impl InstanceBlock {
         self.len() < BLOCK_SIZE
     }
 
-    fn allocate_slot(&mut self, draw_cmd: DrawIndirectCommand) -> SlotId {
+    fn allocate_slot(&mut self, shape_id: ShapeId, draw_cmd: DrawIndirectCommand) -> SlotId {
         assert!(self.has_open_slot());
         let slot_id = SlotId::new(self.block_id, self.next_slot);
         self.next_slot += 1;
@@ -249,11 +333,39 @@ impl InstanceBlock {
         // uploaded, once we enter the draw portion.
         self.slot_map[slot_id.index()] = slot_id.index();
         self.command_buffer_scratch[slot_id.index()] = draw_cmd;
-        self.slots_dirty = true;
+        self.command_dirty.mark(slot_id.index());
+        self.slot_shapes.push(shape_id);
 
         slot_id
     }
 
+    // Point every slot back at its part, after the chunk under us was rebuilt.
+    fn refresh_draw_commands(&mut self, chunk_man: &ShapeChunkBuffer) {
+        for (index, shape_id) in self.slot_shapes.iter().enumerate() {
+            let prior = self.command_buffer_scratch[index];
+            let draw_cmd = chunk_man
+                .part(*shape_id)
+                .draw_command(prior.first_instance, prior.instance_count);
+            if draw_cmd.first_vertex != prior.first_vertex
+                || draw_cmd.vertex_count != prior.vertex_count
+            {
+                self.command_buffer_scratch[index] = draw_cmd;
+                self.command_dirty.mark(index);
+            }
+        }
+    }
+
+    fn take_upload_ranges(&mut self) -> UploadRanges {
+        let len = self.len();
+        UploadRanges {
+            command: self.command_dirty.take_upload_range(len),
+            transform: self.transform_dirty.take_upload_range(len),
+            flag: self.flag_dirty.take_upload_range(len),
+            xform_index: self.xform_index_dirty.take_upload_range(len),
+            xform: self.xform_dirty.take_upload_range(self.xform_cursor),
+        }
+    }
+
     #[inline]
     fn begin_frame(&mut self) {
         self.xform_cursor = 0;
@@ -269,12 +381,24 @@ impl InstanceBlock {
         xform_count: usize,
     ) {
         let offset = self.slot_map[slot_id.index()];
-        self.transform_buffer_scratch[offset] = *transform;
-        self.flag_buffer_scratch[offset] = flags;
+        if self.transform_buffer_scratch[offset] != *transform {
+            self.transform_buffer_scratch[offset] = *transform;
+            self.transform_dirty.mark(offset);
+        }
+        if self.flag_buffer_scratch[offset] != flags {
+            self.flag_buffer_scratch[offset] = flags;
+            self.flag_dirty.mark(offset);
+        }
         if let Some(xf) = xforms {
-            self.xform_index_buffer_scratch[offset] = self.xform_cursor as u32;
-            self.xform_buffer_scratch[self.xform_cursor..self.xform_cursor + xform_count]
-                .copy_from_slice(&xf[0..xform_count]);
+            if self.xform_index_buffer_scratch[offset] != self.xform_cursor as u32 {
+                self.xform_index_buffer_scratch[offset] = self.xform_cursor as u32;
+                self.xform_index_dirty.mark(offset);
+            }
+            let span = self.xform_cursor..self.xform_cursor + xform_count;
+            if self.xform_buffer_scratch[span.clone()] != xf[0..xform_count] {
+                self.xform_buffer_scratch[span.clone()].copy_from_slice(&xf[0..xform_count]);
+                self.xform_dirty.mark_range(span);
+            }
             self.xform_cursor += xform_count;
         }
     }
@@ -283,6 +407,10 @@ impl InstanceBlock {
         self.chunk_id
     }
 
+    pub fn no_cull(&self) -> bool {
+        self.no_cull
+    }
+
     pub fn len(&self) -> usize {
         self.next_slot as usize
     }
@@ -521,10 +649,11 @@ impl ShapeInstanceBuffer {
         BlockId(bid)
     }
 
-    fn find_open_block(&mut self, chunk_id: ChunkId) -> Option<BlockId> {
+    fn find_open_block(&mut self, chunk_id: ChunkId, no_cull: bool) -> Option<BlockId> {
         if let Some(blocks) = self.chunk_to_block_map.get(&chunk_id) {
             for block_id in blocks {
-                if self.blocks[block_id].has_open_slot() {
+                let block = &self.blocks[block_id];
+                if block.no_cull == no_cull && block.has_open_slot() {
                     return Some(*block_id);
                 }
             }
@@ -545,13 +674,57 @@ impl ShapeInstanceBuffer {
             .chunk_man
             .upload_shape(name, selection, &palette, &catalog, gpu)?;
 
+        let slot_id = self.allocate_slot(chunk_id, shape_id, gpu)?;
+        Ok((shape_id, slot_id))
+    }
+
+    /// Re-parse a shape from `data` in place. Existing instances keep their ShapeRef and
+    /// ShapeSlot and draw the new geometry from the next frame.
+    pub fn reload_shape(
+        &mut self,
+        name: &str,
+        data: &[u8],
+        selection: DrawSelection,
+        palette: &Palette,
+        catalog: &Catalog,
+        gpu: &mut GPU,
+    ) -> Fallible<ShapeId> {
+        let (chunk_id, shape_id) = self
+            .chunk_man
+            .reload_shape(name, data, selection, palette, catalog, gpu)?;
+
+        // Every part in the rebuilt chunk may have moved, not just the one we replaced.
+        if let Some(block_ids) = self.chunk_to_block_map.get(&chunk_id) {
+            for block_id in block_ids {
+                self.blocks
+                    .get_mut(block_id)
+                    .expect("a block")
+                    .refresh_draw_commands(&self.chunk_man);
+            }
+        }
+        WIDGET_GENERATION.fetch_add(1, Ordering::SeqCst);
+        Ok(shape_id)
+    }
+
+    fn allocate_slot(
+        &mut self,
+        chunk_id: ChunkId,
+        shape_id: ShapeId,
+        gpu: &mut GPU,
+    ) -> Fallible<SlotId> {
         // Find or create a block that we can use to track the instance data.
-        let block_id = if let Some(block_id) = self.find_open_block(chunk_id) {
+        let no_cull = self.errata(shape_id).no_cull;
+        let block_id = if let Some(block_id) = self.find_open_block(chunk_id, no_cull) {
             block_id
         } else {
             let block_id = self.allocate_block_id();
-            let block =
-                InstanceBlock::new(block_id, chunk_id, &self.bind_group_layout, gpu.device())?;
+            let block = InstanceBlock::new(
+                block_id,
+                chunk_id,
+                no_cull,
+                &self.bind_group_layout,
+                gpu.device(),
+            )?;
             self.chunk_to_block_map
                 .entry(chunk_id)
                 .or_insert_with(Vec::new)
@@ -565,17 +738,20 @@ impl ShapeInstanceBuffer {
             .chunk_man
             .part(shape_id)
             .draw_command(self.blocks[&block_id].len() as u32, 1);
-        let slot_id = self
+        Ok(self
             .blocks
             .get_mut(&block_id)
             .unwrap()
-            .allocate_slot(draw_cmd);
-
-        Ok((shape_id, slot_id))
+            .allocate_slot(shape_id, draw_cmd))
     }
 
     pub fn ensure_uploaded(&mut self, gpu: &mut GPU) -> Fallible<()> {
-        self.chunk_man.finish_open_chunks(gpu)
+        self.chunk_man.finish_open_chunks(gpu)?;
+
+        // Slots are never freed, so any chunk with a block still has instances.
+        let live_chunks = self.chunk_to_block_map.keys().cloned().collect();
+        self.chunk_man.evict_over_budget(&live_chunks);
+        Ok(())
     }
 
     #[inline]
@@ -602,23 +778,22 @@ impl ShapeInstanceBuffer {
 
     pub fn make_upload_buffer(
         &mut self,
-        start: &Instant,
+        clock: &Clock,
         world: &mut World,
         gpu: &GPU,
         tracker: &mut FrameStateTracker,
     ) -> Fallible<()> {
-        let now = Instant::now();
-
         // Reset cursor for our next upload.
         for block in self.blocks.values_mut() {
             block.begin_frame();
         }
 
         // Animate the draw_state. We'll use the updated values below when computing
-        // xform and frame based animation states.
-        <Write<ShapeState>>::query().par_for_each_mut(world, |mut shape_state| {
-            shape_state.draw_state.animate(&now)
-        });
+        // xform and frame based animation states. A fixed clock holds everything still.
+        if let Clock::Real { now, .. } = clock {
+            <Write<ShapeState>>::query()
+                .par_for_each_mut(world, |mut shape_state| shape_state.draw_state.animate(now));
+        }
 
         let query = <(
             Read<Transform>,
@@ -641,34 +816,32 @@ impl ShapeInstanceBuffer {
         query.par_for_each_mut(world, |(shape_state, mut flag_buffer)| {
             shape_state
                 .draw_state
-                .build_mask_into(&start, &mut flag_buffer.buffer)
+                .build_mask_into(clock, &mut flag_buffer.buffer)
                 .unwrap();
         });
 
+        let generation = WIDGET_GENERATION.load(Ordering::SeqCst);
         let query = <(Read<ShapeRef>, Read<ShapeState>, Write<ShapeXformBuffer>)>::query();
         query.par_for_each_mut(world, |(shape_ref, shape_state, mut xform_buffer)| {
             let part = self.chunk_man.part(shape_ref.shape_id);
             WIDGET_CACHE.with(|widget_cache| {
-                match widget_cache.borrow_mut().entry(shape_ref.shape_id) {
+                let mut widget_cache = widget_cache.borrow_mut();
+                WIDGET_CACHE_GENERATION.with(|cache_generation| {
+                    if cache_generation.get() != generation {
+                        widget_cache.clear();
+                        cache_generation.set(generation);
+                    }
+                });
+                match widget_cache.entry(shape_ref.shape_id) {
                     Entry::Occupied(mut e) => {
                         e.get_mut()
-                            .animate_into(
-                                &shape_state.draw_state,
-                                &start,
-                                &now,
-                                &mut xform_buffer.buffer,
-                            )
+                            .animate_into(&shape_state.draw_state, clock, &mut xform_buffer.buffer)
                             .unwrap();
                     }
                     Entry::Vacant(e) => {
                         let mut widgets = part.widgets().read().unwrap().clone();
                         widgets
-                            .animate_into(
-                                &shape_state.draw_state,
-                                &start,
-                                &now,
-                                &mut xform_buffer.buffer,
-                            )
+                            .animate_into(&shape_state.draw_state, clock, &mut xform_buffer.buffer)
                             .unwrap();
                         e.insert(widgets);
                     }
@@ -696,46 +869,72 @@ impl ShapeInstanceBuffer {
             );
         }
 
-        for block in self.blocks.values() {
-            gpu.upload_slice_to(
-                "shape-instance-command-buffer-scratch",
-                &block.command_buffer_scratch[..block.len()],
-                block.command_buffer.clone(),
-                wgpu::BufferUsage::all(),
-                tracker,
-            );
+        for block in self.blocks.values_mut() {
+            let ranges = block.take_upload_ranges();
+            if let Some(range) = ranges.command {
+                let source = gpu.push_slice(
+                    "shape-instance-command-buffer-scratch",
+                    &block.command_buffer_scratch[range.clone()],
+                    wgpu::BufferUsage::COPY_SRC,
+                );
+                tracker.upload_to_array_element::<DrawIndirectCommand>(
+                    source,
+                    block.command_buffer.clone(),
+                    range.start,
+                );
+            }
 
-            gpu.upload_slice_to(
-                "shape-instance-transform-buffer-scratch",
-                &block.transform_buffer_scratch[..block.len()],
-                block.transform_buffer.clone(),
-                wgpu::BufferUsage::all(),
-                tracker,
-            );
+            if let Some(range) = ranges.transform {
+                let source = gpu.push_slice(
+                    "shape-instance-transform-buffer-scratch",
+                    &block.transform_buffer_scratch[range.clone()],
+                    wgpu::BufferUsage::COPY_SRC,
+                );
+                tracker.upload_to_array_element::<TransformType>(
+                    source,
+                    block.transform_buffer.clone(),
+                    range.start,
+                );
+            }
 
-            gpu.upload_slice_to(
-                "shape-instance-flag-buffer-scratch",
-                &block.flag_buffer_scratch[..block.len()],
-                block.flag_buffer.clone(),
-                wgpu::BufferUsage::all(),
-                tracker,
-            );
+            if let Some(range) = ranges.flag {
+                let source = gpu.push_slice(
+                    "shape-instance-flag-buffer-scratch",
+                    &block.flag_buffer_scratch[range.clone()],
+                    wgpu::BufferUsage::COPY_SRC,
+                );
+                tracker.upload_to_array_element::<[u32; 2]>(
+                    source,
+                    block.flag_buffer.clone(),
+                    range.start,
+                );
+            }
 
-            gpu.upload_slice_to(
-                "shape-instance-xform-index-buffer-scratch",
-                &block.xform_index_buffer_scratch[..block.len()],
-                block.xform_index_buffer.clone(),
-                wgpu::BufferUsage::all(),
-                tracker,
-            );
+            if let Some(range) = ranges.xform_index {
+                let source = gpu.push_slice(
+                    "shape-instance-xform-index-buffer-scratch",
+                    &block.xform_index_buffer_scratch[range.clone()],
+                    wgpu::BufferUsage::COPY_SRC,
+                );
+                tracker.upload_to_array_element::<u32>(
+                    source,
+                    block.xform_index_buffer.clone(),
+                    range.start,
+                );
+            }
 
-            gpu.upload_slice_to(
-                "shape-instance-xform-buffer-scratch",
-                &block.xform_buffer_scratch[..block.xform_cursor],
-                block.xform_buffer.clone(),
-                wgpu::BufferUsage::all(),
-                tracker,
-            );
+            if let Some(range) = ranges.xform {
+                let source = gpu.push_slice(
+                    "shape-instance-xform-buffer-scratch",
+                    &block.xform_buffer_scratch[range.clone()],
+                    wgpu::BufferUsage::COPY_SRC,
+                );
+                tracker.upload_to_array_element::<[f32; 6]>(
+                    source,
+                    block.xform_buffer.clone(),
+                    range.start,
+                );
+            }
         }
         Ok(())
     }
@@ -749,6 +948,264 @@ mod test {
     use pal::Palette;
     use shape_chunk::DrawSelection;
 
+    #[test]
+    fn test_dirty_range() {
+        // The first upload covers everything, however few slots are live.
+        let mut dirty = DirtyRange::all(BLOCK_SIZE);
+        assert_eq!(dirty.take_upload_range(4), Some(0..BLOCK_SIZE));
+        assert_eq!(dirty.take_upload_range(4), None);
+
+        dirty.mark(2);
+        assert_eq!(dirty.range, Some(2..3));
+        dirty.mark_range(5..7);
+        assert_eq!(dirty.range, Some(2..7));
+        dirty.mark_range(3..3);
+        assert_eq!(dirty.range, Some(2..7));
+        assert_eq!(dirty.take_upload_range(BLOCK_SIZE), Some(2..7));
+        assert_eq!(dirty, DirtyRange::default());
+
+        // Spans past the live end of the scratch are clipped or dropped.
+        dirty.mark_range(2..7);
+        assert_eq!(dirty.take_upload_range(5), Some(2..5));
+        dirty.mark_range(6..9);
+        assert_eq!(dirty.take_upload_range(5), None);
+    }
+
+    #[test]
+    fn test_dirty_range_uploads_only_the_changed_values() {
+        let mut scratch = [0u32; 16];
+        let mut dirty = DirtyRange::default();
+        for &index in &[9usize, 11, 10] {
+            scratch[index] = index as u32 * 100;
+            dirty.mark(index);
+        }
+
+        let range = dirty.take_upload_range(scratch.len()).unwrap();
+        assert_eq!(range.start, 9);
+        assert_eq!(&scratch[range], &[900, 1000, 1100]);
+    }
+
+    // Stands in for the GPU side of a scratch buffer.
+    fn copy_range<T: Copy>(gpu_copy: &mut [T], scratch: &[T], range: Option<Range<usize>>) {
+        if let Some(range) = range {
+            gpu_copy[range.clone()].copy_from_slice(&scratch[range]);
+        }
+    }
+
+    #[test]
+    fn test_late_slots_are_uploaded() -> Fallible<()> {
+        let input = InputSystem::new(vec![])?;
+        let mut gpu = GPU::new(&input, Default::default())?;
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let palette = Palette::from_bytes(&catalog.read_name_sync("PALETTE.PAL")?)?;
+            let inst_man = ShapeInstanceBuffer::new(gpu.device())?;
+            let mut inst_man = inst_man.borrow_mut();
+            let (_, first) = inst_man.upload_and_allocate_slot(
+                "F18.SH",
+                DrawSelection::NormalModel,
+                &palette,
+                &catalog,
+                &mut gpu,
+            )?;
+            inst_man.ensure_uploaded(&mut gpu)?;
+
+            // New GPU buffers hold whatever was there before.
+            let mut commands = [DrawIndirectCommand {
+                vertex_count: 7,
+                instance_count: 7,
+                first_vertex: 7,
+                first_instance: 7,
+            }; BLOCK_SIZE];
+            let mut transforms = [[7f32; 8]; BLOCK_SIZE];
+            let mut flags = [[7u32; 2]; BLOCK_SIZE];
+            let block = inst_man.blocks.get_mut(&first.block_id).unwrap();
+            let ranges = block.take_upload_ranges();
+            copy_range(&mut commands, &block.command_buffer_scratch, ranges.command);
+            copy_range(
+                &mut transforms,
+                &*block.transform_buffer_scratch,
+                ranges.transform,
+            );
+            copy_range(&mut flags, &*block.flag_buffer_scratch, ranges.flag);
+
+            // A slot allocated after the first upload, whose values match the
+            // zeroed scratch, so that pushing them marks nothing.
+            let (_, second) = inst_man.upload_and_allocate_slot(
+                "F18.SH",
+                DrawSelection::NormalModel,
+                &palette,
+                &catalog,
+                &mut gpu,
+            )?;
+            assert_eq!(second.block_id, first.block_id);
+            inst_man.push_values(second, &[0f32; 8], [0, 0], &None, 0);
+
+            let block = inst_man.blocks.get_mut(&second.block_id).unwrap();
+            let ranges = block.take_upload_ranges();
+            copy_range(&mut commands, &block.command_buffer_scratch, ranges.command);
+            copy_range(
+                &mut transforms,
+                &*block.transform_buffer_scratch,
+                ranges.transform,
+            );
+            copy_range(&mut flags, &*block.flag_buffer_scratch, ranges.flag);
+            let index = second.index();
+            let cmd = block.command_buffer_scratch[index];
+            assert_eq!(commands[index].first_vertex, cmd.first_vertex);
+            assert_eq!(commands[index].vertex_count, cmd.vertex_count);
+            assert_eq!(commands[index].first_instance, cmd.first_instance);
+            assert_eq!(transforms[index], [0f32; 8]);
+            assert_eq!(flags[index], [0, 0]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_reload_moves_instances() -> Fallible<()> {
+        let input = InputSystem::new(vec![])?;
+        let mut gpu = GPU::new(&input, Default::default())?;
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let palette = Palette::from_bytes(&catalog.read_name_sync("PALETTE.PAL")?)?;
+            let inst_man = ShapeInstanceBuffer::new(gpu.device())?;
+            inst_man.borrow_mut().chunk_man.set_use_placeholder(true);
+            let mut slots = Vec::new();
+            for name in &["F18.SH", "FUEL.SH", "F18.SH"] {
+                slots.push(inst_man.borrow_mut().upload_and_allocate_slot(
+                    name,
+                    DrawSelection::NormalModel,
+                    &palette,
+                    &catalog,
+                    &mut gpu,
+                )?);
+            }
+
+            // Put other shapes next to FUEL, so that there is one to stand in for an edit.
+            let mut names = Vec::new();
+            for other in catalog
+                .find_labeled_matching(&label, "*.SH")?
+                .iter()
+                .take(40)
+            {
+                names.push(catalog.stat_sync(*other)?.name);
+            }
+            let names = names.iter().map(|n| n.as_str()).collect::<Vec<_>>();
+            inst_man.borrow_mut().chunk_man.upload_shapes(
+                &names,
+                DrawSelection::NormalModel,
+                &palette,
+                &catalog,
+                &mut gpu,
+            )?;
+            inst_man.borrow_mut().ensure_uploaded(&mut gpu)?;
+
+            let fuel_id = slots[1].0;
+            let (chunk_id, edited_name) = {
+                let inst_man = inst_man.borrow();
+                let chunk_man = &inst_man.chunk_man;
+                let chunk_id = chunk_man.closed_chunk_for_shape(fuel_id).unwrap();
+                let fuel_len = chunk_man.part(fuel_id).vertex_range().len();
+                let edited_name = names
+                    .iter()
+                    .find(|name| {
+                        let shape_id = chunk_man.shape_for(name).unwrap();
+                        Some(shape_id) != chunk_man.placeholder_shape()
+                            && chunk_man.closed_chunk_for_shape(shape_id) == Some(chunk_id)
+                            && chunk_man.part(shape_id).vertex_range().len() != fuel_len
+                    })
+                    .expect("a differently sized shape in FUEL's chunk");
+                (chunk_id, edited_name.to_string())
+            };
+            let block_count = inst_man.borrow().blocks.len();
+            let instances = slots
+                .iter()
+                .map(|(_, slot_id)| {
+                    let cmd = inst_man.borrow().blocks[&slot_id.block_id].command_buffer_scratch
+                        [slot_id.index()];
+                    (cmd.first_instance, cmd.instance_count)
+                })
+                .collect::<Vec<_>>();
+
+            // Stand in for an edited FUEL with a static shape of a different size.
+            let edited = catalog.read_name_sync(&edited_name)?.to_vec();
+            let reloaded_id = inst_man.borrow_mut().reload_shape(
+                "FUEL.SH",
+                &edited,
+                DrawSelection::NormalModel,
+                &palette,
+                &catalog,
+                &mut gpu,
+            )?;
+            inst_man.borrow_mut().ensure_uploaded(&mut gpu)?;
+
+            let inst_man = inst_man.borrow();
+            assert_eq!(reloaded_id, fuel_id);
+            assert_eq!(inst_man.chunk_man.shape_for("FUEL.SH")?, fuel_id);
+            assert_eq!(
+                inst_man.chunk_man.closed_chunk_for_shape(fuel_id),
+                Some(chunk_id)
+            );
+            assert_eq!(inst_man.blocks.len(), block_count);
+            assert_eq!(
+                inst_man.part(fuel_id).vertex_range().len(),
+                inst_man
+                    .chunk_man
+                    .part_for(&edited_name)?
+                    .vertex_range()
+                    .len()
+            );
+
+            // The same slots now draw the new part for FUEL and wherever its neighbors moved to.
+            for ((shape_id, slot_id), instance) in slots.iter().zip(&instances) {
+                let block = &inst_man.blocks[&slot_id.block_id];
+                let cmd = &block.command_buffer_scratch[slot_id.index()];
+                let range = inst_man.part(*shape_id).vertex_range();
+                assert_eq!(cmd.first_vertex as usize, range.start);
+                assert_eq!(cmd.vertex_count as usize, range.len());
+                assert_eq!((cmd.first_instance, cmd.instance_count), *instance);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_thin_shapes_draw_without_culling() -> Fallible<()> {
+        let input = InputSystem::new(vec![])?;
+        let mut gpu = GPU::new(&input, Default::default())?;
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:*.SH".to_owned()])?;
+        let label = catalog.file_label(inputs[0])?;
+        catalog.set_default_label(&label);
+        let palette = Palette::from_bytes(&catalog.read_name_sync("PALETTE.PAL")?)?;
+        let inst_man = ShapeInstanceBuffer::new(gpu.device())?;
+        inst_man.borrow_mut().chunk_man.set_use_placeholder(true);
+        for &fid in &inputs {
+            let name = catalog.stat_sync(fid)?.name;
+            let (shape_id, slot_id) = inst_man.borrow_mut().upload_and_allocate_slot(
+                &name,
+                DrawSelection::NormalModel,
+                &palette,
+                &catalog,
+                &mut gpu,
+            )?;
+            let inst_man = inst_man.borrow();
+            assert_eq!(
+                inst_man.block(&slot_id.block_id).no_cull(),
+                inst_man.errata(shape_id).no_cull
+            );
+        }
+        inst_man.borrow_mut().ensure_uploaded(&mut gpu)?;
+
+        let inst_man = inst_man.borrow();
+        assert!(inst_man.blocks.values().any(InstanceBlock::no_cull));
+        assert!(inst_man.blocks.values().any(|block| !block.no_cull()));
+        Ok(())
+    }
+
     #[test]
     fn test_creation() -> Fallible<()> {
         let input = InputSystem::new(vec![])?;
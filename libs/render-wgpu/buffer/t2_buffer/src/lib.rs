@@ -13,10 +13,12 @@
 // You should have received a copy of the GNU General Public License
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
 
+mod material;
 mod t2_buffer;
 mod texture_atlas;
 
 pub use crate::{
+    material::{T2Band, T2Material},
     t2_buffer::{T2Buffer, Vertex as T2Vertex},
     texture_atlas::TextureAtlas,
 };
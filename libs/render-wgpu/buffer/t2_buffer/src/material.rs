@@ -0,0 +1,180 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use failure::{ensure, Fallible};
+use lay::{Layer, TerrainBand, BAND_COUNT, BAND_SIZE};
+use pal::Palette;
+
+// T2 sample colors point into the parts of the system palette that LAY rows get copied over, so
+// picking the right row for each destination is what gives the terrain its coloring.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct T2Band {
+    // Which 16 color row of the LAY fragment to copy.
+    pub lay_row: usize,
+    // Where in the palette to put it.
+    pub palette_offset: usize,
+}
+
+impl T2Band {
+    pub fn new(lay_row: usize, palette_offset: usize) -> Self {
+        Self {
+            lay_row,
+            palette_offset,
+        }
+    }
+
+    pub fn from_terrain_band(band: TerrainBand) -> Self {
+        Self::new(band.lay_row(), Layer::band_for(band).start as usize)
+    }
+}
+
+// The palette bands that the terrain is drawn with, and the LAY fragment they come from. The
+// defaults are the fixed palette offsets that Layer::band_for places each band at; the LAY
+// itself only supplies the colors.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct T2Material {
+    layer_index: Option<usize>,
+    // 0xF0 and up. The T2 marks all water with 0xFF.
+    water: T2Band,
+    // 0xE0 to 0xEF. Not used by T2 samples, so possibly the horizon or fog. It overlaps the top
+    // of the land band, so it must be applied before it.
+    horizon: T2Band,
+    // 0xD0 to 0xDA. The colors used by nearly every T2 land sample.
+    land: T2Band,
+    // 0xC2 to 0xC7. Only used by the land in TVIET.
+    alternate_land: T2Band,
+}
+
+impl Default for T2Material {
+    fn default() -> Self {
+        Self {
+            layer_index: None,
+            water: T2Band::from_terrain_band(TerrainBand::Water),
+            horizon: T2Band::from_terrain_band(TerrainBand::Horizon),
+            land: T2Band::from_terrain_band(TerrainBand::Land),
+            alternate_land: T2Band::from_terrain_band(TerrainBand::AlternateLand),
+        }
+    }
+}
+
+impl T2Material {
+    // Use a specific fragment of the LAY instead of the one the MM asks for.
+    pub fn set_layer_index(&mut self, layer_index: usize) {
+        self.layer_index = Some(layer_index);
+    }
+
+    pub fn set_water_band(&mut self, band: T2Band) {
+        self.water = band;
+    }
+
+    pub fn set_horizon_band(&mut self, band: T2Band) {
+        self.horizon = band;
+    }
+
+    pub fn set_land_band(&mut self, band: T2Band) {
+        self.land = band;
+    }
+
+    pub fn set_alternate_land_band(&mut self, band: T2Band) {
+        self.alternate_land = band;
+    }
+
+    // The MM stores 0 when it wants the default daytime fragment.
+    pub fn layer_index(&self, mm_layer_index: usize) -> usize {
+        if let Some(index) = self.layer_index {
+            index
+        } else if mm_layer_index != 0 {
+            mm_layer_index
+        } else {
+            2
+        }
+    }
+
+    pub fn build_palette(
+        &self,
+        layer: &Layer,
+        layer_index: usize,
+        system_palette: &Palette,
+    ) -> Fallible<Palette> {
+        let layer_data = layer.for_index(layer_index)?;
+        let mut palette = system_palette.clone();
+        for band in &[self.water, self.horizon, self.land, self.alternate_land] {
+            ensure!(band.lay_row < BAND_COUNT, "t2: no LAY row {}", band.lay_row);
+            let start = band.lay_row * BAND_SIZE;
+            palette.overlay_at(
+                &layer_data.slice(start, start + BAND_SIZE)?,
+                band.palette_offset,
+            )?;
+        }
+        Ok(palette)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lib::{from_dos_string, CatalogBuilder};
+    use mm::MissionMap;
+    use xt::TypeManager;
+
+    #[test]
+    fn it_builds_a_different_palette_per_band() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:BAL.MM".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let types = TypeManager::empty();
+            let content = from_dos_string(catalog.read_sync(fid)?);
+            let mm = MissionMap::from_str(&content, &types, &catalog)?;
+            let system_palette = Palette::from_bytes(&catalog.read_name_sync("PALETTE.PAL")?)?;
+            let layer =
+                Layer::from_bytes(&catalog.read_name_sync(mm.layer_name())?, &system_palette)?;
+
+            let material = T2Material::default();
+            let index = material.layer_index(mm.layer_index());
+            let base = material.build_palette(&layer, index, &system_palette)?;
+            assert_eq!(
+                base.as_bytes(),
+                layer.overlay_palette(index, &system_palette)?.as_bytes()
+            );
+
+            let setters: [fn(&mut T2Material, T2Band); 4] = [
+                T2Material::set_water_band,
+                T2Material::set_horizon_band,
+                T2Material::set_land_band,
+                T2Material::set_alternate_land_band,
+            ];
+            let mut palettes = Vec::new();
+            for (i, setter) in setters.iter().enumerate() {
+                let mut material = T2Material::default();
+                setter(&mut material, T2Band::new(i, 0x10 * (i + 1)));
+                let palette = material.build_palette(&layer, index, &system_palette)?;
+                assert_ne!(palette.as_bytes(), base.as_bytes());
+                palettes.push(palette.as_bytes());
+            }
+            for i in 0..palettes.len() {
+                for j in i + 1..palettes.len() {
+                    assert_ne!(palettes[i], palettes[j]);
+                }
+            }
+
+            let mut material = T2Material::default();
+            material.set_land_band(T2Band::new(BAND_COUNT, 0xD0));
+            assert!(material
+                .build_palette(&layer, index, &system_palette)
+                .is_err());
+        }
+        Ok(())
+    }
+}
@@ -12,7 +12,7 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
-use crate::texture_atlas::TextureAtlas;
+use crate::{material::T2Material, texture_atlas::TextureAtlas};
 use catalog::Catalog;
 use failure::Fallible;
 use gpu::GPU;
@@ -34,6 +34,14 @@ use std::{
 use t2::{Sample, Terrain};
 use zerocopy::{AsBytes, FromBytes};
 
+// Keep each buffer to the exact usages we need so that drivers can place them optimally.
+const ATLAS_UPLOAD_USAGE: wgpu::BufferUsage = wgpu::BufferUsage::COPY_SRC;
+const ATLAS_TEXTURE_USAGE: wgpu::TextureUsage = wgpu::TextureUsage::from_bits_truncate(
+    wgpu::TextureUsage::SAMPLED.bits() | wgpu::TextureUsage::COPY_DST.bits(),
+);
+const VERTEX_BUFFER_USAGE: wgpu::BufferUsage = wgpu::BufferUsage::VERTEX;
+const INDEX_BUFFER_USAGE: wgpu::BufferUsage = wgpu::BufferUsage::INDEX;
+
 #[repr(C)]
 #[derive(AsBytes, FromBytes, Copy, Clone, Default)]
 pub struct Vertex {
@@ -98,26 +106,168 @@ impl Vertex {
     }
 }
 
+// Builds vertices for terrain samples, remembering the positions and normals we need again
+// for neighboring samples and for collisions later.
+#[derive(Default)]
+struct T2Vertices {
+    memo_normal: HashMap<[(u32, u32); 3], Vector3<f32>>,
+    memo_position: HashMap<(u32, u32), Vector3<f32>>,
+    memo_vert: HashMap<(u32, u32), Vertex>,
+}
+
+impl T2Vertices {
+    fn sample_at(terrain: &Terrain, xi: u32, zi: u32) -> Sample {
+        let offset = (zi * terrain.width() + xi) as usize;
+        if offset < terrain.samples.len() {
+            terrain.samples[offset]
+        } else {
+            let offset = ((zi - 1) * terrain.width() + xi) as usize;
+            if offset < terrain.samples.len() {
+                terrain.samples[offset]
+            } else {
+                let offset = ((zi - 1) * terrain.width() + (xi - 1)) as usize;
+                terrain.samples[offset]
+            }
+        }
+    }
+
+    fn position_at(&mut self, terrain: &Terrain, xi: u32, zi: u32) -> Vector3<f32> {
+        if let Some(v) = self.memo_position.get(&(xi, zi)) {
+            return *v;
+        }
+
+        let sample = Self::sample_at(terrain, xi, zi);
+
+        let xf = xi as f32 / terrain.width() as f32;
+        let zf = zi as f32 / terrain.height() as f32;
+        let scale_x_ft = terrain.extent_east_west_in_ft();
+        let scale_z_ft = terrain.extent_north_south_in_ft();
+        let x_hm = xf * scale_x_ft * FEET_TO_HM_32;
+        let z_hm = (1f32 - zf) * scale_z_ft * FEET_TO_HM_32;
+        let mut h = -f32::from(sample.height) * 3f32; /*/ 512f32 + 0.1f32*/
+
+        // Compute distance from center.
+        let center_x_km = scale_x_ft * FEET_TO_KM / 2f32;
+        let center_z_km = scale_z_ft * FEET_TO_KM / 2f32;
+        let x_km = xf * scale_x_ft * FEET_TO_KM;
+        let z_km = zf * scale_z_ft * FEET_TO_KM;
+        let xc = (center_x_km - x_km).abs();
+        let zc = (center_z_km - z_km).abs();
+        let d_km = (xc * xc + zc * zc).sqrt();
+        let hyp_km = (d_km * d_km + EARTH_RADIUS_KM_32 * EARTH_RADIUS_KM_32).sqrt();
+        let dev_km = hyp_km - EARTH_RADIUS_KM_32;
+        h += dev_km * 10f32;
+
+        let position = Vector3::new(x_hm, h, z_hm);
+        self.memo_position.insert((xi, zi), position);
+        position
+    }
+
+    fn normal_for(&mut self, terrain: &Terrain, coords: [(u32, u32); 3]) -> Vector3<f32> {
+        if let Some(&normal) = self.memo_normal.get(&coords) {
+            return normal;
+        }
+        if coords[0] == coords[1] || coords[1] == coords[2] || coords[0] == coords[2] {
+            let normal = Vector3::new(0f32, -1f32, 0f32);
+            self.memo_normal.insert(coords, normal);
+            return normal;
+        }
+        let p0 = self.position_at(terrain, coords[0].0, coords[0].1);
+        let p1 = self.position_at(terrain, coords[1].0, coords[1].1);
+        let p2 = self.position_at(terrain, coords[2].0, coords[2].1);
+        let normal = (p2 - p1).cross(&(p0 - p1)).normalize();
+        self.memo_normal.insert(coords, normal);
+        normal
+    }
+
+    // The T2 are flat and square. And cover several degrees of the earth. That means we need to
+    // actually account for curvature in a reasonable way. Flightgear handles this by having tile
+    // coordinates in lat/lon/asl. This (probably) won't work because MM files list shapes to put
+    // on the map in feet off of the origin. If we change the value of up, we need to rotate
+    // all the shapes. Some of the shapes need to line up closely to make sense, like runways.
+    //
+    // We deal with this by draping down in the direction of the tile, rather than towards
+    // earth center, and using the result as the lat-lon. e.g. we treat XYZ as primary, but
+    //
+    fn compute_at(
+        &mut self,
+        terrain: &Terrain,
+        palette: &Palette,
+        xi: u32,
+        zi: u32,
+        tex_coord: [f32; 2],
+        verts: &mut Vec<Vertex>,
+    ) {
+        if let Some(v) = self.memo_vert.get(&(xi, zi)) {
+            let mut vert = *v;
+            vert.tex_coord = tex_coord;
+            verts.push(vert);
+            return;
+        }
+
+        let sample = Self::sample_at(terrain, xi, zi);
+
+        let x0 = xi.saturating_sub(1);
+        let x1 = xi;
+        let x2 = (xi + 1).min(terrain.width() - 1);
+        let z0 = zi.saturating_sub(1);
+        let z1 = zi;
+        let z2 = (zi + 1).min(terrain.height() - 1);
+        let p11 = self.position_at(terrain, x1, z1);
+        let normals = [
+            self.normal_for(terrain, [(x0, z1), (x1, z1), (x0, z0)]),
+            self.normal_for(terrain, [(x0, z0), (x1, z1), (x1, z0)]),
+            self.normal_for(terrain, [(x0, z2), (x1, z2), (x0, z1)]),
+            self.normal_for(terrain, [(x0, z1), (x1, z2), (x1, z1)]),
+            self.normal_for(terrain, [(x1, z1), (x2, z1), (x1, z0)]),
+            self.normal_for(terrain, [(x1, z0), (x2, z1), (x2, z0)]),
+            self.normal_for(terrain, [(x1, z2), (x2, z2), (x1, z1)]),
+            self.normal_for(terrain, [(x1, z1), (x2, z2), (x2, z1)]),
+        ];
+        let mut normal = Vector3::zeros();
+        for n in &normals {
+            normal += n;
+        }
+        let normal = normal.normalize();
+
+        let mut color = palette.rgba_f32_srgb(sample.color as usize).unwrap();
+        if sample.color == 0xFF {
+            color[3] = 0f32;
+        }
+
+        let vert = Vertex {
+            position: [p11[0], p11[1], p11[2]],
+            normal: [normal[0], normal[1], normal[2]],
+            color,
+            tex_coord,
+        };
+        self.memo_vert.insert((xi, zi), vert);
+        verts.push(vert);
+    }
+}
+
 // Hold our working state.
 struct T2BufferFactory<'a> {
     mm: &'a MissionMap,
+    material: &'a T2Material,
     system_palette: &'a Palette,
     catalog: &'a Catalog,
-
-    memo_normal: HashMap<[(u32, u32); 3], Vector3<f32>>,
-    memo_position: HashMap<(u32, u32), Vector3<f32>>,
-    memo_vert: HashMap<(u32, u32), Vertex>,
+    vertices: T2Vertices,
 }
 
 impl<'a> T2BufferFactory<'a> {
-    fn new(mm: &'a MissionMap, system_palette: &'a Palette, catalog: &'a Catalog) -> Self {
+    fn new(
+        mm: &'a MissionMap,
+        material: &'a T2Material,
+        system_palette: &'a Palette,
+        catalog: &'a Catalog,
+    ) -> Self {
         Self {
             mm,
+            material,
             system_palette,
             catalog,
-            memo_position: HashMap::new(),
-            memo_normal: HashMap::new(),
-            memo_vert: HashMap::new(),
+            vertices: T2Vertices::default(),
         }
     }
 
@@ -129,10 +279,10 @@ impl<'a> T2BufferFactory<'a> {
             self.upload_terrain_textured_simple(&terrain, &atlas, &palette, gpu)?;
 
         let mut positions = HashMap::new();
-        mem::swap(&mut positions, &mut self.memo_position);
+        mem::swap(&mut positions, &mut self.vertices.memo_position);
 
         let mut normals = HashMap::new();
-        mem::swap(&mut normals, &mut self.memo_normal);
+        mem::swap(&mut normals, &mut self.vertices.memo_normal);
 
         Ok(Arc::new(RefCell::new(T2Buffer {
             bind_group_layout,
@@ -151,26 +301,9 @@ impl<'a> T2BufferFactory<'a> {
             &self.catalog.read_name_sync(&self.mm.layer_name())?,
             &self.system_palette,
         )?;
-        let layer_index = if self.mm.layer_index() != 0 {
-            self.mm.layer_index()
-        } else {
-            2
-        };
-
-        let layer_data = layer.for_index(layer_index)?;
-        let r0 = layer_data.slice(0x00, 0x10)?;
-        let r1 = layer_data.slice(0x10, 0x20)?;
-        let r2 = layer_data.slice(0x20, 0x30)?;
-        let r3 = layer_data.slice(0x30, 0x40)?;
-
-        // We need to put rows r0, r1, and r2 into into 0xC0, 0xE0, 0xF0 somehow.
-        let mut palette = self.system_palette.clone();
-        palette.overlay_at(&r1, 0xF0 - 1)?;
-        palette.overlay_at(&r0, 0xE0 - 1)?;
-        palette.overlay_at(&r3, 0xD0)?;
-        palette.overlay_at(&r2, 0xC0)?;
-
-        Ok(palette)
+        let layer_index = self.material.layer_index(self.mm.layer_index());
+        self.material
+            .build_palette(&layer, layer_index, &self.system_palette)
     }
 
     // Texture counts for all FA T2's.
@@ -222,11 +355,8 @@ impl<'a> T2BufferFactory<'a> {
         };
         let image_data = image_buf.into_raw();
 
-        let transfer_buffer = gpu.push_buffer(
-            "t2-buffer-atlas-upload",
-            &image_data,
-            wgpu::BufferUsage::all(),
-        );
+        let transfer_buffer =
+            gpu.push_buffer("t2-buffer-atlas-upload", &image_data, ATLAS_UPLOAD_USAGE);
         let atlas_texture = gpu.device().create_texture(&wgpu::TextureDescriptor {
             label: Some("t2-buffer-atlas"),
             size: extent,
@@ -234,8 +364,8 @@ impl<'a> T2BufferFactory<'a> {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: wgpu::TextureUsage::all(),
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: ATLAS_TEXTURE_USAGE,
         });
         let mut encoder = gpu
             .device()
@@ -261,7 +391,7 @@ impl<'a> T2BufferFactory<'a> {
         gpu.device().poll(wgpu::Maintain::Wait);
 
         let atlas_texture_view = atlas_texture.create_view(&wgpu::TextureViewDescriptor {
-            format: wgpu::TextureFormat::Rgba8Unorm,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
             dimension: wgpu::TextureViewDimension::D2,
             aspect: wgpu::TextureAspect::All,
             base_mip_level: 0,
@@ -320,140 +450,6 @@ impl<'a> T2BufferFactory<'a> {
         Ok((atlas, bind_group_layout, bind_group))
     }
 
-    fn sample_at(terrain: &Terrain, xi: u32, zi: u32) -> Sample {
-        let offset = (zi * terrain.width() + xi) as usize;
-        if offset < terrain.samples.len() {
-            terrain.samples[offset]
-        } else {
-            let offset = ((zi - 1) * terrain.width() + xi) as usize;
-            if offset < terrain.samples.len() {
-                terrain.samples[offset]
-            } else {
-                let offset = ((zi - 1) * terrain.width() + (xi - 1)) as usize;
-                terrain.samples[offset]
-            }
-        }
-    }
-
-    fn position_at(&mut self, terrain: &Terrain, xi: u32, zi: u32) -> Vector3<f32> {
-        if let Some(v) = self.memo_position.get(&(xi, zi)) {
-            return *v;
-        }
-
-        let sample = Self::sample_at(terrain, xi, zi);
-
-        let xf = xi as f32 / terrain.width() as f32;
-        let zf = zi as f32 / terrain.height() as f32;
-        let scale_x_ft = terrain.extent_east_west_in_ft();
-        let scale_z_ft = terrain.extent_north_south_in_ft();
-        let x_hm = xf * scale_x_ft * FEET_TO_HM_32;
-        let z_hm = (1f32 - zf) * scale_z_ft * FEET_TO_HM_32;
-        let mut h = -f32::from(sample.height) * 3f32; /*/ 512f32 + 0.1f32*/
-
-        // Compute distance from center.
-        let center_x_km = scale_x_ft * FEET_TO_KM / 2f32;
-        let center_z_km = scale_z_ft * FEET_TO_KM / 2f32;
-        let x_km = xf * scale_x_ft * FEET_TO_KM;
-        let z_km = zf * scale_z_ft * FEET_TO_KM;
-        let xc = (center_x_km - x_km).abs();
-        let zc = (center_z_km - z_km).abs();
-        let d_km = (xc * xc + zc * zc).sqrt();
-        let hyp_km = (d_km * d_km + EARTH_RADIUS_KM_32 * EARTH_RADIUS_KM_32).sqrt();
-        let dev_km = hyp_km - EARTH_RADIUS_KM_32;
-        h += dev_km * 10f32;
-
-        let position = Vector3::new(x_hm, h, z_hm);
-        self.memo_position.insert((xi, zi), position);
-        position
-    }
-
-    fn normal_for(&mut self, terrain: &Terrain, coords: [(u32, u32); 3]) -> Vector3<f32> {
-        if let Some(&normal) = self.memo_normal.get(&coords) {
-            return normal;
-        }
-        if coords[0] == coords[1] || coords[1] == coords[2] || coords[0] == coords[2] {
-            let normal = Vector3::new(0f32, -1f32, 0f32);
-            self.memo_normal.insert(coords, normal);
-            return normal;
-        }
-        let p0 = self.position_at(terrain, coords[0].0, coords[0].1);
-        let p1 = self.position_at(terrain, coords[1].0, coords[1].1);
-        let p2 = self.position_at(terrain, coords[2].0, coords[2].1);
-        let normal = (p2 - p1).cross(&(p0 - p1)).normalize();
-        self.memo_normal.insert(coords, normal);
-        normal
-    }
-
-    // The T2 are flat and square. And cover several degrees of the earth. That means we need to
-    // actually account for curvature in a reasonable way. Flightgear handles this by having tile
-    // coordinates in lat/lon/asl. This (probably) won't work because MM files list shapes to put
-    // on the map in feet off of the origin. If we change the value of up, we need to rotate
-    // all the shapes. Some of the shapes need to line up closely to make sense, like runways.
-    //
-    // We deal with this by draping down in the direction of the tile, rather than towards
-    // earth center, and using the result as the lat-lon. e.g. we treat XYZ as primary, but
-    //
-    fn compute_at(
-        &mut self,
-        terrain: &Terrain,
-        palette: &Palette,
-        xi: u32,
-        zi: u32,
-        tex_coord: [f32; 2],
-        verts: &mut Vec<Vertex>,
-    ) {
-        if let Some(v) = self.memo_vert.get(&(xi, zi)) {
-            let mut vert = *v;
-            vert.tex_coord = tex_coord;
-            verts.push(vert);
-            return;
-        }
-
-        let sample = Self::sample_at(terrain, xi, zi);
-
-        let x0 = xi.saturating_sub(1);
-        let x1 = xi;
-        let x2 = (xi + 1).min(terrain.width() - 1);
-        let z0 = zi.saturating_sub(1);
-        let z1 = zi;
-        let z2 = (zi + 1).min(terrain.height() - 1);
-        let p11 = self.position_at(terrain, x1, z1);
-        let normals = [
-            self.normal_for(terrain, [(x0, z1), (x1, z1), (x0, z0)]),
-            self.normal_for(terrain, [(x0, z0), (x1, z1), (x1, z0)]),
-            self.normal_for(terrain, [(x0, z2), (x1, z2), (x0, z1)]),
-            self.normal_for(terrain, [(x0, z1), (x1, z2), (x1, z1)]),
-            self.normal_for(terrain, [(x1, z1), (x2, z1), (x1, z0)]),
-            self.normal_for(terrain, [(x1, z0), (x2, z1), (x2, z0)]),
-            self.normal_for(terrain, [(x1, z2), (x2, z2), (x1, z1)]),
-            self.normal_for(terrain, [(x1, z1), (x2, z2), (x2, z1)]),
-        ];
-        let mut normal = Vector3::identity();
-        for n in &normals {
-            normal += n;
-        }
-        let normal = normal.normalize();
-
-        let mut color = palette.rgba(sample.color as usize).unwrap();
-        if sample.color == 0xFF {
-            color.data[3] = 0;
-        }
-
-        let vert = Vertex {
-            position: [p11[0], p11[1], p11[2]],
-            normal: [normal[0], normal[1], normal[2]],
-            color: [
-                f32::from(color[0]) / 255f32,
-                f32::from(color[1]) / 255f32,
-                f32::from(color[2]) / 255f32,
-                f32::from(color[3]) / 255f32,
-            ],
-            tex_coord,
-        };
-        self.memo_vert.insert((xi, zi), vert);
-        verts.push(vert);
-    }
-
     fn upload_terrain_textured_simple(
         &mut self,
         terrain: &Terrain,
@@ -506,15 +502,16 @@ impl<'a> T2BufferFactory<'a> {
                             })
                             .unwrap_or([0f32, 0f32]);
 
-                        self.compute_at(terrain, palette, xi, zi, tex_coord, &mut verts);
+                        self.vertices
+                            .compute_at(terrain, palette, xi, zi, tex_coord, &mut verts);
                     }
                 }
                 push_patch_indices(base, &mut indices);
             }
         }
 
-        let vertex_buffer = gpu.push_slice("t2-buffer-vertices", &verts, wgpu::BufferUsage::all());
-        let index_buffer = gpu.push_slice("t2-buffer-indices", &indices, wgpu::BufferUsage::all());
+        let vertex_buffer = gpu.push_slice("t2-buffer-vertices", &verts, VERTEX_BUFFER_USAGE);
+        let index_buffer = gpu.push_slice("t2-buffer-indices", &indices, INDEX_BUFFER_USAGE);
         Ok((vertex_buffer, index_buffer, indices.len() as u32))
     }
 }
@@ -538,9 +535,19 @@ impl T2Buffer {
         system_palette: &Palette,
         catalog: &Catalog,
         gpu: &mut GPU,
+    ) -> Fallible<Arc<RefCell<Self>>> {
+        Self::new_with_material(mm, &T2Material::default(), system_palette, catalog, gpu)
+    }
+
+    pub fn new_with_material(
+        mm: &MissionMap,
+        material: &T2Material,
+        system_palette: &Palette,
+        catalog: &Catalog,
+        gpu: &mut GPU,
     ) -> Fallible<Arc<RefCell<Self>>> {
         trace!("T2Renderer::new");
-        T2BufferFactory::new(mm, system_palette, catalog).build(gpu)
+        T2BufferFactory::new(mm, material, system_palette, catalog).build(gpu)
     }
 
     pub fn t2(&self) -> &Terrain {
@@ -656,4 +663,73 @@ mod test {
         }
         Ok(())
     }
+
+    // A 3x3 BIT2 covering 3000ft on a side, with every sample at the given color and height.
+    fn make_flat_t2(colors: [u8; 9], height: u8) -> Fallible<Terrain> {
+        let mut data = vec![0u8; 149];
+        data[0..4].copy_from_slice(b"BIT2");
+        data[119..123].copy_from_slice(&524_288u32.to_le_bytes());
+        data[123..127].copy_from_slice(&3000u32.to_le_bytes());
+        data[127..131].copy_from_slice(&3000u32.to_le_bytes());
+        data[137..141].copy_from_slice(&3u32.to_le_bytes());
+        data[141..145].copy_from_slice(&3u32.to_le_bytes());
+        for &color in &colors {
+            data.extend_from_slice(&[color, 0, height]);
+        }
+        Terrain::from_bytes(&data)
+    }
+
+    #[test]
+    fn test_vertices_for_a_flat_tile() -> Fallible<()> {
+        let mut colors = [0xD0; 9];
+        colors[0] = 0xFF;
+        let terrain = make_flat_t2(colors, 2)?;
+        let palette = Palette::grayscale()?;
+        let mut vertices = T2Vertices::default();
+        let mut verts = Vec::new();
+        vertices.compute_at(&terrain, &palette, 1, 1, [0.25, 0.75], &mut verts);
+        vertices.compute_at(&terrain, &palette, 0, 0, [0.0, 0.0], &mut verts);
+
+        // The center sample is a third of the way along x and, since z runs backwards from the
+        // first row, two thirds of the way along z. Each height unit is 3 hm, with up towards
+        // -y. Over 3000ft, the earth's curvature is lost in the noise.
+        let center = &verts[0];
+        for (&actual, &expected) in center.position.iter().zip(&[3.048, -6.0, 6.096]) {
+            assert!(
+                (actual - expected).abs() < 0.01,
+                "{} != {}",
+                actual,
+                expected
+            );
+        }
+        assert!(center.normal[0].abs() < 0.001);
+        assert!((center.normal[1] + 1.0).abs() < 0.001);
+        assert!(center.normal[2].abs() < 0.001);
+        // 0xD0 is 208 in the grayscale palette: ((208 / 255 + 0.055) / 1.055)^2.4.
+        for &c in &center.color[..3] {
+            assert!((c - 0.630_76).abs() < 0.0001, "{} != 0.63076", c);
+        }
+        assert_eq!(center.color[3], 1.0);
+        assert_eq!(center.tex_coord, [0.25, 0.75]);
+
+        // The first sample is at the origin in x and the full extent in z. 0xFF is see-through.
+        let corner = &verts[1];
+        assert!(corner.position[0].abs() < 0.01);
+        assert!((corner.position[2] - 9.144).abs() < 0.01);
+        assert_eq!(corner.color[3], 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_buffer_usages_are_minimal() {
+        assert_eq!(ATLAS_UPLOAD_USAGE, wgpu::BufferUsage::COPY_SRC);
+        assert!(!ATLAS_TEXTURE_USAGE.contains(wgpu::TextureUsage::STORAGE));
+        assert!(!ATLAS_TEXTURE_USAGE.contains(wgpu::TextureUsage::OUTPUT_ATTACHMENT));
+        assert!(!VERTEX_BUFFER_USAGE.intersects(
+            wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::INDEX | wgpu::BufferUsage::UNIFORM
+        ));
+        assert!(!INDEX_BUFFER_USAGE.intersects(
+            wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::UNIFORM
+        ));
+    }
 }
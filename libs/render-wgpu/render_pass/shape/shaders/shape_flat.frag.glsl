@@ -0,0 +1,34 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+#version 450
+
+// Inputs
+layout(location = 0) smooth in vec4 v_color;
+layout(location = 1) smooth in vec2 v_tex_coord;
+layout(location = 2) flat in uint f_flags0;
+layout(location = 3) flat in uint f_flags1;
+
+// Output
+layout(location = 0) out vec4 f_color;
+
+// Debug variant of shape.frag: always shows the facet's palette color, even
+// where texture coordinates would normally have us sample from the atlas.
+void main() {
+    if ((f_flags0 & 0xFFFFFFFE) == 0 && f_flags1 == 0) {
+        discard;
+    } else {
+        f_color = vec4(v_color.xyz, 1.0);
+    }
+}
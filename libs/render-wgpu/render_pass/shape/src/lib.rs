@@ -18,11 +18,41 @@ use global_data::GlobalParametersBuffer;
 use gpu::GPU;
 use ofa_groups::Group as LocalGroup;
 use shader_shared::Group;
-use shape_chunk::Vertex;
+use shape_chunk::{Vertex, MAX_SHAPE_VERTEX_COUNT};
 use shape_instance::ShapeInstanceBuffer;
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ShapeRenderMode {
+    // Sample from the atlas wherever a facet has texture coordinates.
+    Textured,
+
+    // Draw every facet with its raw palette color, ignoring texture coordinates.
+    FlatColor,
+
+    // Draw facet edges in their palette color. Note that wgpu does not give us a
+    // polygon fill mode, so this draws the triangle list through an edge index buffer.
+    Wireframe,
+}
+
+// Walk each triangle of a triangle list as three line segments. This is relative
+// to the first vertex of the draw, so one buffer serves every shape.
+fn make_edge_indices(vertex_count: usize) -> Vec<u32> {
+    let mut indices = Vec::with_capacity(vertex_count * 2);
+    for base in (0..vertex_count as u32).step_by(3) {
+        indices.extend_from_slice(&[base, base + 1, base + 1, base + 2, base + 2, base]);
+    }
+    indices
+}
+
 pub struct ShapeRenderPass {
-    pipeline: wgpu::RenderPipeline,
+    textured_pipeline: wgpu::RenderPipeline,
+    flat_pipeline: wgpu::RenderPipeline,
+    wireframe_pipeline: wgpu::RenderPipeline,
+    // Variants for single-sided shapes that must be visible from behind.
+    textured_no_cull_pipeline: wgpu::RenderPipeline,
+    flat_no_cull_pipeline: wgpu::RenderPipeline,
+    edge_index_buffer: wgpu::Buffer,
+    render_mode: ShapeRenderMode,
 }
 
 impl ShapeRenderPass {
@@ -34,6 +64,8 @@ impl ShapeRenderPass {
     ) -> Fallible<Self> {
         let vert_shader = gpu.create_shader_module(include_bytes!("../target/shape.vert.spirv"))?;
         let frag_shader = gpu.create_shader_module(include_bytes!("../target/shape.frag.spirv"))?;
+        let flat_frag_shader =
+            gpu.create_shader_module(include_bytes!("../target/shape_flat.frag.spirv"))?;
 
         let pipeline_layout =
             gpu.device()
@@ -46,26 +78,97 @@ impl ShapeRenderPass {
                     ],
                 });
 
-        let pipeline = gpu
-            .device()
+        let textured_pipeline = Self::make_pipeline(
+            gpu,
+            &pipeline_layout,
+            &vert_shader,
+            &frag_shader,
+            wgpu::PrimitiveTopology::TriangleList,
+            wgpu::IndexFormat::Uint16,
+            wgpu::CullMode::Back,
+        );
+        let flat_pipeline = Self::make_pipeline(
+            gpu,
+            &pipeline_layout,
+            &vert_shader,
+            &flat_frag_shader,
+            wgpu::PrimitiveTopology::TriangleList,
+            wgpu::IndexFormat::Uint16,
+            wgpu::CullMode::Back,
+        );
+        let wireframe_pipeline = Self::make_pipeline(
+            gpu,
+            &pipeline_layout,
+            &vert_shader,
+            &flat_frag_shader,
+            wgpu::PrimitiveTopology::LineList,
+            wgpu::IndexFormat::Uint32,
+            wgpu::CullMode::None,
+        );
+        let textured_no_cull_pipeline = Self::make_pipeline(
+            gpu,
+            &pipeline_layout,
+            &vert_shader,
+            &frag_shader,
+            wgpu::PrimitiveTopology::TriangleList,
+            wgpu::IndexFormat::Uint16,
+            wgpu::CullMode::None,
+        );
+        let flat_no_cull_pipeline = Self::make_pipeline(
+            gpu,
+            &pipeline_layout,
+            &vert_shader,
+            &flat_frag_shader,
+            wgpu::PrimitiveTopology::TriangleList,
+            wgpu::IndexFormat::Uint16,
+            wgpu::CullMode::None,
+        );
+
+        let edge_index_buffer = gpu.push_slice(
+            "shape-edge-indices",
+            &make_edge_indices(MAX_SHAPE_VERTEX_COUNT),
+            wgpu::BufferUsage::INDEX,
+        );
+
+        Ok(Self {
+            textured_pipeline,
+            flat_pipeline,
+            wireframe_pipeline,
+            textured_no_cull_pipeline,
+            flat_no_cull_pipeline,
+            edge_index_buffer,
+            render_mode: ShapeRenderMode::Textured,
+        })
+    }
+
+    fn make_pipeline(
+        gpu: &GPU,
+        pipeline_layout: &wgpu::PipelineLayout,
+        vert_shader: &wgpu::ShaderModule,
+        frag_shader: &wgpu::ShaderModule,
+        primitive_topology: wgpu::PrimitiveTopology,
+        index_format: wgpu::IndexFormat,
+        cull_mode: wgpu::CullMode,
+    ) -> wgpu::RenderPipeline {
+        gpu.device()
             .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                layout: &pipeline_layout,
+                layout: pipeline_layout,
                 vertex_stage: wgpu::ProgrammableStageDescriptor {
-                    module: &vert_shader,
+                    module: vert_shader,
                     entry_point: "main",
                 },
                 fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
-                    module: &frag_shader,
+                    module: frag_shader,
                     entry_point: "main",
                 }),
                 rasterization_state: Some(wgpu::RasterizationStateDescriptor {
                     front_face: wgpu::FrontFace::Cw,
-                    cull_mode: wgpu::CullMode::Back,
+                    cull_mode,
                     depth_bias: 0,
                     depth_bias_slope_scale: 0.0,
                     depth_bias_clamp: 0.0,
                 }),
-                primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+                primitive_topology,
                 color_states: &[wgpu::ColorStateDescriptor {
                     format: GPU::SCREEN_FORMAT,
                     color_blend: wgpu::BlendDescriptor::REPLACE,
@@ -82,15 +185,32 @@ impl ShapeRenderPass {
                     stencil_write_mask: 0,
                 }),
                 vertex_state: wgpu::VertexStateDescriptor {
-                    index_format: wgpu::IndexFormat::Uint16,
+                    index_format,
                     vertex_buffers: &[Vertex::descriptor()],
                 },
                 sample_count: 1,
                 sample_mask: !0,
                 alpha_to_coverage_enabled: false,
-            });
+            })
+    }
 
-        Ok(Self { pipeline })
+    pub fn render_mode(&self) -> ShapeRenderMode {
+        self.render_mode
+    }
+
+    pub fn set_render_mode(&mut self, render_mode: ShapeRenderMode) {
+        self.render_mode = render_mode;
+    }
+
+    // Lines are never culled, so wireframe does not need a no-cull variant.
+    fn pipeline(&self, no_cull: bool) -> &wgpu::RenderPipeline {
+        match (self.render_mode, no_cull) {
+            (ShapeRenderMode::Textured, false) => &self.textured_pipeline,
+            (ShapeRenderMode::Textured, true) => &self.textured_no_cull_pipeline,
+            (ShapeRenderMode::FlatColor, false) => &self.flat_pipeline,
+            (ShapeRenderMode::FlatColor, true) => &self.flat_no_cull_pipeline,
+            (ShapeRenderMode::Wireframe, _) => &self.wireframe_pipeline,
+        }
     }
 
     pub fn draw<'a>(
@@ -104,7 +224,6 @@ impl ShapeRenderPass {
         assert_ne!(LocalGroup::ShapeChunk.index(), Group::Atmosphere.index());
         assert_ne!(LocalGroup::ShapeBlock.index(), Group::Globals.index());
         assert_ne!(LocalGroup::ShapeBlock.index(), Group::Atmosphere.index());
-        rpass.set_pipeline(&self.pipeline);
         rpass.set_bind_group(Group::Globals.index(), globals_buffer.bind_group(), &[]);
         rpass.set_bind_group(
             Group::Atmosphere.index(),
@@ -114,19 +233,32 @@ impl ShapeRenderPass {
 
         for block in shape_instance_buffer.blocks.values() {
             let chunk = shape_instance_buffer.chunk_man.chunk(block.chunk_id());
+            rpass.set_pipeline(self.pipeline(block.no_cull()));
 
             // FIXME: reorganize blocks by chunk so that we can avoid thrashing this bind group
             rpass.set_bind_group(LocalGroup::ShapeChunk.index(), chunk.bind_group(), &[]);
             rpass.set_bind_group(LocalGroup::ShapeBlock.index(), block.bind_group(), &[]);
             rpass.set_vertex_buffer(0, &chunk.vertex_buffer(), 0, 0);
+            if self.render_mode == ShapeRenderMode::Wireframe {
+                rpass.set_index_buffer(&self.edge_index_buffer, 0, 0);
+            }
             for i in 0..block.len() {
                 //rpass.draw_indirect(block.command_buffer(), i as u64);
                 let cmd = block.command_buffer_scratch[i];
-                #[allow(clippy::range_plus_one)]
-                rpass.draw(
-                    cmd.first_vertex..cmd.first_vertex + cmd.vertex_count,
-                    i as u32..i as u32 + 1,
-                );
+                if self.render_mode == ShapeRenderMode::Wireframe {
+                    #[allow(clippy::range_plus_one)]
+                    rpass.draw_indexed(
+                        0..cmd.vertex_count * 2,
+                        cmd.first_vertex as i32,
+                        i as u32..i as u32 + 1,
+                    );
+                } else {
+                    #[allow(clippy::range_plus_one)]
+                    rpass.draw(
+                        cmd.first_vertex..cmd.first_vertex + cmd.vertex_count,
+                        i as u32..i as u32 + 1,
+                    );
+                }
             }
         }
         rpass
@@ -146,13 +278,32 @@ mod tests {
         let globals_buffer = GlobalParametersBuffer::new(gpu.device())?;
         let inst_man = ShapeInstanceBuffer::new(gpu.device())?;
 
-        let _ = ShapeRenderPass::new(
+        let mut pass = ShapeRenderPass::new(
             &gpu,
             &globals_buffer.borrow(),
             &atmosphere_buffer.borrow(),
             &inst_man.borrow(),
         )?;
+        assert_eq!(pass.render_mode(), ShapeRenderMode::Textured);
+        for &mode in &[
+            ShapeRenderMode::FlatColor,
+            ShapeRenderMode::Wireframe,
+            ShapeRenderMode::Textured,
+        ] {
+            pass.set_render_mode(mode);
+            assert_eq!(pass.render_mode(), mode);
+        }
 
         Ok(())
     }
+
+    #[test]
+    fn it_walks_every_triangle_edge() {
+        assert_eq!(
+            make_edge_indices(6),
+            vec![0, 1, 1, 2, 2, 0, 3, 4, 4, 5, 5, 3]
+        );
+        // Any shape draw must stay inside the shared buffer.
+        assert!(make_edge_indices(MAX_SHAPE_VERTEX_COUNT).len() >= MAX_SHAPE_VERTEX_COUNT * 2);
+    }
 }
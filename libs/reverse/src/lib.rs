@@ -15,7 +15,7 @@
 #![allow(clippy::transmute_ptr_to_ptr)]
 
 use ansi::{ansi, Color};
-use std::mem;
+use std::{fmt, mem};
 
 pub fn n2h(n: u8) -> char {
     match n {
@@ -58,10 +58,32 @@ pub fn b2b(b: u8, v: &mut Vec<char>) {
     }
 }
 
+/// Push every byte of bs as hex, with sep between each byte.
+pub fn bytes_to_hex(bs: &[u8], sep: char, v: &mut Vec<char>) {
+    v.reserve(bs.len() * 3);
+    for (i, &b) in bs.iter().enumerate() {
+        if i > 0 {
+            v.push(sep);
+        }
+        b2h(b, v);
+    }
+}
+
+/// Push every byte of bs as binary, with sep between each byte.
+pub fn bytes_to_bin(bs: &[u8], sep: char, v: &mut Vec<char>) {
+    v.reserve(bs.len() * 10);
+    for (i, &b) in bs.iter().enumerate() {
+        if i > 0 {
+            v.push(sep);
+        }
+        b2b(b, v);
+    }
+}
+
 pub fn bs2s(bs: &[u8]) -> String {
     let mut v = Vec::new();
-    for &b in bs.iter() {
-        b2h(b, &mut v);
+    bytes_to_hex(bs, ' ', &mut v);
+    if !bs.is_empty() {
         v.push(' ');
     }
     v.iter().collect::<String>()
@@ -188,12 +210,75 @@ impl Section {
     // }
 }
 
-#[allow(clippy::cognitive_complexity)]
+/// Routes ansi escapes into a formatted line, or drops them entirely in plain
+/// mode so that dumps can be piped to a file without control codes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Escape {
+    plain: bool,
+}
+
+impl Escape {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_plain(mut self, plain: bool) -> Self {
+        self.plain = plain;
+        self
+    }
+
+    pub fn is_plain(&self) -> bool {
+        self.plain
+    }
+
+    pub fn put<T: fmt::Display>(&self, escape: T, v: &mut Vec<char>) {
+        if self.plain {
+            return;
+        }
+        v.extend(escape.to_string().chars());
+    }
+
+    /// Strip the escapes from an already formatted line when in plain mode.
+    pub fn filter(&self, line: &str) -> String {
+        if !self.plain {
+            return line.to_owned();
+        }
+        let mut out = String::with_capacity(line.len());
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            if c != '\x1B' {
+                out.push(c);
+                continue;
+            }
+            // CSI sequences run through the first byte in @..~.
+            if chars.next() == Some('[') {
+                for c in &mut chars {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
 pub fn format_sections(
     code: &[u8],
     sections: &[Section],
     tags: &mut Vec<Tag>,
     mode: &ShowMode,
+) -> Vec<String> {
+    format_sections_with(code, sections, tags, mode, &Escape::new())
+}
+
+#[allow(clippy::cognitive_complexity)]
+pub fn format_sections_with(
+    code: &[u8],
+    sections: &[Section],
+    tags: &mut Vec<Tag>,
+    mode: &ShowMode,
+    esc: &Escape,
 ) -> Vec<String> {
     // Assert that sections tightly abut.
     // let mut next_offset = 0;
@@ -225,14 +310,14 @@ pub fn format_sections(
         ShowMode::AllOneLine => {
             let mut line: Vec<char> = Vec::new();
             for section in sections {
-                accumulate_section(code, section, tags, &mut line);
+                accumulate_section(code, section, tags, esc, &mut line);
             }
             out.push(line.iter().collect::<String>());
         }
         ShowMode::AllPerLine => {
             for section in sections {
                 let mut line: Vec<char> = Vec::new();
-                accumulate_section(code, section, tags, &mut line);
+                accumulate_section(code, section, tags, esc, &mut line);
                 out.push(line.iter().collect::<String>());
             }
         }
@@ -240,7 +325,7 @@ pub fn format_sections(
             for section in sections {
                 if let SectionKind::Unknown = section.kind {
                     let mut line: Vec<char> = Vec::new();
-                    accumulate_section(code, section, tags, &mut line);
+                    accumulate_section(code, section, tags, esc, &mut line);
                     out.push(line.iter().collect::<String>());
                 }
             }
@@ -249,15 +334,15 @@ pub fn format_sections(
             for (i, section) in sections.iter().enumerate() {
                 if let SectionKind::Unknown = section.kind {
                     let mut line: Vec<char> = Vec::new();
-                    accumulate_section(code, section, tags, &mut line);
+                    accumulate_section(code, section, tags, esc, &mut line);
                     if i > 2 {
-                        accumulate_section(code, &sections[i - 3], tags, &mut line);
+                        accumulate_section(code, &sections[i - 3], tags, esc, &mut line);
                     }
                     if i > 1 {
-                        accumulate_section(code, &sections[i - 2], tags, &mut line);
+                        accumulate_section(code, &sections[i - 2], tags, esc, &mut line);
                     }
                     if i > 0 {
-                        accumulate_section(code, &sections[i - 1], tags, &mut line);
+                        accumulate_section(code, &sections[i - 1], tags, esc, &mut line);
                     }
                     out.push(line.iter().collect::<String>());
                 }
@@ -269,7 +354,7 @@ pub fn format_sections(
                     if section.length > 0 && code[section.offset] == 0xFC {
                         let mut line: Vec<char> = Vec::new();
                         //accumulate_section(code, section, tags, &mut line);
-                        accumulate_facet_section(code, section, &mut line);
+                        accumulate_facet_section(code, section, esc, &mut line);
                         out.push(line.iter().collect::<String>());
                     }
                 }
@@ -289,20 +374,20 @@ pub fn format_sections(
                             line.push('|');
                             line.push(' ');
                             if k == 0xFC {
-                                accumulate_facet_section(code, &sections[i - 1], &mut line);
+                                accumulate_facet_section(code, &sections[i - 1], esc, &mut line);
                             } else {
-                                accumulate_section(code, &sections[i - 1], tags, &mut line)
+                                accumulate_section(code, &sections[i - 1], tags, esc, &mut line)
                             }
-                            accumulate_section(code, &sections[i], tags, &mut line);
+                            accumulate_section(code, &sections[i], tags, esc, &mut line);
                             out.push(line.iter().collect::<String>());
                         } else {
                             line.push('1');
                             line.push('|');
                             line.push(' ');
                             if k == 0xFC {
-                                accumulate_facet_section(code, &sections[i - 1], &mut line);
+                                accumulate_facet_section(code, &sections[i - 1], esc, &mut line);
                             } else {
-                                accumulate_section(code, &sections[i - 1], tags, &mut line)
+                                accumulate_section(code, &sections[i - 1], tags, esc, &mut line)
                             }
                             out.push(line.iter().collect::<String>());
                         }
@@ -315,7 +400,13 @@ pub fn format_sections(
     out
 }
 
-pub fn accumulate_section(code: &[u8], section: &Section, tags: &[Tag], v: &mut Vec<char>) {
+pub fn accumulate_section(
+    code: &[u8],
+    section: &Section,
+    tags: &[Tag],
+    esc: &Escape,
+    v: &mut Vec<char>,
+) {
     if section.length == 0 {
         return;
     }
@@ -324,50 +415,45 @@ pub fn accumulate_section(code: &[u8], section: &Section, tags: &[Tag], v: &mut
         return;
     }
 
-    let mut nul = Vec::new();
-    let n = &mut nul;
-
     let section_tags = find_tags_in_section(section, tags);
     if let Some(t) = section_tags.first() {
         if t.offset == section.offset {
-            ansi().underline().put(tgt(v, n));
+            esc.put(ansi().underline(), v);
         }
     }
 
     if section.length == 1 {
-        ansi().bg(section.color()).put(tgt(v, n));
+        esc.put(ansi().bg(section.color()), v);
         b2h(code[section.offset], v);
-        ansi().put(tgt(v, n));
+        esc.put(ansi(), v);
         v.push(' ');
         return;
     }
 
-    ansi().bg(section.color()).put(tgt(v, n));
-    b2h(code[section.offset], v);
-    v.push(' ');
-    b2h(code[section.offset + 1], v);
+    esc.put(ansi().bg(section.color()), v);
+    bytes_to_hex(&code[section.offset..section.offset + 2], ' ', v);
     //    v.push('_');
     //    v.push('_');
-    ansi().put(tgt(v, n));
-    ansi().fg(section.color()).put(tgt(v, n));
+    esc.put(ansi(), v);
+    esc.put(ansi().fg(section.color()), v);
     let mut off = section.offset + 2;
     for &b in &code[section.offset + 2..section.offset + section.length] {
         // Push any tag closers.
         for tag in section_tags.iter() {
             if tag.offset + tag.length == off {
                 if let TagKind::RelocatedCall(ref target) = &tag.kind {
-                    ansi().put(tgt(v, n));
+                    esc.put(ansi(), v);
                     v.push('(');
-                    ansi().fg(Color::Red).put(tgt(v, n));
+                    esc.put(ansi().fg(Color::Red), v);
                     for c in target.chars() {
                         v.push(c)
                     }
-                    ansi().put(tgt(v, n));
+                    esc.put(ansi(), v);
                     v.push(')');
                     v.push(' ');
                 }
-                ansi().put(tgt(v, n));
-                ansi().fg(section.color()).put(tgt(v, n));
+                esc.put(ansi(), v);
+                esc.put(ansi().fg(section.color()), v);
             }
         }
         v.push(' ');
@@ -375,55 +461,41 @@ pub fn accumulate_section(code: &[u8], section: &Section, tags: &[Tag], v: &mut
         for tag in section_tags.iter() {
             if tag.offset == off {
                 match &tag.kind {
-                    TagKind::RelocatedCall(_) => ansi().dimmed().put(tgt(v, n)),
-                    TagKind::RelocatedRef => ansi().bg(Color::BrightRed).bold().put(tgt(v, n)),
-                    TagKind::RelocationTarget => ansi()
-                        .fg(Color::BrightMagenta)
-                        .strike_through()
-                        .put(tgt(v, n)),
+                    TagKind::RelocatedCall(_) => esc.put(ansi().dimmed(), v),
+                    TagKind::RelocatedRef => esc.put(ansi().bg(Color::BrightRed).bold(), v),
+                    TagKind::RelocationTarget => {
+                        esc.put(ansi().fg(Color::BrightMagenta).strike_through(), v)
+                    }
                 };
             }
         }
         b2h(b, v);
         off += 1;
     }
-    ansi().put(tgt(v, n));
+    esc.put(ansi(), v);
     v.push(' ');
 }
 
-const COLORIZE: bool = true;
-
-fn tgt<'a>(x: &'a mut Vec<char>, y: &'a mut Vec<char>) -> &'a mut Vec<char> {
-    if COLORIZE {
-        return x;
-    }
-    y
-}
-
-fn accumulate_facet_section(code: &[u8], section: &Section, line: &mut Vec<char>) {
+fn accumulate_facet_section(code: &[u8], section: &Section, esc: &Escape, line: &mut Vec<char>) {
     if section.offset + section.length >= code.len() {
         println!("OVERFLOW at section: {:?}", section);
         return;
     }
-    let mut nul = Vec::new();
-    let n = &mut nul;
-
-    ansi().bg(section.color()).put(tgt(line, n));
+    esc.put(ansi().bg(section.color()), line);
     b2h(code[section.offset], line);
-    ansi().put(tgt(line, n));
+    esc.put(ansi(), line);
 
-    ansi().fg(section.color()).put(tgt(line, n));
+    esc.put(ansi().fg(section.color()), line);
     line.push(' ');
-    b2b(code[section.offset + 1], line);
-    line.push('_');
-    b2b(code[section.offset + 2], line);
+    bytes_to_bin(&code[section.offset + 1..section.offset + 3], '_', line);
 
-    for &b in &code[section.offset + 3..section.offset + section.length] {
+    let rest = &code[section.offset + 3..section.offset + section.length];
+    if !rest.is_empty() {
         line.push(' ');
-        b2h(b, line);
+        bytes_to_hex(rest, ' ', line);
     }
 
-    ansi().put(tgt(line, n));
+    esc.put(ansi(), line);
     line.push(' ');
 }
 
@@ -490,3 +562,66 @@ pub fn get_all_tags(pe: &peff::PE) -> Vec<Tag> {
     }
     tags
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batch_hex_matches_per_byte() {
+        let bs = [0x00u8, 0x0F, 0xA5, 0xFF, 0x10];
+        let mut expect = Vec::new();
+        for (i, &b) in bs.iter().enumerate() {
+            if i > 0 {
+                expect.push(':');
+            }
+            b2h(b, &mut expect);
+        }
+        let mut batch = Vec::new();
+        bytes_to_hex(&bs, ':', &mut batch);
+        assert_eq!(batch, expect);
+        assert_eq!(bs2s(&bs), "00 0F A5 FF 10 ");
+        assert_eq!(bs2s(&[]), "");
+    }
+
+    #[test]
+    fn batch_bin_matches_per_byte() {
+        let mut expect = Vec::new();
+        b2b(0xA5, &mut expect);
+        expect.push('_');
+        b2b(0x0F, &mut expect);
+        let mut batch = Vec::new();
+        bytes_to_bin(&[0xA5, 0x0F], '_', &mut batch);
+        assert_eq!(batch, expect);
+        assert_eq!(batch.iter().collect::<String>(), "1010_0101_0000_1111");
+    }
+
+    #[test]
+    fn plain_mode_has_no_escapes() {
+        let code = [0xFCu8, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07];
+        let sections = vec![Section::new(0x00FC, 0, 4), Section::unknown(4, 4)];
+        let plain = Escape::new().with_plain(true);
+        for mode in &[
+            ShowMode::AllOneLine,
+            ShowMode::AllPerLine,
+            ShowMode::UnknownFacet,
+        ] {
+            for line in format_sections_with(&code, &sections, &mut vec![], mode, &plain) {
+                assert!(!line.contains('\x1B'), "escape in: {:?}", line);
+            }
+        }
+        let lines =
+            format_sections_with(&code, &sections, &mut vec![], &ShowMode::AllOneLine, &plain);
+        assert_eq!(lines, vec!["FC 01 02 03 04 05 06 07 "]);
+    }
+
+    #[test]
+    fn plain_mode_filters_formatted_lines() {
+        let line = "\x1B[31;1mTrailer\x1B[0m: 0018 \x1B[4m12\x1B[0m";
+        assert_eq!(Escape::new().filter(line), line);
+        assert_eq!(
+            Escape::new().with_plain(true).filter(line),
+            "Trailer: 0018 12"
+        );
+    }
+}
@@ -0,0 +1,166 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use crate::{Instr, RawShape};
+use ansi::{ansi, Color};
+use std::cmp;
+
+// One difference between two shapes, in terms of the instructions in each.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SectionDiff {
+    Removed {
+        left_offset: usize,
+        magic: &'static str,
+    },
+    Added {
+        right_offset: usize,
+        magic: &'static str,
+    },
+    Changed {
+        left_offset: usize,
+        right_offset: usize,
+        magic: &'static str,
+    },
+}
+
+impl RawShape {
+    // Align the two instruction streams by kind, then compare the raw bytes of
+    // each aligned pair. Alignment is a weighted longest-common-subsequence over
+    // the instructions, preferring byte-identical pairs over pairs that merely
+    // share a kind, so that an insertion only shows up as an insertion, rather
+    // than shifting every following instruction into a change.
+    pub fn diff(&self, other: &RawShape) -> Vec<SectionDiff> {
+        let left = &self.instrs;
+        let right = &other.instrs;
+        let n = left.len();
+        let m = right.len();
+        let weight = |i: usize, j: usize| {
+            if left[i].magic() != right[j].magic() {
+                None
+            } else if self.instr_bytes(&left[i]) == other.instr_bytes(&right[j]) {
+                Some(2)
+            } else {
+                Some(1)
+            }
+        };
+
+        // score[i * (m + 1) + j] is the best alignment score of left[i..] and right[j..].
+        let mut score = vec![0u32; (n + 1) * (m + 1)];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                let skip = cmp::max(score[(i + 1) * (m + 1) + j], score[i * (m + 1) + j + 1]);
+                score[i * (m + 1) + j] = match weight(i, j) {
+                    Some(w) => cmp::max(skip, score[(i + 1) * (m + 1) + j + 1] + w),
+                    None => skip,
+                };
+            }
+        }
+
+        let mut diffs = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < n && j < m {
+            let here = score[i * (m + 1) + j];
+            match weight(i, j) {
+                Some(w) if here == score[(i + 1) * (m + 1) + j + 1] + w => {
+                    if w == 1 {
+                        diffs.push(SectionDiff::Changed {
+                            left_offset: left[i].at_offset(),
+                            right_offset: right[j].at_offset(),
+                            magic: left[i].magic(),
+                        });
+                    }
+                    i += 1;
+                    j += 1;
+                }
+                _ if here == score[(i + 1) * (m + 1) + j] => {
+                    diffs.push(SectionDiff::Removed {
+                        left_offset: left[i].at_offset(),
+                        magic: left[i].magic(),
+                    });
+                    i += 1;
+                }
+                _ => {
+                    diffs.push(SectionDiff::Added {
+                        right_offset: right[j].at_offset(),
+                        magic: right[j].magic(),
+                    });
+                    j += 1;
+                }
+            }
+        }
+        for instr in &left[i..] {
+            diffs.push(SectionDiff::Removed {
+                left_offset: instr.at_offset(),
+                magic: instr.magic(),
+            });
+        }
+        for instr in &right[j..] {
+            diffs.push(SectionDiff::Added {
+                right_offset: instr.at_offset(),
+                magic: instr.magic(),
+            });
+        }
+        diffs
+    }
+
+    fn instr_bytes(&self, instr: &Instr) -> &[u8] {
+        let start = cmp::min(instr.at_offset(), self.pe.code.len());
+        let end = cmp::min(instr.at_offset() + instr.size(), self.pe.code.len());
+        &self.pe.code[start..end]
+    }
+
+    fn instr_at(&self, offset: usize) -> Option<&Instr> {
+        self.bytes_to_index(offset)
+            .ok()
+            .map(|index| &self.instrs[index])
+    }
+}
+
+pub fn format_diff(diffs: &[SectionDiff], left: &RawShape, right: &RawShape) -> String {
+    let show = |shape: &RawShape, offset: usize| {
+        shape
+            .instr_at(offset)
+            .map(Instr::show)
+            .unwrap_or_else(|| format!("@{:04X} ???", offset))
+    };
+    let mut out = Vec::new();
+    for diff in diffs {
+        out.push(match diff {
+            SectionDiff::Removed { left_offset, .. } => format!(
+                "{}-{} {}",
+                ansi().fg(Color::Red).bold(),
+                ansi(),
+                show(left, *left_offset)
+            ),
+            SectionDiff::Added { right_offset, .. } => format!(
+                "{}+{} {}",
+                ansi().fg(Color::Green).bold(),
+                ansi(),
+                show(right, *right_offset)
+            ),
+            SectionDiff::Changed {
+                left_offset,
+                right_offset,
+                ..
+            } => format!(
+                "{}~{} {}\n  {}",
+                ansi().fg(Color::Yellow).bold(),
+                ansi(),
+                show(left, *left_offset),
+                show(right, *right_offset)
+            ),
+        });
+    }
+    out.join("\n")
+}
@@ -12,7 +12,7 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
-use crate::instr::read_name;
+use crate::instr::{read_name, ShError};
 use ansi::ansi;
 use bitflags::bitflags;
 use failure::{bail, ensure, Fallible};
@@ -137,13 +137,19 @@ pub struct VertexBuf {
 impl VertexBuf {
     pub const MAGIC: u8 = 0x82;
 
+    const HEADER_SIZE: usize = 6;
+
     pub fn from_bytes_after(offset: usize, data: &[u8]) -> Fallible<Self> {
         assert_eq!(data[0], Self::MAGIC);
         assert_eq!(data[1], 0);
-        let head: &[u16] = unsafe { mem::transmute(&data[2..6]) };
-        let words: &[i16] = unsafe { mem::transmute(&data[6..]) };
+        Self::ensure_available(offset, Self::HEADER_SIZE, data.len())?;
+        let head: &[u16] = unsafe { mem::transmute(&data[2..Self::HEADER_SIZE]) };
         let nverts = head[0] as usize;
         let target_offset = head[1] as usize;
+
+        // Do not trust the count: a corrupt one would have us read far past the section.
+        Self::ensure_available(offset, Self::HEADER_SIZE + nverts * 6, data.len())?;
+        let words: &[i16] = unsafe { mem::transmute(&data[Self::HEADER_SIZE..]) };
         ensure!(
             target_offset % 8 == 0,
             "expected the vert buffer target offset to be a multiple of 8"
@@ -162,8 +168,21 @@ impl VertexBuf {
         Ok(buf)
     }
 
+    fn ensure_available(offset: usize, needed: usize, available: usize) -> Fallible<()> {
+        if needed > available {
+            return Err(ShError::Truncated {
+                section: "VertexBuf",
+                offset,
+                needed,
+                available,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
     pub fn size(&self) -> usize {
-        6 + self.verts.len() * 6
+        Self::HEADER_SIZE + self.verts.len() * 6
     }
 
     pub fn magic(&self) -> &'static str {
@@ -183,6 +202,21 @@ impl VertexBuf {
         self.verts.iter()
     }
 
+    // The vertices multiplied out of raw shape units: pass SHAPE_UNIT_TO_FEET
+    // to get feet, or 1.0 for the raw values as floats.
+    pub fn verts_scaled(&self, scale: f32) -> Vec<[f32; 3]> {
+        self.verts
+            .iter()
+            .map(|v| {
+                [
+                    f32::from(v[0]) * scale,
+                    f32::from(v[1]) * scale,
+                    f32::from(v[2]) * scale,
+                ]
+            })
+            .collect()
+    }
+
     pub fn show(&self) -> String {
         let s = self
             .verts
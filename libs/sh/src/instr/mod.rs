@@ -35,6 +35,30 @@ pub use crate::instr::{
 pub enum ShError {
     #[fail(display = "name ran off end of file")]
     NameUnending {},
+
+    #[fail(display = "byte offset {:04X} is outside of the shape", offset)]
+    OffsetOutOfRange { offset: usize },
+
+    #[fail(
+        display = "byte offset {:04X} lands inside instruction {} at {:04X}",
+        offset, index, instr_offset
+    )]
+    OffsetInsideInstruction {
+        offset: usize,
+        index: usize,
+        instr_offset: usize,
+    },
+
+    #[fail(
+        display = "{} at {:04X} needs {} bytes, but only {} remain",
+        section, offset, needed, available
+    )]
+    Truncated {
+        section: &'static str,
+        offset: usize,
+        needed: usize,
+        available: usize,
+    },
 }
 
 pub fn read_name(n: &[u8]) -> Fallible<String> {
@@ -14,19 +14,22 @@
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
 #![allow(clippy::transmute_ptr_to_ptr)]
 
+mod diff;
 mod instr;
 
+pub use crate::diff::{format_diff, SectionDiff};
 pub use crate::instr::{
     read_name, EndOfObject, EndOfShape, Facet, FacetFlags, Jump, JumpToDamage, JumpToDetail,
-    JumpToFrame, JumpToLOD, Pad1E, PtrToObjEnd, SourceRef, TextureIndex, TextureRef, Unmask,
-    Unmask4, VertexBuf, VertexNormal, X86Code, X86Message, X86Trampoline, XformUnmask,
+    JumpToFrame, JumpToLOD, Pad1E, PtrToObjEnd, ShError, SourceRef, TextureIndex, TextureRef,
+    Unmask, Unmask4, VertexBuf, VertexNormal, X86Code, X86Message, X86Trampoline, XformUnmask,
     XformUnmask4,
 };
 use ansi::{ansi, Color};
-use failure::{bail, ensure, err_msg, Fallible};
+use failure::{bail, ensure, Fallible};
 use lazy_static::lazy_static;
 use log::trace;
 use reverse::{bs2s, bs_2_i16, p2s};
+use serde_json::json;
 use std::{
     cmp,
     collections::{HashMap, HashSet},
@@ -51,6 +54,10 @@ use std::{
 
 pub const SHAPE_LOAD_BASE: u32 = 0xAA00_0000;
 
+// Vertex buffers store raw i16 coordinates; this many feet per unit puts
+// shapes at their real world size.
+pub const SHAPE_UNIT_TO_FEET: f32 = 4f32;
+
 lazy_static! {
     // Virtual instructions that have a one-byte header instead of
     static ref ONE_BYTE_MAGIC: HashSet<u8> =
@@ -863,6 +870,79 @@ macro_rules! opaque_instr {
     };
 }
 
+// Like opaque_instr, but copies the payload out of the code section so that
+// the instruction does not borrow from (or outlive) the PE it was parsed from.
+macro_rules! owned_instr {
+    ($name:ident, $magic_str: expr, $magic:expr, $size:expr) => {
+        pub struct $name {
+            pub offset: usize,
+            pub data: Vec<u8>,
+        }
+
+        impl $name {
+            pub const MAGIC: u8 = $magic;
+            pub const SIZE: usize = $size;
+
+            fn from_bytes_after(offset: usize, data: &[u8]) -> Fallible<Self> {
+                ensure!(
+                    data.len() >= Self::SIZE,
+                    "{} at {:04X} runs past end of code: {} of {} bytes",
+                    stringify!($name),
+                    offset,
+                    data.len(),
+                    Self::SIZE
+                );
+                assert_eq!(data[0], Self::MAGIC);
+                ensure!(data[1] == 0, "expected 0 in hi byte");
+                Ok(Self {
+                    offset,
+                    data: data[2..Self::SIZE].to_vec(),
+                })
+            }
+
+            fn size(&self) -> usize {
+                Self::SIZE
+            }
+
+            fn magic(&self) -> &'static str {
+                $magic_str
+            }
+
+            fn at_offset(&self) -> usize {
+                self.offset
+            }
+
+            fn show(&self) -> String {
+                format!(
+                    "@{:04X} {}{}{}: {}{:02X} 00{}| {}{}{}",
+                    self.offset,
+                    ansi().fg(Color::Red).bold(),
+                    stringify!($name),
+                    ansi(),
+                    ansi().fg(Color::Red).bold(),
+                    Self::MAGIC,
+                    ansi(),
+                    ansi().fg(Color::Red),
+                    bs2s(&self.data),
+                    ansi()
+                )
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(
+                    f,
+                    "{} @{:04X}: {}",
+                    stringify!($name),
+                    self.offset,
+                    bs2s(&self.data),
+                )
+            }
+        }
+    };
+}
+
 opaque_instr!(Unk4E, "4E", 0x4E, 2); // 6 instances
 opaque_instr!(Unk08, "08", 0x08, 4); // 7 instances
 opaque_instr!(UnkB2, "B2", 0xB2, 2); // 9 instances
@@ -890,10 +970,10 @@ opaque_instr!(UnkD0, "D0", 0xD0, 4);
 opaque_instr!(UnkD2, "D2", 0xD2, 8);
 opaque_instr!(UnkDA, "DA", 0xDA, 4);
 opaque_instr!(UnkDC, "DC", 0xDC, 12);
-opaque_instr!(UnkE4, "E4", 0xE4, 20);
+owned_instr!(UnkE4, "E4", 0xE4, 20);
 opaque_instr!(UnkE6, "E6", 0xE6, 10);
 opaque_instr!(UnkE8, "E8", 0xE8, 6);
-opaque_instr!(UnkEA, "EA", 0xEA, 8);
+owned_instr!(UnkEA, "EA", 0xEA, 8);
 opaque_instr!(UnkEE, "EE", 0xEE, 2);
 
 #[derive(Debug)]
@@ -1043,7 +1123,82 @@ macro_rules! impl_for_all_instr {
     };
 }
 
+macro_rules! impl_instr_kind {
+    ($self:ident, $($variant:ident),*) => {
+        match $self {
+            $(Instr::$variant(_) => stringify!($variant)),*
+        }
+    };
+}
+
 impl Instr {
+    pub fn kind(&self) -> &'static str {
+        impl_instr_kind!(
+            self,
+            Header,
+            PtrToObjEnd,
+            SourceRef,
+            Jump,
+            JumpToDamage,
+            JumpToDetail,
+            JumpToFrame,
+            JumpToLOD,
+            TextureRef,
+            TextureIndex,
+            VertexBuf,
+            Facet,
+            VertexNormal,
+            Unmask,
+            Unmask4,
+            XformUnmask,
+            XformUnmask4,
+            Unk06,
+            Unk08,
+            Unk0C,
+            Unk0E,
+            Unk10,
+            Unk2E,
+            Unk3A,
+            Unk44,
+            Unk46,
+            Unk4E,
+            Unk66,
+            Unk68,
+            Unk6C,
+            Unk50,
+            Unk72,
+            Unk74,
+            Unk76,
+            Unk78,
+            Unk7A,
+            Unk96,
+            UnkB2,
+            UnkB8,
+            UnkCA,
+            UnkCE,
+            UnkD0,
+            UnkD2,
+            UnkDA,
+            UnkDC,
+            UnkE4,
+            UnkE6,
+            UnkE8,
+            UnkEA,
+            UnkEE,
+            Pad1E,
+            Unk38,
+            UnkBC,
+            TrailerUnknown,
+            X86Code,
+            X86Trampoline,
+            X86Message,
+            UnknownUnknown,
+            UnknownData,
+            EndOfObject,
+            EndOfShape
+        )
+    }
+
     pub fn show(&self) -> String {
         impl_for_all_instr!(self, show)
     }
@@ -1083,28 +1238,106 @@ impl Instr {
             _ => bail!("not a facet instruction"),
         })
     }
+
+    // True for the sections that we keep around as raw bytes because we do
+    // not yet understand them.
+    pub fn is_unknown(&self) -> bool {
+        match self {
+            Instr::TrailerUnknown(_) | Instr::UnknownUnknown(_) | Instr::UnknownData(_) => true,
+            _ => false,
+        }
+    }
+}
+
+// How much of a shape file we are able to make sense of.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ParseCoverage {
+    // Every section decoded and all embedded code disassembled.
+    Full,
+    // The shape loads, but some sections are still opaque bytes.
+    Partial,
+    // The shape did not load at all. Note that an unknown opcode in the
+    // embedded x86 lands here, as we cannot find the next section without it.
+    Failed,
+}
+
+impl ParseCoverage {
+    pub fn classify(data: &[u8]) -> Self {
+        match RawShape::from_bytes(data) {
+            Ok(shape) => shape.coverage(),
+            Err(_) => ParseCoverage::Failed,
+        }
+    }
 }
 
+// If an instruction would extend past the end of the code, we are not looking
+// at an instruction at all; close out the shape with a trailer instead of
+// letting the section over-read into the trampolines.
 macro_rules! consume_instr {
     ($name:ident, $pe:ident, $offset:ident, $end_offset:ident, $instrs:ident) => {{
         let instr = $name::from_bytes(*$offset, &$pe.code[..$end_offset])?;
-        *$offset += instr.size();
-        $instrs.push(Instr::$name(instr));
+        if *$offset + instr.size() > $end_offset {
+            RawShape::close_on_trailer($offset, $pe, $end_offset, $instrs)?;
+        } else {
+            *$offset += instr.size();
+            $instrs.push(Instr::$name(instr));
+        }
     }};
 }
 
 macro_rules! consume_instr2 {
     ($name:ident, $pe:ident, $offset:ident, $end_offset:ident, $instrs:ident) => {{
         let instr = $name::from_bytes_after(*$offset, &$pe.code[*$offset..$end_offset])?;
-        *$offset += instr.size();
-        $instrs.push(Instr::$name(instr));
+        if *$offset + instr.size() > $end_offset {
+            RawShape::close_on_trailer($offset, $pe, $end_offset, $instrs)?;
+        } else {
+            *$offset += instr.size();
+            $instrs.push(Instr::$name(instr));
+        }
+    }};
+}
+
+// Fixed size instructions know up front whether they fit, so check that before
+// decoding instead of failing the whole parse on a short slice.
+macro_rules! consume_sized_instr {
+    ($name:ident, $pe:ident, $offset:ident, $end_offset:ident, $instrs:ident) => {{
+        if *$offset + $name::SIZE > $end_offset {
+            RawShape::close_on_trailer($offset, $pe, $end_offset, $instrs)?;
+        } else {
+            consume_instr2!($name, $pe, $offset, $end_offset, $instrs);
+        }
     }};
 }
 
+// Summary counts over a shape's instruction stream.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ShapeStats {
+    pub vertex_count: usize,
+    pub facet_count: usize,
+    // Distinct texture filenames referenced.
+    pub texture_count: usize,
+    pub has_code: bool,
+    // Distinct game detail levels branched on; 1 if the shape never branches.
+    pub detail_levels: usize,
+    // The most frames in any animation; 1 if the shape is not animated.
+    pub frame_count: usize,
+}
+
+// A 32bit pointer in the shape's code that the PE asks the loader to fix up.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Relocation {
+    // Offset of the pointer from the start of the code.
+    pub offset: u32,
+    // The address stored at that offset, after relocation to SHAPE_LOAD_BASE.
+    pub target: u32,
+    // The import the pointer refers to, if it lands on a trampoline or thunk.
+    pub thunk_name: Option<String>,
+}
+
 pub struct RawShape {
     pub instrs: Vec<Instr>,
     pub trampolines: Vec<X86Trampoline>,
-    offset_map: HashMap<usize, usize>,
+    offset_table: Vec<(usize, usize)>,
     pub pe: peff::PE,
 }
 
@@ -1129,29 +1362,50 @@ impl RawShape {
         // References inside shape are relative byte offsets. We map these
         // to absolute byte offsets using the instruction offset and size
         // which we can look up in the following table to get the instr
-        // index we need to jump to.
-        let offset_map: HashMap<usize, usize> = instrs
+        // index we need to jump to. The table is sorted by offset so that
+        // we can also tell which instruction an offset lands inside of.
+        let mut offset_table = instrs
             .iter()
             .enumerate()
             .map(|(i, instr)| (instr.at_offset(), i))
-            .collect();
+            .collect::<Vec<_>>();
+        offset_table.sort();
 
         Ok(RawShape {
             instrs,
             trampolines,
-            offset_map,
+            offset_table,
             pe,
         })
     }
 
     pub fn bytes_to_index(&self, absolute_byte_offset: usize) -> Fallible<usize> {
         // FIXME: we need to handle ERRATA here?
-        Ok(*self.offset_map.get(&absolute_byte_offset).ok_or_else(|| {
-            err_msg(format!(
-                "absolute byte offset {:04X} does not point to an instruction",
-                absolute_byte_offset
-            ))
-        })?)
+        match self
+            .offset_table
+            .binary_search_by_key(&absolute_byte_offset, |&(offset, _)| offset)
+        {
+            Ok(pos) => Ok(self.offset_table[pos].1),
+            Err(0) => Err(ShError::OffsetOutOfRange {
+                offset: absolute_byte_offset,
+            }
+            .into()),
+            Err(pos) => {
+                let (instr_offset, index) = self.offset_table[pos - 1];
+                if absolute_byte_offset >= instr_offset + self.instrs[index].size() {
+                    return Err(ShError::OffsetOutOfRange {
+                        offset: absolute_byte_offset,
+                    }
+                    .into());
+                }
+                Err(ShError::OffsetInsideInstruction {
+                    offset: absolute_byte_offset,
+                    index,
+                    instr_offset,
+                }
+                .into())
+            }
+        }
     }
 
     pub fn all_textures(&self) -> HashSet<String> {
@@ -1293,10 +1547,10 @@ impl RawShape {
             UnkD2::MAGIC => consume_instr2!(UnkD2, pe, offset, end_offset, instrs),
             UnkDA::MAGIC => consume_instr2!(UnkDA, pe, offset, end_offset, instrs),
             UnkDC::MAGIC => consume_instr2!(UnkDC, pe, offset, end_offset, instrs),
-            UnkE4::MAGIC => consume_instr2!(UnkE4, pe, offset, end_offset, instrs),
+            UnkE4::MAGIC => consume_sized_instr!(UnkE4, pe, offset, end_offset, instrs),
             UnkE6::MAGIC => consume_instr2!(UnkE6, pe, offset, end_offset, instrs),
             UnkE8::MAGIC => consume_instr2!(UnkE8, pe, offset, end_offset, instrs),
-            UnkEA::MAGIC => consume_instr2!(UnkEA, pe, offset, end_offset, instrs),
+            UnkEA::MAGIC => consume_sized_instr!(UnkEA, pe, offset, end_offset, instrs),
             UnkEE::MAGIC => consume_instr2!(UnkEE, pe, offset, end_offset, instrs),
 
             Unk06::MAGIC => consume_instr!(Unk06, pe, offset, end_offset, instrs),
@@ -1365,12 +1619,26 @@ impl RawShape {
                     }
                 }
 
+                let mut object_end = None;
+                if let Some(&Instr::PtrToObjEnd(ref end_ptr)) = find_first_instr(0xF2, &instrs) {
+                    object_end = Some(end_ptr.end_byte_offset());
+                }
+
                 let remaining = &pe.code[*offset..end_offset];
-                if remaining.len() < 18 {
+                if remaining.len() < EndOfObject::SIZE {
                     // If we're just out of space... :shrug:
-                    let unk = TrailerUnknown::from_bytes_after(*offset, remaining)?;
-                    instrs.push(Instr::TrailerUnknown(unk));
-                    *offset = end_offset;
+                    Self::close_on_trailer(offset, pe, end_offset, instrs)?;
+                } else if object_end == Some(*offset) {
+                    // The F2 points at the block that ends the object. This is the
+                    // same 18 byte block as below, without the trailing zeros when
+                    // the 12321 follows. Nothing past it is drawn, so stop here.
+                    let obj_end =
+                        EndOfObject::from_bytes_after(*offset, &remaining[..EndOfObject::SIZE])?;
+                    instrs.push(Instr::EndOfObject(obj_end));
+                    *offset += EndOfObject::SIZE;
+                    if *offset < end_offset {
+                        Self::close_on_trailer(offset, pe, end_offset, instrs)?;
+                    }
                 } else if remaining[16] == 0 && remaining[17] == 0 {
                     // Cases were the block should stop rendering look more or less like:
                     // 00 .. .. .. .. .. .. .. .. .. .. .. .. .. .. .. 00 00
@@ -1381,9 +1649,7 @@ impl RawShape {
                     *offset += EndOfObject::SIZE;
                 } else {
                     // Other instances of 0 we expect to end the file.
-                    let unk = TrailerUnknown::from_bytes_after(*offset, remaining)?;
-                    instrs.push(Instr::TrailerUnknown(unk));
-                    *offset = end_offset;
+                    Self::close_on_trailer(offset, pe, end_offset, instrs)?;
                 }
             }
 
@@ -1405,6 +1671,19 @@ impl RawShape {
         Ok(())
     }
 
+    // Consume everything up to end_offset as an opaque trailer.
+    fn close_on_trailer(
+        offset: &mut usize,
+        pe: &peff::PE,
+        end_offset: usize,
+        instrs: &mut Vec<Instr>,
+    ) -> Fallible<()> {
+        let unk = TrailerUnknown::from_bytes_after(*offset, &pe.code[*offset..end_offset])?;
+        instrs.push(Instr::TrailerUnknown(unk));
+        *offset = end_offset;
+        Ok(())
+    }
+
     // Map an offset in bytes from the beginning of the virtual instruction stream
     // to an offset into the virtual instructions.
     pub fn map_absolute_offset_to_instr_offset(&self, abs_offset: usize) -> Fallible<usize> {
@@ -1445,6 +1724,250 @@ impl RawShape {
         bail!("no trampoline with name: {}", name);
     }
 
+    pub fn coverage(&self) -> ParseCoverage {
+        if self.instrs.iter().any(Instr::is_unknown) {
+            ParseCoverage::Partial
+        } else {
+            ParseCoverage::Full
+        }
+    }
+
+    pub fn stats(&self) -> ShapeStats {
+        let mut stats = ShapeStats::default();
+        let mut textures = HashSet::new();
+        let mut levels = HashSet::new();
+        let mut frame_count = 1;
+        for instr in &self.instrs {
+            match instr {
+                Instr::VertexBuf(buf) => stats.vertex_count += buf.verts.len(),
+                Instr::Facet(_) => stats.facet_count += 1,
+                Instr::TextureRef(tex) => {
+                    textures.insert(tex.filename.as_str());
+                }
+                Instr::X86Code(_) => stats.has_code = true,
+                Instr::JumpToDetail(detail) => {
+                    levels.insert(detail.level);
+                }
+                Instr::JumpToFrame(frame) => {
+                    frame_count = cmp::max(frame_count, frame.num_frames())
+                }
+                _ => {}
+            }
+        }
+        stats.texture_count = textures.len();
+        stats.detail_levels = cmp::max(1, levels.len());
+        stats.frame_count = frame_count;
+        stats
+    }
+
+    // Facets that are wound the opposite way from the rest of the shape, and so
+    // are likely to vanish under backface culling. A facet's orientation is
+    // whether its normal points away from the centroid of all vertices; we
+    // take the majority as correct, so that this works regardless of which
+    // front face convention FA used. Returns instruction indices.
+    pub fn check_winding(&self) -> Vec<usize> {
+        let signs = self.facet_orientations();
+        let outward = signs.iter().filter(|(_, out)| *out).count();
+        let majority = outward * 2 >= signs.len();
+        signs
+            .into_iter()
+            .filter(|(_, out)| *out != majority)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    // For each facet with a measurable area, the instruction index and whether
+    // the facet's right-handed normal points away from the shape's centroid.
+    // Vertex buffers are loaded into a pool in instruction order, as the
+    // renderer does, ignoring any branches.
+    fn facet_orientations(&self) -> Vec<(usize, bool)> {
+        let mut centroid = [0f64; 3];
+        let mut count = 0usize;
+        for instr in &self.instrs {
+            if let Instr::VertexBuf(buf) = instr {
+                for v in buf.vertices() {
+                    for i in 0..3 {
+                        centroid[i] += f64::from(v[i]);
+                    }
+                    count += 1;
+                }
+            }
+        }
+        if count == 0 {
+            return Vec::new();
+        }
+        for c in &mut centroid {
+            *c /= count as f64;
+        }
+
+        let mut pool: Vec<[f64; 3]> = Vec::new();
+        let mut out = Vec::new();
+        for (index, instr) in self.instrs.iter().enumerate() {
+            match instr {
+                Instr::VertexBuf(buf) => {
+                    pool.resize(buf.buffer_target_offset(), [0f64; 3]);
+                    for v in buf.vertices() {
+                        pool.push([f64::from(v[0]), f64::from(v[1]), f64::from(v[2])]);
+                    }
+                }
+                Instr::Facet(facet) => {
+                    if facet.indices.len() < 3
+                        || facet.indices.iter().any(|&i| i as usize >= pool.len())
+                    {
+                        continue;
+                    }
+                    let verts = facet
+                        .indices
+                        .iter()
+                        .map(|&i| pool[i as usize])
+                        .collect::<Vec<_>>();
+
+                    // Newell's method, so that non-planar polygons still get
+                    // a sensible normal.
+                    let mut normal = [0f64; 3];
+                    let mut center = [0f64; 3];
+                    for (j, a) in verts.iter().enumerate() {
+                        let b = verts[(j + 1) % verts.len()];
+                        normal[0] += (a[1] - b[1]) * (a[2] + b[2]);
+                        normal[1] += (a[2] - b[2]) * (a[0] + b[0]);
+                        normal[2] += (a[0] - b[0]) * (a[1] + b[1]);
+                        for i in 0..3 {
+                            center[i] += a[i] / verts.len() as f64;
+                        }
+                    }
+                    let dot = (0..3)
+                        .map(|i| normal[i] * (center[i] - centroid[i]))
+                        .sum::<f64>();
+                    if dot.abs() > std::f64::EPSILON {
+                        out.push((index, dot > 0f64));
+                    }
+                }
+                _ => {}
+            }
+        }
+        out
+    }
+
+    // The translation that each XformUnmask applies to its target vertex buffer
+    // before any animation runs, keyed by the offset of that buffer.
+    fn xform_offsets(&self) -> HashMap<usize, [i16; 3]> {
+        let mut out = HashMap::new();
+        for instr in &self.instrs {
+            match instr {
+                Instr::XformUnmask(x) => {
+                    out.insert(x.target_byte_offset(), [x.t0, x.t1, x.t2]);
+                }
+                Instr::XformUnmask4(x) => {
+                    out.insert(x.target_byte_offset(), [x.t0, x.t1, x.t2]);
+                }
+                _ => {}
+            }
+        }
+        out
+    }
+
+    // The [min, max] corners of all vertex buffers in the shape, in shape
+    // coordinates, or None if the shape has no vertices. Buffers under an xform
+    // are moved by its resting offset; rotations and animation are not applied.
+    pub fn bounding_box(&self) -> Option<[[i16; 3]; 2]> {
+        let offsets = self.xform_offsets();
+        let mut aabb: Option<[[i16; 3]; 2]> = None;
+        for instr in &self.instrs {
+            if let Instr::VertexBuf(buf) = instr {
+                let offset = offsets.get(&buf.at_offset()).cloned().unwrap_or([0i16; 3]);
+                for v in buf.vertices() {
+                    let mut p = *v;
+                    for i in 0..3 {
+                        p[i] = p[i].saturating_add(offset[i]);
+                    }
+                    let [lo, hi] = aabb.get_or_insert([p, p]);
+                    for i in 0..3 {
+                        lo[i] = lo[i].min(p[i]);
+                        hi[i] = hi[i].max(p[i]);
+                    }
+                }
+            }
+        }
+        aabb
+    }
+
+    // Every relocation in the PE, with the pointer it fixes up and the name of
+    // the engine import it points at, if any.
+    pub fn relocations(&self) -> Fallible<Vec<Relocation>> {
+        let code = &self.pe.code;
+        let mut out = Vec::new();
+        for &offset in &self.pe.relocs {
+            let start = offset as usize;
+            ensure!(
+                start + 4 <= code.len(),
+                "relocation at 0x{:04X} points past the end of the code",
+                offset
+            );
+            let mut dword = [0u8; 4];
+            dword.copy_from_slice(&code[start..start + 4]);
+            let target = u32::from_le_bytes(dword);
+            let thunk_name = if let Ok(tramp) =
+                self.lookup_trampoline_by_offset(target.wrapping_sub(SHAPE_LOAD_BASE))
+            {
+                Some(tramp.name.clone())
+            } else if let Some(thunk) = self.pe.thunks.iter().find(|t| t.vaddr == target) {
+                Some(thunk.name.clone())
+            } else {
+                self.trampolines
+                    .iter()
+                    .find(|t| t.target == target)
+                    .map(|t| t.name.clone())
+            };
+            out.push(Relocation {
+                offset,
+                target,
+                thunk_name,
+            });
+        }
+        Ok(out)
+    }
+
+    // The names of all trampolines that the embedded x86 reads from or calls,
+    // sorted and deduplicated. This is the set of engine variables and
+    // functions that the shape depends on.
+    pub fn referenced_ports(&self) -> Vec<String> {
+        let mut ports = HashSet::new();
+        for instr in &self.instrs {
+            if let Instr::X86Code(x86) = instr {
+                for x86_instr in &x86.bytecode.instrs {
+                    for operand in &x86_instr.operands {
+                        let target = match operand {
+                            i386::Operand::Memory(memref) => memref.displacement as u32,
+                            i386::Operand::Imm32s(v) => *v as u32,
+                            _ => continue,
+                        };
+                        if let Ok(tramp) =
+                            self.lookup_trampoline_by_offset(target.wrapping_sub(SHAPE_LOAD_BASE))
+                        {
+                            ports.insert(tramp.name.clone());
+                        }
+                    }
+                }
+            }
+        }
+        let mut ports = ports.into_iter().collect::<Vec<_>>();
+        ports.sort();
+        ports
+    }
+
+    // A listing of all embedded x86, with trampoline names resolved inline.
+    pub fn disassemble(&self) -> String {
+        let mut s = String::new();
+        for instr in &self.instrs {
+            if let Instr::X86Code(x86) = instr {
+                let code_offset = x86.code_offset(0) as usize;
+                s += &format!("@{:04X} X86Code:\n", instr.at_offset());
+                s += &x86.bytecode.show_relative(code_offset);
+            }
+        }
+        s
+    }
+
     pub fn has_damage_section(&self) -> bool {
         for instr in &self.instrs {
             if let Instr::JumpToDamage(_) = instr {
@@ -1458,6 +1981,46 @@ impl RawShape {
         self.pe.code.len()
     }
 
+    // Every section with its offset, kind, and the fields we know how to decode.
+    // Sections we do not understand yet carry their raw bytes as hex.
+    pub fn to_json(&self) -> Fallible<String> {
+        let mut instrs = Vec::with_capacity(self.instrs.len());
+        for instr in &self.instrs {
+            let start = instr.at_offset();
+            let end = cmp::min(start + instr.size(), self.pe.code.len());
+            let mut value = json!({
+                "offset": start,
+                "size": instr.size(),
+                "magic": instr.magic(),
+                "kind": instr.kind(),
+            });
+            let fields = match instr {
+                Instr::TextureRef(tex) => json!({ "filename": tex.filename }),
+                Instr::VertexBuf(buf) => json!({
+                    "target_offset": buf.buffer_target_offset(),
+                    "vertices": buf.verts,
+                }),
+                Instr::Facet(facet) => json!({
+                    "flags": facet.flags.to_u16(),
+                    "color": facet.color,
+                    "material": bs2s(&facet.raw_material),
+                    "indices": facet.indices,
+                    "tex_coords": facet.tex_coords,
+                }),
+                Instr::SourceRef(source) => json!({ "source": source.source }),
+                Instr::X86Trampoline(tramp) => json!({ "name": tramp.name }),
+                _ => json!({ "data": bs2s(&self.pe.code[start..end]) }),
+            };
+            if let (Some(obj), Some(fields)) = (value.as_object_mut(), fields.as_object()) {
+                for (k, v) in fields {
+                    obj.insert(k.to_owned(), v.to_owned());
+                }
+            }
+            instrs.push(value);
+        }
+        Ok(serde_json::to_string_pretty(&json!({ "instrs": instrs }))?)
+    }
+
     pub fn length(&self) -> usize {
         self.instrs.len()
     }
@@ -1478,6 +2041,7 @@ mod tests {
     use super::*;
     use lib::CatalogBuilder;
     use simplelog::{Config, LevelFilter, TermLogger};
+    use std::collections::HashMap;
 
     fn offset_of_trailer(shape: &RawShape) -> Option<usize> {
         let mut offset = None;
@@ -1491,10 +2055,8 @@ mod tests {
     }
 
     fn find_f2_target(shape: &RawShape) -> Option<usize> {
-        for instr in shape.instrs.iter().rev() {
-            if let Instr::PtrToObjEnd(f2) = instr {
-                return Some(f2.end_byte_offset());
-            }
+        if let Some(Instr::PtrToObjEnd(f2)) = find_first_instr(0xF2, &shape.instrs) {
+            return Some(f2.end_byte_offset());
         }
         None
     }
@@ -1548,11 +2110,16 @@ mod tests {
             let data = catalog.read_sync(fid)?;
             let shape = RawShape::from_bytes(&data)?;
 
-            // Ensure that f2 points to the trailer if it exists.
-            // And conversely that we found the trailer in the right place.
-            if let Some(offset) = offset_of_trailer(&shape) {
-                if let Some(f2_target) = find_f2_target(&shape) {
-                    assert_eq!(offset, f2_target);
+            // Ensure that f2 points to the end of the object, which is a trailer only
+            // if there was not enough room left for a full EndOfObject.
+            if let Some(f2_target) = find_f2_target(&shape) {
+                match &shape.instrs[shape.bytes_to_index(f2_target)?] {
+                    Instr::EndOfObject(end) => assert_eq!(end.at_offset(), f2_target),
+                    Instr::TrailerUnknown(trailer) => {
+                        assert_eq!(trailer.offset, f2_target);
+                        assert!(trailer.data.len() < EndOfObject::SIZE);
+                    }
+                    other => panic!("f2 target is not the end of object: {}", other.show()),
                 }
             }
 
@@ -1593,11 +2160,12 @@ mod tests {
                 }
             }
 
-            // Ensure that all offsets and sizes line up.
+            // Ensure that all offsets and sizes line up and that nothing runs off the end.
             let mut expect_offset = 0;
             for instr in &shape.instrs {
                 assert_eq!(expect_offset, instr.at_offset());
                 expect_offset += instr.size();
+                assert!(instr.at_offset() + instr.size() <= shape.byte_length());
             }
         }
 
@@ -1605,4 +2173,535 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn it_parses_unknown_sections_with_expected_sizes() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["*:*.SH".to_owned()])?;
+        let (mut e4_count, mut ea_count) = (0, 0);
+        for &fid in &inputs {
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            for instr in &shape.instrs {
+                match instr {
+                    Instr::UnkE4(e4) => {
+                        e4_count += 1;
+                        assert_eq!(e4.size(), UnkE4::SIZE);
+                        assert_eq!(e4.data.len(), UnkE4::SIZE - 2);
+                    }
+                    Instr::UnkEA(ea) => {
+                        ea_count += 1;
+                        assert_eq!(ea.size(), UnkEA::SIZE);
+                        assert_eq!(ea.data.len(), UnkEA::SIZE - 2);
+                    }
+                    Instr::UnknownData(unk) => {
+                        assert_eq!(unk.size(), unk.data.len());
+                        assert!(unk.at_offset() + unk.size() <= shape.pe.code.len());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        assert!(e4_count > 0);
+        assert!(ea_count > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn it_does_not_over_read_e4_at_eof() -> Fallible<()> {
+        let mut code = vec![0u8; UnkE4::SIZE];
+        code[0] = UnkE4::MAGIC;
+        for (i, b) in code.iter_mut().enumerate().skip(2) {
+            *b = i as u8;
+        }
+        let e4 = UnkE4::from_bytes_after(0x40, &code)?;
+        assert_eq!(e4.offset, 0x40);
+        assert_eq!(e4.data, &code[2..]);
+
+        // Truncated by the end of the code section.
+        for len in 0..UnkE4::SIZE {
+            assert!(UnkE4::from_bytes_after(0x40, &code[..len]).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_closes_on_trailer_for_truncated_owned_instrs() -> Fallible<()> {
+        for &(magic, size) in &[(UnkE4::MAGIC, UnkE4::SIZE), (UnkEA::MAGIC, UnkEA::SIZE)] {
+            let mut code = vec![0u8; size - 1];
+            code[0] = magic;
+            let pe = peff::PE {
+                thunks: Vec::new(),
+                relocs: Vec::new(),
+                code: code.clone(),
+                section_info: HashMap::new(),
+                image_base: 0,
+                code_vaddr: 0,
+                code_addr: 0,
+            };
+            let instrs = RawShape::read_sections(&pe, &[], &[])?;
+            assert_eq!(instrs.len(), 1);
+            if let Instr::TrailerUnknown(trailer) = &instrs[0] {
+                assert_eq!(trailer.offset, 0);
+                assert_eq!(trailer.data, code);
+            } else {
+                panic!("expected a trailer for {:02X}", magic);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_compute_bounding_boxes() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["*:T80.SH".to_owned()])?;
+        for &fid in &inputs {
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            let [lo, hi] = shape.bounding_box().expect("vertices");
+            for i in 0..3 {
+                assert!(lo[i] < hi[i]);
+            }
+
+            // Check each buffer where its xform puts it.
+            let mut targets = HashMap::new();
+            let mut checked = 0;
+            for instr in &shape.instrs {
+                match instr {
+                    Instr::XformUnmask(x) => {
+                        targets.insert(x.target_byte_offset(), [x.t0, x.t1, x.t2]);
+                    }
+                    Instr::XformUnmask4(x) => {
+                        targets.insert(x.target_byte_offset(), [x.t0, x.t1, x.t2]);
+                    }
+                    Instr::VertexBuf(buf) => {
+                        let t = targets.get(&buf.at_offset()).cloned().unwrap_or([0; 3]);
+                        for v in buf.vertices() {
+                            for i in 0..3 {
+                                let p = v[i].saturating_add(t[i]);
+                                assert!(lo[i] <= p && p <= hi[i]);
+                            }
+                            checked += 1;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            assert!(checked > 0);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_show_instructions() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["*:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            let mut saw_vertex_buf = false;
+            for instr in &shape.instrs {
+                assert!(!instr.show().is_empty());
+                if let Instr::VertexBuf(buf) = instr {
+                    saw_vertex_buf = true;
+                    let expect = format!("{}verts", buf.verts.len());
+                    assert!(instr.show().contains(&expect));
+                }
+            }
+            assert!(saw_vertex_buf);
+        }
+        Ok(())
+    }
+
+    // One texture, four vertices, and two triangles.
+    fn make_quad_code() -> Vec<u8> {
+        let mut code = vec![TextureRef::MAGIC, 0x00];
+        code.extend_from_slice(b"QUAD.PIC\0\0\0\0\0\0");
+        code.extend_from_slice(&[VertexBuf::MAGIC, 0x00, 0x04, 0x00, 0x00, 0x00]);
+        for &[x, z] in &[[0u8, 0u8], [0x10, 0], [0x10, 0x10], [0, 0x10]] {
+            code.extend_from_slice(&[x, 0, 0, 0, z, 0]);
+        }
+        code.extend_from_slice(&[Facet::MAGIC, 0x00, 0x00, 0x9F, 0x00, 0x03, 0x00, 0x01, 0x02]);
+        code.extend_from_slice(&[Facet::MAGIC, 0x00, 0x00, 0x9F, 0x00, 0x03, 0x00, 0x02, 0x03]);
+        code
+    }
+
+    fn shape_from_code(code: Vec<u8>) -> Fallible<RawShape> {
+        let pe = peff::PE {
+            thunks: Vec::new(),
+            relocs: Vec::new(),
+            code,
+            section_info: HashMap::new(),
+            image_base: 0,
+            code_vaddr: 0,
+            code_addr: 0,
+        };
+        Ok(RawShape {
+            instrs: RawShape::read_sections(&pe, &[], &[])?,
+            trampolines: Vec::new(),
+            offset_table: Vec::new(),
+            pe,
+        })
+    }
+
+    #[test]
+    fn it_classifies_parse_coverage() -> Fallible<()> {
+        // Every section of the quad decodes.
+        let quad = shape_from_code(make_quad_code())?;
+        assert_eq!(quad.coverage(), ParseCoverage::Full);
+
+        // The same quad with a cut-off E4 behind it keeps those bytes opaque.
+        let mut code = make_quad_code();
+        code.extend_from_slice(&[UnkE4::MAGIC, 0x00, 0x00]);
+        let quad = shape_from_code(code)?;
+        assert!(quad.instrs.last().unwrap().is_unknown());
+        assert_eq!(quad.coverage(), ParseCoverage::Partial);
+
+        // Bytes that are not a PE at all do not load.
+        assert_eq!(ParseCoverage::classify(b"QUAD.SH"), ParseCoverage::Failed);
+
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["*:FUEL.SH".to_owned()])?;
+        for &fid in &inputs {
+            let data = catalog.read_sync(fid)?;
+            let truncated = &data[..data.len() / 4];
+            assert_eq!(ParseCoverage::classify(truncated), ParseCoverage::Failed);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_flags_reversed_facets() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["*:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let mut shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            let flagged = shape.check_winding();
+            let orientations = shape.facet_orientations();
+            assert!(flagged.len() * 2 < orientations.len());
+
+            let (target, _) = *orientations
+                .iter()
+                .find(|(index, _)| !flagged.contains(index))
+                .expect("a correctly wound facet");
+            if let Instr::Facet(ref mut facet) = shape.instrs[target] {
+                facet.indices.reverse();
+            }
+            assert!(shape.check_winding().contains(&target));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_stores_contiguous_instr_offsets() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["*:FUEL.SH".to_owned()])?;
+        for &fid in &inputs {
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            let mut offset = 0;
+            for instr in &shape.instrs {
+                assert_eq!(instr.at_offset(), offset);
+                offset += instr.size();
+            }
+            assert_eq!(offset, shape.byte_length());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_export_json() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["*:FUEL.SH".to_owned()])?;
+        for &fid in &inputs {
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            let facet_count = shape
+                .instrs
+                .iter()
+                .filter(|i| i.unwrap_facet().is_ok())
+                .count();
+            assert!(facet_count > 0);
+
+            let value: serde_json::Value = serde_json::from_str(&shape.to_json()?)?;
+            let instrs = value["instrs"].as_array().expect("instrs");
+            assert_eq!(instrs.len(), shape.instrs.len());
+            let facets = instrs
+                .iter()
+                .filter(|i| i["kind"] == "Facet")
+                .collect::<Vec<_>>();
+            assert_eq!(facets.len(), facet_count);
+            for facet in facets {
+                assert!(facet["indices"].as_array().is_some());
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_compute_stats() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["*:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            let stats = shape.stats();
+            assert!(stats.has_code);
+            assert!(stats.vertex_count > 0);
+            assert!(stats.facet_count > 0);
+            assert!(stats.texture_count >= 1);
+            assert!(stats.detail_levels >= 1);
+            assert!(stats.frame_count >= 1);
+        }
+
+        // A hand-assembled quad: one texture, four vertices, and two triangles.
+        let shape = shape_from_code(make_quad_code())?;
+        assert_eq!(shape.instrs.len(), 4);
+        assert_eq!(
+            shape.stats(),
+            ShapeStats {
+                vertex_count: 4,
+                facet_count: 2,
+                texture_count: 1,
+                has_code: false,
+                detail_levels: 1,
+                frame_count: 1,
+            }
+        );
+
+        // A shape with nothing in it.
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["*:FUEL.SH".to_owned()])?;
+        let mut shape = RawShape::from_bytes(&catalog.read_sync(inputs[0])?)?;
+        shape.instrs.clear();
+        assert_eq!(
+            shape.stats(),
+            ShapeStats {
+                vertex_count: 0,
+                facet_count: 0,
+                texture_count: 0,
+                has_code: false,
+                detail_levels: 1,
+                frame_count: 1,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_find_referenced_ports() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["*:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            let ports = shape.referenced_ports();
+            assert!(ports.contains(&"_PLgearDown".to_owned()));
+            assert!(ports.contains(&"_PLafterBurner".to_owned()));
+        }
+
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["*:*.SH".to_owned()])?;
+        for &fid in &inputs {
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            if find_first_instr(X86Code::MAGIC, &shape.instrs).is_none() {
+                assert!(shape.referenced_ports().is_empty());
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_resolves_relocation_thunks() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["*:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            let relocs = shape.relocations()?;
+            assert_eq!(relocs.len(), shape.pe.relocs.len());
+            assert!(relocs
+                .iter()
+                .any(|r| r.thunk_name == Some("_PLgearDown".to_owned())));
+            for reloc in &relocs {
+                if let Some(ref name) = reloc.thunk_name {
+                    assert!(shape.pe.thunks.iter().any(|t| &t.name == name));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_ends_objects_explicitly() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["*:*.SH".to_owned()])?;
+        let mut explicit_ends = 0;
+        for &fid in &inputs {
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            let f2_target = match find_f2_target(&shape) {
+                Some(f2_target) => f2_target,
+                None => continue,
+            };
+            let index = shape.bytes_to_index(f2_target)?;
+            if let Instr::EndOfObject(end) = &shape.instrs[index] {
+                assert_eq!(end.at_offset(), f2_target);
+                explicit_ends += 1;
+
+                // Decoding stops at the end of the object.
+                for instr in &shape.instrs[index + 1..] {
+                    match instr {
+                        Instr::TrailerUnknown(_)
+                        | Instr::EndOfShape(_)
+                        | Instr::X86Trampoline(_) => {}
+                        other => panic!("decoded past the end of object: {}", other.show()),
+                    }
+                }
+            }
+        }
+        assert!(explicit_ends > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn it_keeps_object_and_lod_jumps_in_bounds() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["*:*.SH".to_owned()])?;
+        let mut seen = (0, 0);
+        for &fid in &inputs {
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            for instr in &shape.instrs {
+                match instr {
+                    Instr::PtrToObjEnd(end) => {
+                        assert!(end.end_byte_offset() > end.at_offset());
+                        assert!(end.end_byte_offset() <= shape.byte_length());
+                        seen.0 += 1;
+                    }
+                    Instr::JumpToLOD(lod) => {
+                        assert!(lod.target_byte_offset() < shape.byte_length());
+                        seen.1 += 1;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        assert!(seen.0 > 0 && seen.1 > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_scale_vertices() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["*:FUEL.SH".to_owned()])?;
+        let mut checked = 0;
+        for &fid in &inputs {
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            for instr in &shape.instrs {
+                if let Instr::VertexBuf(buf) = instr {
+                    checked += 1;
+                    let raw = buf.verts_scaled(1f32);
+                    let feet = buf.verts_scaled(SHAPE_UNIT_TO_FEET);
+                    assert_eq!(raw.len(), buf.verts.len());
+                    for ((v, r), f) in buf.verts.iter().zip(&raw).zip(&feet) {
+                        for i in 0..3 {
+                            assert_eq!(r[i], f32::from(v[i]));
+                            assert_eq!(f[i], f32::from(v[i]) * SHAPE_UNIT_TO_FEET);
+                        }
+                    }
+                }
+            }
+        }
+        assert!(checked > 0, "expected FUEL.SH to have vertex buffers");
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_inflated_vertex_counts() {
+        // Claims 255 vertices, but only carries two.
+        let mut data = vec![VertexBuf::MAGIC, 0x00, 0xFF, 0x00, 0x00, 0x00];
+        data.extend_from_slice(&[0u8; 12]);
+        let err = VertexBuf::from_bytes_after(0x40, &data).unwrap_err();
+        match err.downcast_ref::<ShError>() {
+            Some(ShError::Truncated {
+                offset,
+                needed,
+                available,
+                ..
+            }) => {
+                assert_eq!(*offset, 0x40);
+                assert_eq!(*needed, 6 + 255 * 6);
+                assert_eq!(*available, data.len());
+            }
+            _ => panic!("expected a truncation error, got: {}", err),
+        }
+
+        data[2] = 2;
+        let buf = VertexBuf::from_bytes_after(0x40, &data).unwrap();
+        assert_eq!(buf.verts.len(), 2);
+        assert_eq!(buf.size(), data.len());
+    }
+
+    #[test]
+    fn it_finds_unmask_targets() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["*:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            let mut unmask_count = 0;
+            for instr in &shape.instrs {
+                if let Ok(target) = instr.unwrap_unmask_target() {
+                    assert!(target > instr.at_offset());
+                    assert!(target < shape.byte_length());
+                    let index = shape.bytes_to_index(target)?;
+                    assert_eq!(shape.instrs[index].at_offset(), target);
+                    unmask_count += 1;
+                }
+            }
+            assert!(unmask_count > 0);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_relocations_past_the_code() -> Fallible<()> {
+        let mut shape = shape_from_code(make_quad_code())?;
+        let last = shape.pe.code.len() as u32 - 4;
+        shape.pe.relocs = vec![last];
+        let relocs = shape.relocations()?;
+        assert_eq!(relocs.len(), 1);
+        assert_eq!(relocs[0].offset, last);
+        assert_eq!(relocs[0].target, 0x0302_0003);
+        assert_eq!(relocs[0].thunk_name, None);
+
+        shape.pe.relocs.push(last + 1);
+        assert!(shape.relocations().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_map_offsets_to_indices() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["*:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            for (i, instr) in shape.instrs.iter().enumerate() {
+                assert_eq!(shape.bytes_to_index(instr.at_offset())?, i);
+                if instr.size() > 1 {
+                    assert!(shape.bytes_to_index(instr.at_offset() + 1).is_err());
+                }
+            }
+            assert!(shape.bytes_to_index(shape.byte_length() + 1).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_disassemble_embedded_code() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["*:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            let listing = shape.disassemble();
+            assert!(listing.contains("[do_start_interp]"));
+            assert!(listing.contains("[_PLgearDown]"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_diff_shapes() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["*:FUEL.SH".to_owned()])?;
+        for &fid in &inputs {
+            let data = catalog.read_sync(fid)?;
+            let shape = RawShape::from_bytes(&data)?;
+            assert!(shape.diff(&shape).is_empty());
+
+            let mut mutated = RawShape::from_bytes(&data)?;
+            let removed = mutated.instrs.remove(0);
+            let diffs = shape.diff(&mutated);
+            assert_eq!(diffs.len(), 1);
+            assert_eq!(
+                diffs[0],
+                SectionDiff::Removed {
+                    left_offset: removed.at_offset(),
+                    magic: removed.magic(),
+                }
+            );
+        }
+        Ok(())
+    }
 }
@@ -133,6 +133,10 @@ lazy_static! {
     };
 }
 
+// The true vertical scale of the height byte is not known. This matches the 3 hectometers per unit
+// that the terrain renderer uses, so that anything placed with it lines up with the drawn terrain.
+pub const FEET_PER_HEIGHT_UNIT: f32 = 300f32 / 0.304_8;
+
 #[derive(Copy, Clone)]
 pub struct Sample {
     pub color: u8,
@@ -188,6 +192,21 @@ impl Sample {
     }
 }
 
+// What the ground is made of at a point, from the color of the nearest sample.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TerrainType {
+    Water,
+    Land,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TerrainSample {
+    pub height: f32,
+    pub terrain_type: TerrainType,
+    // Unit up vector of the surface.
+    pub normal: [f32; 3],
+}
+
 pub struct Terrain {
     name: String,
     _pic_file: String,
@@ -708,6 +727,114 @@ impl Terrain {
     pub fn height(&self) -> u32 {
         self.height
     }
+
+    // Map a position in feet, as used by MM object placement, to the nearest sample. Samples are
+    // stored south to north, so z grows northward from the bottom edge of the map.
+    pub fn sample_at(&self, x_ft: f32, z_ft: f32) -> Option<&Sample> {
+        if x_ft < 0f32
+            || z_ft < 0f32
+            || x_ft > self.width_ft
+            || z_ft > self.height_ft
+            || self.width == 0
+            || self.height == 0
+        {
+            return None;
+        }
+        let xi = ((x_ft / self.width_ft * self.width as f32) as u32).min(self.width - 1);
+        let zi = ((z_ft / self.height_ft * self.height as f32) as u32).min(self.height - 1);
+        self.samples.get((zi * self.width + xi) as usize)
+    }
+
+    // Ground height in feet at the given position, or 0 off the edge of the map. This is
+    // interpolated between the four surrounding samples so that objects sit on the surface that
+    // we draw rather than stepping between samples.
+    pub fn height_at(&self, x_ft: f32, z_ft: f32) -> f32 {
+        if self.sample_at(x_ft, z_ft).is_none() {
+            return 0f32;
+        }
+        let fx = x_ft / self.width_ft * self.width as f32;
+        let fz = z_ft / self.height_ft * self.height as f32;
+        let x0 = (fx as u32).min(self.width - 1);
+        let z0 = (fz as u32).min(self.height - 1);
+        let x1 = (x0 + 1).min(self.width - 1);
+        let z1 = (z0 + 1).min(self.height - 1);
+        let tx = (fx - x0 as f32).min(1f32);
+        let tz = (fz - z0 as f32).min(1f32);
+        let h = |xi: u32, zi: u32| {
+            f32::from(self.samples[(zi * self.width + xi) as usize].height) * FEET_PER_HEIGHT_UNIT
+        };
+        let south = h(x0, z0) * (1f32 - tx) + h(x1, z0) * tx;
+        let north = h(x0, z1) * (1f32 - tx) + h(x1, z1) * tx;
+        south * (1f32 - tz) + north * tz
+    }
+
+    // Off the edge of the map is treated as open ocean.
+    pub fn terrain_type_at(&self, x_ft: f32, z_ft: f32) -> TerrainType {
+        match self.sample_at(x_ft, z_ft) {
+            Some(sample) if sample.color != 0xFF => TerrainType::Land,
+            _ => TerrainType::Water,
+        }
+    }
+
+    // The surface normal, from the height one sample away on either side.
+    pub fn normal_at(&self, x_ft: f32, z_ft: f32) -> [f32; 3] {
+        let (dx, dz) = self.sample_spacing_in_ft();
+        let x0 = (x_ft - dx).max(0f32);
+        let x1 = (x_ft + dx).min(self.width_ft);
+        let z0 = (z_ft - dz).max(0f32);
+        let z1 = (z_ft + dz).min(self.height_ft);
+        if x1 <= x0 || z1 <= z0 {
+            return [0f32, 1f32, 0f32];
+        }
+        let slope_x = (self.height_at(x1, z_ft) - self.height_at(x0, z_ft)) / (x1 - x0);
+        let slope_z = (self.height_at(x_ft, z1) - self.height_at(x_ft, z0)) / (z1 - z0);
+        let len = (slope_x * slope_x + 1f32 + slope_z * slope_z).sqrt();
+        [-slope_x / len, 1f32 / len, -slope_z / len]
+    }
+
+    pub fn sample(&self, x_ft: f32, z_ft: f32) -> TerrainSample {
+        TerrainSample {
+            height: self.height_at(x_ft, z_ft),
+            terrain_type: self.terrain_type_at(x_ft, z_ft),
+            normal: self.normal_at(x_ft, z_ft),
+        }
+    }
+
+    // The lowest and highest raw height values in the map.
+    pub fn height_range(&self) -> (u8, u8) {
+        let lo = self.samples.iter().map(|s| s.height).min().unwrap_or(0);
+        let hi = self.samples.iter().map(|s| s.height).max().unwrap_or(0);
+        (lo, hi)
+    }
+
+    // Stretch the height range of the map out over the full 16 bit range, with north at the top.
+    // Use height_range to recover the real heights from the gray values.
+    pub fn to_heightmap_image(&self) -> image::ImageBuffer<image::Luma<u16>, Vec<u16>> {
+        let (lo, hi) = self.height_range();
+        let span = u32::from(hi - lo).max(1);
+        let mut buf = image::ImageBuffer::new(self.width, self.height);
+        for (pos, sample) in self.samples.iter().enumerate() {
+            let w = (pos % self.width as usize) as u32;
+            let h = (self.height as usize - (pos / self.width as usize) - 1) as u32;
+            let gray = u32::from(sample.height - lo) * u32::from(u16::max_value()) / span;
+            buf.put_pixel(
+                w,
+                h,
+                image::Luma {
+                    data: [gray as u16],
+                },
+            );
+        }
+        buf
+    }
+
+    // The distance between neighboring samples, in feet, east-west and north-south.
+    pub fn sample_spacing_in_ft(&self) -> (f32, f32) {
+        (
+            self.width_ft / self.width as f32,
+            self.height_ft / self.height as f32,
+        )
+    }
 }
 
 #[cfg(test)]
@@ -742,4 +869,100 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn it_can_look_up_heights() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:BAL.T2".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let terrain = Terrain::from_bytes(&catalog.read_sync(fid)?)?;
+            let (dx, dz) = terrain.sample_spacing_in_ft();
+            for zi in (0..terrain.height()).step_by(17) {
+                for xi in (0..terrain.width()).step_by(13) {
+                    let sample = terrain.samples[(zi * terrain.width() + xi) as usize];
+                    let expect = f32::from(sample.height) * FEET_PER_HEIGHT_UNIT;
+                    let actual = terrain.height_at(xi as f32 * dx, zi as f32 * dz);
+                    assert!((actual - expect).abs() < 0.01 * FEET_PER_HEIGHT_UNIT);
+                }
+            }
+            assert_eq!(terrain.height_at(-1f32, 0f32), 0f32);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_interpolates_between_samples() {
+        // Two samples on a side, 100ft apart.
+        let terrain = Terrain {
+            name: "TEST.T2".to_owned(),
+            _pic_file: "TEST.PIC".to_owned(),
+            width: 2,
+            height: 2,
+            width_ft: 200f32,
+            height_ft: 200f32,
+            origin_latitude: 0f32,
+            origin_longitude: 0f32,
+            samples: [0, 10, 20, 30]
+                .iter()
+                .map(|&height| Sample {
+                    height,
+                    ..Default::default()
+                })
+                .collect(),
+        };
+        let feet = |units: f32| units * FEET_PER_HEIGHT_UNIT;
+        assert_eq!(terrain.height_at(0f32, 0f32), feet(0f32));
+        assert_eq!(terrain.height_at(100f32, 100f32), feet(30f32));
+        // Midway between all four: (0 + 10) / 2 to the south, (20 + 30) / 2 to the north.
+        assert!((terrain.height_at(50f32, 50f32) - feet(15f32)).abs() < 0.01);
+        // South: 0 * 0.75 + 10 * 0.25 = 2.5, north: 20 * 0.75 + 30 * 0.25 = 22.5,
+        // 2.5 * 0.25 + 22.5 * 0.75 = 17.5
+        assert!((terrain.height_at(25f32, 75f32) - feet(17.5f32)).abs() < 0.01);
+        assert_eq!(terrain.height_at(-1f32, 50f32), 0f32);
+    }
+
+    #[test]
+    fn it_can_find_water() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:BAL.T2".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let terrain = Terrain::from_bytes(&catalog.read_sync(fid)?)?;
+            let (dx, dz) = terrain.sample_spacing_in_ft();
+            let water = terrain
+                .samples
+                .iter()
+                .position(|s| s.color == 0xFF)
+                .expect("water in the baltics");
+            let xi = water as u32 % terrain.width();
+            let zi = water as u32 / terrain.width();
+            let x = (xi as f32 + 0.5) * dx;
+            let z = (zi as f32 + 0.5) * dz;
+            assert_eq!(terrain.terrain_type_at(x, z), TerrainType::Water);
+            let sample = terrain.sample(x, z);
+            assert_eq!(sample.terrain_type, TerrainType::Water);
+            assert!(sample.normal[1] > 0f32);
+            assert_eq!(terrain.terrain_type_at(-1f32, -1f32), TerrainType::Water);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_make_a_heightmap() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:BAL.T2".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let terrain = Terrain::from_bytes(&catalog.read_sync(fid)?)?;
+            let img = terrain.to_heightmap_image();
+            assert_eq!(img.dimensions(), (terrain.width(), terrain.height()));
+            let (lo, hi) = terrain.height_range();
+            if hi > lo {
+                assert!(img.pixels().any(|p| p.data[0] == 0));
+                assert!(img.pixels().any(|p| p.data[0] == u16::max_value()));
+            }
+        }
+        Ok(())
+    }
 }
@@ -15,13 +15,14 @@
 pub use jt::ProjectileType;
 pub use nt::{HardpointType, NpcType};
 pub use ot::parse;
-pub use ot::ObjectType;
+pub use ot::{ObjectKind, ObjectType};
 pub use pt::{Envelope, PlaneType};
 
 use catalog::Catalog;
 use failure::{bail, Fallible};
 use lib::from_dos_string;
 use log::trace;
+use sh::RawShape;
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 // A generic type.
@@ -158,6 +159,26 @@ impl TypeManager {
         }
         panic!("unreachable")
     }
+
+    // Look up a type that has already been loaded.
+    pub fn get(&self, name: &str) -> Fallible<TypeRef> {
+        if let Some(item) = self.cache.borrow().get(name) {
+            return Ok(item.clone());
+        }
+        bail!("type {} has not been loaded", name)
+    }
+
+    // Load the type and the shape it is drawn with. Returns the shape's file name
+    // along with the parsed shape.
+    pub fn shape_for(&self, name: &str, catalog: &Catalog) -> Fallible<(String, RawShape)> {
+        let xt = self.load(name, catalog)?;
+        let shape_name = match xt.ot().shape {
+            Some(ref shape_name) => shape_name.to_owned(),
+            None => bail!("type {} does not reference a shape", name),
+        };
+        let shape = RawShape::from_bytes(&catalog.read_name_sync(&shape_name)?)?;
+        Ok((shape_name, shape))
+    }
 }
 
 #[cfg(test)]
@@ -192,4 +213,58 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn can_get_object_type_metadata() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:*.OT".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            let meta = catalog.stat_sync(fid)?;
+            catalog.set_default_label(&label);
+            let types = TypeManager::empty();
+            assert!(types.get(&meta.name).is_err());
+            types.load(&meta.name, &catalog)?;
+
+            let ty = types.get(&meta.name)?;
+            let ot = ty.ot();
+            assert_eq!(ot.file_name(), meta.name);
+            if let Some(shape_name) = ot.shape_name() {
+                assert!(shape_name.ends_with(".SH"));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn can_get_metadata_for_a_known_type() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:F18.PT".to_owned()])?;
+        assert!(!inputs.is_empty());
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let types = TypeManager::empty();
+            types.load("F18.PT", &catalog)?;
+
+            let ty = types.get("F18.PT")?;
+            let ot = ty.ot();
+            assert!(ot.long_name().contains("Hornet"), "{}", ot.long_name());
+            assert_eq!(ot.category(), &ObjectKind::Fighter);
+            assert_eq!(ot.shape_name(), Some("F18.SH"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn can_load_the_shape_for_a_type() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:F18.PT".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let types = TypeManager::empty();
+            let (shape_name, shape) = types.shape_for("F18.PT", &catalog)?;
+            assert!(shape_name.ends_with(".SH"));
+            assert!(!shape.instrs.is_empty());
+        }
+        Ok(())
+    }
 }